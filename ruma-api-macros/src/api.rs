@@ -7,15 +7,25 @@ use quote::{quote, ToTokens};
 use syn::{
     braced,
     parse::{Parse, ParseStream},
-    Field, FieldValue, Ident, Token,
+    parse_quote, Attribute, Field, FieldValue, GenericArgument, Ident, Index, Item, Lit, Member,
+    NestedMeta, Path, PathArguments, Token, Type, TypePath, Visibility,
 };
 
 mod attribute;
+mod compat;
+mod examples;
 mod metadata;
 mod request;
 mod response;
 
-use self::{metadata::Metadata, request::Request, response::Response};
+use self::{
+    attribute::Meta,
+    compat::Compat,
+    examples::Examples,
+    metadata::Metadata,
+    request::{PathEncoding, Request},
+    response::Response,
+};
 
 /// Removes `serde` attributes from struct fields.
 pub fn strip_serde_attrs(field: &Field) -> Field {
@@ -24,51 +34,254 @@ pub fn strip_serde_attrs(field: &Field) -> Field {
     field
 }
 
+/// Adds the `serde` attributes needed to distinguish an absent field from an explicit `null` to
+/// body fields of type `Option<Option<T>>`.
+///
+/// Fields that already have `serde` attributes are left alone, so users can still provide their
+/// own (de)serialization logic.
+pub fn add_double_option_serde_attrs(field: &mut Field) {
+    let has_serde_attrs = field.attrs.iter().any(|attr| attr.path.is_ident("serde"));
+
+    if !has_serde_attrs && option_inner_type(&field.ty).and_then(option_inner_type).is_some() {
+        field.attrs.push(parse_quote! {
+            #[serde(
+                default,
+                skip_serializing_if = "Option::is_none",
+                with = "ruma_api::serde::double_option"
+            )]
+        });
+    }
+}
+
+/// Replaces a `#[ruma_api(body, flatten)]` attribute with `#[serde(flatten)]`, making the field a
+/// regular body field whose members are flattened into the body object.
+///
+/// Likewise, `#[ruma_api(query, flatten)]` is replaced with `#[ruma_api(query)]` and
+/// `#[serde(flatten)]`, flattening the field's members into the query string.
+pub fn apply_flatten_attr(field: &mut Field) -> syn::Result<()> {
+    let mut query_attr = None;
+
+    for attr in &mut field.attrs {
+        let metas = match Meta::list_from_attribute(attr)? {
+            Some(metas) => metas,
+            None => continue,
+        };
+
+        let is_word = |meta: &Meta, word: &str| match meta {
+            Meta::Word(ident) => ident == word,
+            Meta::NameValue(_) => false,
+        };
+
+        if !metas.iter().any(|meta| is_word(meta, "flatten")) {
+            continue;
+        }
+
+        match &metas[..] {
+            [body, flatten] if is_word(body, "body") && is_word(flatten, "flatten") => {
+                *attr = parse_quote!(#[serde(flatten)]);
+            }
+            [query, flatten] if is_word(query, "query") && is_word(flatten, "flatten") => {
+                query_attr = Some(parse_quote!(#[ruma_api(query)]));
+                *attr = parse_quote!(#[serde(flatten)]);
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "`flatten` can only be used as `#[ruma_api(body, flatten)]` or \
+                     `#[ruma_api(query, flatten)]`",
+                ));
+            }
+        }
+    }
+
+    field.attrs.extend(query_attr);
+
+    Ok(())
+}
+
+/// Returns the type argument if the given type is syntactically an `Option<T>`.
+fn option_inner_type(ty: &Type) -> Option<&Type> {
+    single_type_argument(ty, "Option")
+}
+
+/// Returns the type argument if the given type is syntactically a `Vec<T>`.
+fn vec_inner_type(ty: &Type) -> Option<&Type> {
+    single_type_argument(ty, "Vec")
+}
+
+/// Returns the type argument if the given type is syntactically the generic type `name` with a
+/// single type argument.
+fn single_type_argument<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let segment = match ty {
+        Type::Path(TypePath { qself: None, path }) => path.segments.last()?,
+        _ => return None,
+    };
+
+    if segment.ident != name {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the value of a `#[serde(rename = "...")]` attribute on the field, if any.
+pub fn serde_rename(field: &Field) -> Option<String> {
+    serde_metas(field).find_map(|meta| match meta {
+        NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+            path,
+            lit: Lit::Str(name),
+            ..
+        })) if path.is_ident("rename") => Some(name.value()),
+        _ => None,
+    })
+}
+
+/// Whether the field has a `#[serde(...)]` attribute containing the given word, e.g. `flatten`.
+pub fn has_serde_word(field: &Field, word: &str) -> bool {
+    serde_metas(field).any(|meta| match meta {
+        NestedMeta::Meta(syn::Meta::Path(path)) => path.is_ident(word),
+        _ => false,
+    })
+}
+
+/// Whether the field can be missing when deserializing, because of a `#[serde(default)]` or
+/// `#[serde(default = "...")]` attribute.
+pub fn has_serde_default(field: &Field) -> bool {
+    serde_metas(field).any(|meta| match meta {
+        NestedMeta::Meta(syn::Meta::Path(path)) => path.is_ident("default"),
+        NestedMeta::Meta(syn::Meta::NameValue(name_value)) => name_value.path.is_ident("default"),
+        _ => false,
+    })
+}
+
+/// Produces an iterator over the items of all `#[serde(...)]` attributes on the field.
+fn serde_metas(field: &Field) -> impl Iterator<Item = NestedMeta> + '_ {
+    field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("serde"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(syn::Meta::List(list)) => Some(list.nested.into_iter()),
+            _ => None,
+        })
+        .flatten()
+}
+
 /// The result of processing the `ruma_api` macro, ready for output back to source code.
 pub struct Api {
+    /// The attributes forwarded to all generated items.
+    attrs: Vec<Attribute>,
     /// The `metadata` section of the macro.
     metadata: Metadata,
     /// The `request` section of the macro.
     request: Request,
     /// The `response` section of the macro.
     response: Response,
+    /// The optional `examples` section of the macro.
+    examples: Examples,
+    /// The optional `compat` section of the macro.
+    compat: Option<Compat>,
 }
 
 impl TryFrom<RawApi> for Api {
     type Error = syn::Error;
 
     fn try_from(raw_api: RawApi) -> syn::Result<Self> {
+        let metadata: Metadata = raw_api.metadata.try_into()?;
+        let mut request: Request = raw_api.request.try_into()?;
+        if metadata.patch.value {
+            request.wrap_body_fields_in_maybe()?;
+        }
+
         let res = Self {
-            metadata: raw_api.metadata.try_into()?,
-            request: raw_api.request.try_into()?,
+            attrs: raw_api.attrs,
+            metadata,
+            request,
             response: raw_api.response.try_into()?,
+            examples: match raw_api.examples {
+                Some(raw_examples) => raw_examples.try_into()?,
+                None => Examples::default(),
+            },
+            compat: raw_api.compat.map(Compat::try_from).transpose()?,
+        };
+
+        let mut combined_error: Option<syn::Error> = None;
+        let mut add_error = |error| {
+            if let Some(combined_error_ref) = &mut combined_error {
+                combined_error_ref.combine(error);
+            } else {
+                combined_error = Some(error);
+            }
         };
 
         let newtype_body_field = res.request.newtype_body_field();
-        if res.metadata.method == "GET"
-            && (res.request.has_body_fields() || newtype_body_field.is_some())
+        if res.metadata.method == "GET" {
+            for field in res.request.body_fields().chain(newtype_body_field) {
+                add_error(syn::Error::new_spanned(field, "GET endpoints can't have body fields"));
+            }
+        }
+
+        let path = res.metadata.path.value();
+        let path_params = path
+            .split('/')
+            .filter(|segment| segment.starts_with(':'))
+            .map(|segment| &segment[1..])
+            .collect::<Vec<_>>();
+        let path_field_names = res
+            .request
+            .path_fields()
+            .filter_map(|field| field.ident.as_ref().map(Ident::to_string))
+            .collect::<Vec<_>>();
+
+        for field in res.request.path_fields() {
+            if field.ident.as_ref().map_or(false, |ident| !path_params.iter().any(|p| ident == p)) {
+                add_error(syn::Error::new_spanned(
+                    field,
+                    "path field has no matching parameter in the endpoint's path",
+                ));
+            }
+        }
+
+        for param in
+            path_params.iter().filter(|param| !path_field_names.iter().any(|n| n == *param))
         {
-            let mut combined_error: Option<syn::Error> = None;
-            let mut add_error = |field| {
-                let error = syn::Error::new_spanned(field, "GET endpoints can't have body fields");
-                if let Some(combined_error_ref) = &mut combined_error {
-                    combined_error_ref.combine(error);
-                } else {
-                    combined_error = Some(error);
-                }
-            };
+            add_error(syn::Error::new_spanned(
+                &res.metadata.path,
+                format!("path parameter `:{}` has no matching `#[ruma_api(path)]` field", param),
+            ));
+        }
 
-            for field in res.request.body_fields() {
-                add_error(field);
+        if let Some(example) = &res.examples.request {
+            let is_struct = res.request.newtype_body_field().is_none();
+            for error in example.check_fields(res.request.body_fields(), is_struct) {
+                add_error(error);
             }
+        }
 
-            if let Some(field) = newtype_body_field {
-                add_error(field);
+        if let Some(example) = &res.examples.response {
+            let is_struct = res.response.newtype_body_field().is_none()
+                && res.response.json_stream_field().is_none()
+                && res.response.multipart_field().is_none();
+            for error in example.check_fields(res.response.body_fields(), is_struct) {
+                add_error(error);
             }
+        }
 
-            Err(combined_error.unwrap())
-        } else {
-            Ok(res)
+        if let Some(compat) = &res.compat {
+            for error in compat.check_request_fields(&res.request) {
+                add_error(error);
+            }
+        }
+
+        match combined_error {
+            Some(error) => Err(error),
+            None => Ok(res),
         }
     }
 }
@@ -84,35 +297,80 @@ impl ToTokens for Api {
         let path = &self.metadata.path;
         let rate_limited = &self.metadata.rate_limited;
         let requires_authentication = &self.metadata.requires_authentication;
+        let access_token_placement = &self.metadata.access_token_placement;
+        let compress_request_body = &self.metadata.compress_request_body;
+        let history_paths = &self.metadata.history_paths;
+        let unstable_feature = match &self.metadata.unstable_feature {
+            Some(feature) => quote! { Some(#feature) },
+            None => quote! { None },
+        };
+
+        let is_safe = matches!(&method.to_string()[..], "GET" | "HEAD" | "OPTIONS" | "TRACE");
+        let is_idempotent = is_safe || method == "PUT" || method == "DELETE";
+
+        let path_segments = path
+            .value()
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                if let Some(parameter) = segment.strip_prefix(':') {
+                    quote!(ruma_api::PathSegment::Parameter(#parameter))
+                } else {
+                    quote!(ruma_api::PathSegment::Literal(#segment))
+                }
+            })
+            .collect::<Vec<_>>();
+        let path_param_names = path
+            .value()
+            .split('/')
+            .filter(|segment| segment.starts_with(':'))
+            .map(|segment| segment[1..].to_owned())
+            .collect::<Vec<_>>();
+
+        // Whether the endpoint can also be used on the server side, i.e. its request can be
+        // converted from an `http::Request` and its response to an `http::Response`.
+        let is_incoming = self.request.is_incoming() && self.response.is_outgoing();
+
+        // Which halves of the conversions to emit, selected through the `client` and `server`
+        // features of `ruma-api`.
+        let client = cfg!(feature = "client");
+        let server = cfg!(feature = "server") && is_incoming;
 
         let request = &self.request;
         let request_types = quote! { #request };
+        let request_hidden_types = request.hidden_types(client, server);
+        let query_params_module = request.query_params_module();
         let response = &self.response;
         let response_types = quote! { #response };
+        let response_hidden_types = response.hidden_types(client, server);
 
         let set_request_path = if self.request.has_path_fields() {
             let path_str = path.value();
 
-            assert!(path_str.starts_with('/'), "path needs to start with '/'");
-            assert!(
-                path_str.chars().filter(|c| *c == ':').count() == self.request.path_field_count(),
-                "number of declared path parameters needs to match amount of placeholders in path"
-            );
-
             let request_path_init_fields = self.request.request_path_init_fields();
 
             let path_segments = path_str[1..].split('/');
             let path_segment_push = path_segments.map(|segment| {
-                let arg = if segment.starts_with(':') {
-                    let path_var = &segment[1..];
+                let arg = if let Some(path_var) = segment.strip_prefix(':') {
                     let path_var_ident = Ident::new(path_var, Span::call_site());
-                    quote!(&request_path.#path_var_ident.to_string())
+                    let value = quote!(&request_path.#path_var_ident.to_string());
+
+                    match self.request.path_field_encoding(path_var) {
+                        PathEncoding::Segment => quote! {
+                            &ruma_api::exports::percent_encode_path_segment(#value)
+                        },
+                        PathEncoding::Component => quote! {
+                            &ruma_api::exports::percent_encode_component(#value)
+                        },
+                        PathEncoding::Verbatim => value,
+                    }
                 } else {
                     quote!(#segment)
                 };
 
                 quote! {
-                    path_segments.push(#arg);
+                    path.push('/');
+                    path.push_str(#arg);
                 }
             });
 
@@ -121,11 +379,9 @@ impl ToTokens for Api {
                     #request_path_init_fields
                 };
 
-                // This `unwrap()` can only fail when the url is a
-                // cannot-be-base url like `mailto:` or `data:`, which is not
-                // the case for our placeholder url.
-                let mut path_segments = url.path_segments_mut().unwrap();
+                let mut path = String::new();
                 #(#path_segment_push)*
+                url.set_path(&path);
             }
         } else {
             quote! {
@@ -155,17 +411,7 @@ impl ToTokens for Api {
                 assert_trait_impl::<#field_type>();
 
                 let request_query = RequestQuery(request.#field_name);
-                let query_str = ruma_api::exports::serde_urlencoded::to_string(
-                    request_query,
-                )?;
-
-                let query_opt: Option<&str> = if query_str.is_empty() {
-                    None
-                } else {
-                    Some(&query_str)
-                };
-
-                url.set_query(query_opt);
+                ruma_api::exports::append_query_string(&mut url, request_query)?;
             }
         } else if self.request.has_query_fields() {
             let request_query_init_fields = self.request.request_query_init_fields();
@@ -175,9 +421,7 @@ impl ToTokens for Api {
                     #request_query_init_fields
                 };
 
-                url.set_query(Some(&ruma_api::exports::serde_urlencoded::to_string(
-                    request_query,
-                )?));
+                ruma_api::exports::append_query_string(&mut url, request_query)?;
             }
         } else {
             TokenStream::new()
@@ -194,14 +438,28 @@ impl ToTokens for Api {
         };
 
         let create_http_request = if let Some(field) = self.request.newtype_body_field() {
-            let field_name = field.ident.as_ref().expect("expected field to have an identifier");
-
-            quote! {
-                let request_body = RequestBody(request.#field_name);
+            // The field is unnamed if the request is a tuple struct.
+            let field_name = match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index::from(0)),
+            };
 
-                let mut http_request = ruma_api::exports::http::Request::new(
-                    ruma_api::exports::serde_json::to_vec(&request_body)?,
-                );
+            match self.request.manual_body() {
+                None if self.request.has_raw_body() => quote! {
+                    let mut http_request =
+                        ruma_api::exports::http::Request::new(request.#field_name);
+                },
+                Some(serialize) => quote! {
+                    let mut http_request = ruma_api::exports::http::Request::new(
+                        #serialize(&request.#field_name)
+                            .map_err(ruma_api::exports::manual_request_body_error)?,
+                    );
+                },
+                None => quote! {
+                    let request_body = RequestBody(request.#field_name);
+
+                    let mut http_request = ruma_api::exports::json_request(&request_body)?;
+                },
             }
         } else if self.request.has_body_fields() {
             let request_body_init_fields = self.request.request_body_init_fields();
@@ -211,9 +469,13 @@ impl ToTokens for Api {
                     #request_body_init_fields
                 };
 
-                let mut http_request = ruma_api::exports::http::Request::new(
-                    ruma_api::exports::serde_json::to_vec(&request_body)?,
-                );
+                let mut http_request = ruma_api::exports::json_request(&request_body)?;
+            }
+        } else if self.metadata.empty_object_body.value {
+            quote! {
+                let mut http_request = ruma_api::exports::json_request(
+                    &ruma_api::exports::serde_json::Map::new(),
+                )?;
             }
         } else {
             quote! {
@@ -221,20 +483,47 @@ impl ToTokens for Api {
             }
         };
 
-        let try_deserialize_response_body = if let Some(field) = self.response.newtype_body_field()
+        let compress_http_request_body = if compress_request_body.value
+            && (self.request.newtype_body_field().is_some() || self.request.has_body_fields())
         {
-            let field_type = &field.ty;
+            quote! {
+                ruma_api::exports::gzip_request_body(&mut http_request)?;
+            }
+        } else {
+            TokenStream::new()
+        };
 
+        let try_deserialize_response_body = if let Some(deserialize) = self.response.manual_body() {
+            quote! {
+                #deserialize(http_response.body().as_ref()).map_err(|error| {
+                    ruma_api::exports::manual_response_body_error(error, http_response.headers())
+                })?
+            }
+        } else if self.response.has_raw_body() {
+            quote! {
+                http_response.into_body().into()
+            }
+        } else if self.response.newtype_body_field().is_some() {
+            quote! {
+                {
+                    ruma_api::exports::report_content_type_anomaly(&http_response, #name);
+                    ruma_api::exports::from_json_response::<ResponseBody, _>(&http_response)?.0
+                }
+            }
+        } else if self.response.multipart_field().is_some() {
+            quote! {
+                ruma_api::Multipart::from_response(&http_response)?
+            }
+        } else if self.response.json_stream_field().is_some() {
             quote! {
-                ruma_api::exports::serde_json::from_slice::<#field_type>(
-                    http_response.into_body().as_slice(),
-                )?
+                ruma_api::JsonStream::new(http_response.into_body().into())
             }
         } else if self.response.has_body_fields() {
             quote! {
-                ruma_api::exports::serde_json::from_slice::<ResponseBody>(
-                    http_response.into_body().as_slice(),
-                )?
+                {
+                    ruma_api::exports::report_content_type_anomaly(&http_response, #name);
+                    ruma_api::exports::from_json_response::<ResponseBody, _>(&http_response)?
+                }
             }
         } else {
             quote! {
@@ -250,99 +539,504 @@ impl ToTokens for Api {
             TokenStream::new()
         };
 
+        let validate_response_fn = self.metadata.validate_response.as_ref().map(|validator| {
+            quote! {
+                if let Err(error) = #validator(&response) {
+                    return Err(ruma_api::exports::validation_error(
+                        None,
+                        error,
+                        &response_headers,
+                    ));
+                }
+            }
+        });
+        let validate_response_fields = self.response.validate_fields();
+        let validate_response = quote! {
+            #validate_response_fields
+            #validate_response_fn
+        };
+
+        let save_response_headers =
+            if self.response.has_validated_fields() || self.metadata.validate_response.is_some() {
+                quote! {
+                    let response_headers = http_response.headers().clone();
+                }
+            } else {
+                TokenStream::new()
+            };
+
         let response_init_fields = if self.response.has_fields() {
             self.response.init_fields()
         } else {
             TokenStream::new()
         };
 
-        let request_doc = format!(
+        let mut request_doc = format!(
             "Data for a request to the `{}` API endpoint.\n\n{}",
             name,
             description.value()
         );
-        let response_doc = format!("Data in the response from the `{}` API endpoint.", name);
+        let mut response_doc = format!("Data in the response from the `{}` API endpoint.", name);
 
-        let api = quote! {
-            use ruma_api::exports::serde::de::{Error as _, IntoDeserializer as _};
-            use ruma_api::exports::serde::Deserialize as _;
-            use ruma_api::Endpoint as _;
+        if let Some(example) = &self.examples.request {
+            request_doc.push_str(&example.doc_section());
+        }
+        if let Some(example) = &self.examples.response {
+            response_doc.push_str(&example.doc_section());
+        }
 
-            use std::convert::TryInto as _;
+        let example_request_body = match &self.examples.request {
+            Some(example) => {
+                let json = &example.json;
+                quote! { Some(#json) }
+            }
+            None => quote! { None },
+        };
+        let example_response_body = match &self.examples.response {
+            Some(example) => {
+                let json = &example.json;
+                quote! { Some(#json) }
+            }
+            None => quote! { None },
+        };
 
-            #[doc = #request_doc]
-            #request_types
+        let check_uiaa_response = if self.metadata.uiaa.value {
+            quote! {
+                ruma_api::exports::check_uiaa_response(&http_response)?;
+            }
+        } else {
+            TokenStream::new()
+        };
+        let check_response = match &self.metadata.error_ty {
+            Some(error_ty) => quote! {
+                #check_uiaa_response
+                ruma_api::exports::check_response_as::<#error_ty, _>(&http_response)?;
+            },
+            None => quote! {
+                #check_uiaa_response
+                ruma_api::exports::check_response(&http_response)?;
+            },
+        };
 
-            impl std::convert::TryFrom<Request> for ruma_api::exports::http::Request<Vec<u8>> {
-                type Error = ruma_api::Error;
+        let incoming_conversions =
+            if server { self.incoming_conversions() } else { TokenStream::new() };
 
-                #[allow(unused_mut, unused_variables)]
-                fn try_from(request: Request) -> Result<Self, Self::Error> {
-                    let metadata = Request::METADATA;
+        let response_body_bound =
+            body_bound(self.response.has_raw_body() || self.response.json_stream_field().is_some());
+
+        let outgoing_bytes_conversion = if cfg!(feature = "bytes-bodies") {
+            quote! {
+                impl std::convert::TryFrom<Request>
+                    for ruma_api::exports::http::Request<ruma_api::exports::bytes::Bytes>
+                {
+                    type Error = ruma_api::IntoHttpError;
+
+                    fn try_from(request: Request) -> Result<Self, Self::Error> {
+                        let http_request: ruma_api::exports::http::Request<Vec<u8>> =
+                            request.try_into()?;
+                        Ok(http_request.map(ruma_api::exports::bytes::Bytes::from))
+                    }
+                }
+            }
+        } else {
+            TokenStream::new()
+        };
+
+        let outgoing_conversions = if client {
+            quote! {
+                impl std::convert::TryFrom<Request> for ruma_api::exports::http::Request<Vec<u8>> {
+                    type Error = ruma_api::IntoHttpError;
+
+                    #[allow(unused_mut, unused_variables)]
+                    fn try_from(request: Request) -> Result<Self, Self::Error> {
+                        let metadata = Request::METADATA;
+
+                        let mut url = ruma_api::exports::placeholder_url();
+
+                        { #set_request_path }
+                        { #set_request_query }
+
+                        #create_http_request
 
-                    // Use dummy homeserver url which has to be overwritten in
-                    // the calling code. Previously (with http::Uri) this was
-                    // not required, but Url::parse only accepts absolute urls.
-                    let mut url =
-                        ruma_api::exports::url::Url::parse("http://invalid-host-please-change/")
-                            .unwrap();
+                        ruma_api::exports::set_method_and_uri(
+                            &mut http_request,
+                            metadata.method,
+                            &url,
+                        );
 
-                    { #set_request_path }
-                    { #set_request_query }
+                        { #add_headers_to_request }
+                        ruma_api::exports::apply_default_headers(&mut http_request);
 
-                    #create_http_request
+                        #compress_http_request_body
 
-                    *http_request.method_mut() = ruma_api::exports::http::Method::#method;
-                    *http_request.uri_mut() = url.into_string().parse().unwrap();
+                        ruma_api::exports::set_content_length(&mut http_request);
 
-                    { #add_headers_to_request }
+                        Ok(http_request)
+                    }
+                }
+
+                impl<B> std::convert::TryFrom<ruma_api::exports::http::Response<B>> for Response
+                where
+                    B: #response_body_bound,
+                {
+                    type Error = ruma_api::FromHttpResponseError;
+
+                    #[allow(unused_variables)]
+                    fn try_from(
+                        http_response: ruma_api::exports::http::Response<B>,
+                    ) -> Result<Self, Self::Error> {
+                        #check_response
 
-                    Ok(http_request)
+                        #extract_response_headers
+
+                        #save_response_headers
+
+                        let response_body = #try_deserialize_response_body;
+                        let response = Response {
+                            #response_init_fields
+                        };
+
+                        #validate_response
+
+                        Ok(response)
+                    }
+                }
+
+                #outgoing_bytes_conversion
+
+                impl ruma_api::OutgoingRequest for Request {
+                    type IncomingResponse = Response;
                 }
             }
+        } else {
+            TokenStream::new()
+        };
+
+        let compat_conversions = match &self.compat {
+            Some(compat) => compat.conversions(&self.request),
+            None => TokenStream::new(),
+        };
+
+        let api = quote! {
+            #[doc = #request_doc]
+            #request_types
 
             #[doc = #response_doc]
             #response_types
 
-            impl std::convert::TryFrom<ruma_api::exports::http::Response<Vec<u8>>> for Response {
-                type Error = ruma_api::Error;
+            #compat_conversions
+
+            #query_params_module
+
+            // Everything else is generated in a separate module, so it can't collide with other
+            // items in the module `ruma_api!` is used in.
+            mod __ruma_api {
+                use super::*;
+
+                use ruma_api::exports::serde::de::{Error as _, IntoDeserializer as _};
+                use ruma_api::exports::serde::Deserialize as _;
+                use ruma_api::Endpoint as _;
+
+                use std::convert::TryInto as _;
+
+                #request_hidden_types
+
+                #response_hidden_types
+
+                #outgoing_conversions
+
+                impl Request {
+                    /// The same as `Endpoint::METADATA`, for code that has a request value but
+                    /// can't name its type.
+                    pub fn metadata(&self) -> &'static ruma_api::Metadata {
+                        static METADATA: ruma_api::Metadata =
+                            <Request as ruma_api::Endpoint>::METADATA;
+                        &METADATA
+                    }
+                }
+
+                impl Response {
+                    /// Metadata of the endpoint this is the response of.
+                    pub fn metadata(&self) -> &'static ruma_api::Metadata {
+                        static METADATA: ruma_api::Metadata =
+                            <Request as ruma_api::Endpoint>::METADATA;
+                        &METADATA
+                    }
+                }
+
+                impl ruma_api::Endpoint for Request {
+                    /// Metadata for the `#name` endpoint.
+                    const METADATA: ruma_api::Metadata = ruma_api::Metadata {
+                        description: #description,
+                        method: ruma_api::exports::http::Method::#method,
+                        name: #name,
+                        path: #path,
+                        rate_limited: #rate_limited,
+                        requires_authentication: #requires_authentication,
+                        access_token_placement:
+                            ruma_api::AccessTokenPlacement::#access_token_placement,
+                        compress_request_body: #compress_request_body,
+                        history_paths: &[#(#history_paths),*],
+                        unstable_feature: #unstable_feature,
+                    };
+
+                    const NAME: &'static str = #name;
+                    const PATH: &'static str = #path;
+
+                    const IS_SAFE: bool = #is_safe;
+                    const IS_IDEMPOTENT: bool = #is_idempotent;
+
+                    const PATH_SEGMENTS: &'static [ruma_api::PathSegment] = &[
+                        #(#path_segments),*
+                    ];
+
+                    const PATH_PARAM_NAMES: &'static [&'static str] = &[
+                        #(#path_param_names),*
+                    ];
+
+                    const EXAMPLE_REQUEST_BODY: Option<&'static str> = #example_request_body;
+                    const EXAMPLE_RESPONSE_BODY: Option<&'static str> = #example_response_body;
+                }
+
+                #incoming_conversions
+            }
+        };
+
+        let mut attrs = self.attrs.clone();
+        if let Some(cfg) = &self.metadata.cfg {
+            attrs.push(parse_quote!(#[cfg(#cfg)]));
+        }
+
+        if attrs.is_empty() {
+            api.to_tokens(tokens);
+        } else {
+            let mut items: syn::File = syn::parse2(api).expect("ruma_api! generated invalid items");
+            for item in &mut items.items {
+                add_attrs(item, &attrs);
+            }
+
+            items.to_tokens(tokens);
+        }
+    }
+}
+
+impl Api {
+    /// Produces the server-side conversions from an `http::Request` and to an `http::Response`,
+    /// along with the `IncomingRequest` implementation.
+    fn incoming_conversions(&self) -> TokenStream {
+        let path = &self.metadata.path;
+        let history_paths = &self.metadata.history_paths;
+
+        let parse_request_path = if self.request.has_path_fields() {
+            let verbatim = self.request.verbatim_path_params();
+            quote! {
+                let request_path: RequestPath = ruma_api::exports::parse_request_path(
+                    &http_request,
+                    #path,
+                    &[#(#history_paths),*],
+                    &[#(#verbatim),*],
+                )?;
+            }
+        } else {
+            quote! {
+                ruma_api::exports::parse_request_path::<ruma_api::exports::serde::de::IgnoredAny, _>(
+                    &http_request,
+                    #path,
+                    &[#(#history_paths),*],
+                    &[],
+                )?;
+            }
+        };
+
+        let parse_request_query = if let Some(field) = self.request.query_map_field() {
+            // The query string is a map, which can't be deserialized as the newtype directly.
+            let field_type = &field.ty;
+            quote! {
+                let request_query = RequestQuery(
+                    ruma_api::exports::parse_query_string::<#field_type, _>(&http_request, &[])?,
+                );
+            }
+        } else if self.request.has_query_fields() {
+            let names = self.request.query_param_names();
+            quote! {
+                let request_query: RequestQuery =
+                    ruma_api::exports::parse_query_string(&http_request, &[#(#names),*])?;
+            }
+        } else {
+            TokenStream::new()
+        };
+
+        // Errors in a newtype body name the endpoint, since the body is all there is to go by.
+        let request_body_error = if self.request.newtype_body_field().is_some() {
+            let name = &self.metadata.name;
+            quote! {
+                .map_err(|error| ruma_api::exports::newtype_request_body_error(error, #name))
+            }
+        } else {
+            TokenStream::new()
+        };
+
+        let parse_request_body = if self.request.has_raw_body() {
+            // The body is moved out, the headers are still needed for the header fields.
+            quote! {
+                let (parts, request_body) = http_request.into_parts();
+                let request_body = request_body.into();
+                let http_request = ruma_api::exports::http::Request::from_parts(parts, ());
+            }
+        } else if self.metadata.compress_request_body.value
+            && (self.request.newtype_body_field().is_some() || self.request.has_body_fields())
+        {
+            quote! {
+                let request_body: RequestBody =
+                    ruma_api::exports::from_gzip_json_request(&http_request)#request_body_error?;
+            }
+        } else if self.request.newtype_body_field().is_some() || self.request.has_body_fields() {
+            quote! {
+                let request_body: RequestBody =
+                    ruma_api::exports::from_json_request(&http_request)#request_body_error?;
+            }
+        } else {
+            TokenStream::new()
+        };
+
+        let request_body_bound = body_bound(self.request.has_raw_body());
+        let request_init = self.request.incoming_request_init();
+        let check_incoming_headers = self.request.check_incoming_headers();
+        let query_params = if self.request.has_known_query_params() {
+            let names = self.request.query_param_names();
+            quote! { Some(&[#(#names),*]) }
+        } else {
+            quote! { None }
+        };
+        let compute_response_headers = self.response.compute_headers();
+        let create_http_response = self.response.create_http_response();
+        let add_headers_to_response = self.response.add_headers_to_response();
+
+        let incoming_bytes_conversion = if cfg!(feature = "bytes-bodies") {
+            quote! {
+                impl std::convert::TryFrom<Response>
+                    for ruma_api::exports::http::Response<ruma_api::exports::bytes::Bytes>
+                {
+                    type Error = ruma_api::IntoHttpError;
+
+                    fn try_from(response: Response) -> Result<Self, Self::Error> {
+                        let http_response: ruma_api::exports::http::Response<Vec<u8>> =
+                            response.try_into()?;
+                        Ok(http_response.map(ruma_api::exports::bytes::Bytes::from))
+                    }
+                }
+            }
+        } else {
+            TokenStream::new()
+        };
+
+        let path_round_trip_tests = if cfg!(feature = "path-round-trip-tests") {
+            self.request.path_round_trip_tests()
+        } else {
+            TokenStream::new()
+        };
+
+        quote! {
+            impl<B> std::convert::TryFrom<ruma_api::exports::http::Request<B>> for Request
+            where
+                B: #request_body_bound,
+            {
+                type Error = ruma_api::FromHttpRequestError;
 
                 #[allow(unused_variables)]
                 fn try_from(
-                    http_response: ruma_api::exports::http::Response<Vec<u8>>,
+                    http_request: ruma_api::exports::http::Request<B>,
                 ) -> Result<Self, Self::Error> {
-                    if http_response.status().is_success() {
-                        #extract_response_headers
+                    #parse_request_path
+                    #parse_request_query
+                    #parse_request_body
 
-                        let response_body = #try_deserialize_response_body;
-                        Ok(Response {
-                            #response_init_fields
-                        })
-                    } else {
-                        Err(http_response.status().clone().into())
+                    Ok(#request_init)
+                }
+            }
+
+            impl std::convert::TryFrom<Response> for ruma_api::exports::http::Response<Vec<u8>> {
+                type Error = ruma_api::IntoHttpError;
+
+                #[allow(unused_mut, unused_variables)]
+                fn try_from(response: Response) -> Result<Self, Self::Error> {
+                    #compute_response_headers
+                    #create_http_response
+
+                    {
+                        let headers = http_response.headers_mut();
+                        #add_headers_to_response
                     }
+
+                    Ok(http_response)
                 }
             }
 
-            impl ruma_api::Endpoint for Request {
-                type Response = Response;
+            impl Response {
+                /// Converts the response into an `http::Response` like its `TryFrom`
+                /// implementation, adding `headers` that the response doesn't set itself, e.g.
+                /// for CORS, caching or tracing.
+                pub fn try_into_http_response_with(
+                    self,
+                    headers: ruma_api::exports::http::HeaderMap,
+                ) -> Result<ruma_api::exports::http::Response<Vec<u8>>, ruma_api::IntoHttpError> {
+                    let mut http_response: ruma_api::exports::http::Response<Vec<u8>> =
+                        std::convert::TryFrom::try_from(self)?;
+                    ruma_api::exports::add_extra_headers(http_response.headers_mut(), headers);
+                    Ok(http_response)
+                }
+            }
 
-                /// Metadata for the `#name` endpoint.
-                const METADATA: ruma_api::Metadata = ruma_api::Metadata {
-                    description: #description,
-                    method: ruma_api::exports::http::Method::#method,
-                    name: #name,
-                    path: #path,
-                    rate_limited: #rate_limited,
-                    requires_authentication: #requires_authentication,
-                };
+            #incoming_bytes_conversion
+
+            impl ruma_api::IncomingRequest for Request {
+                type OutgoingResponse = Response;
+
+                const QUERY_PARAMS: Option<&'static [&'static str]> = #query_params;
+
+                #[allow(unused_variables)]
+                fn check_head<B>(
+                    http_request: &ruma_api::exports::http::Request<B>,
+                ) -> Result<(), ruma_api::FromHttpRequestError> {
+                    #parse_request_path
+                    #parse_request_query
+                    #check_incoming_headers
+
+                    Ok(())
+                }
             }
-        };
 
-        api.to_tokens(tokens);
+            #path_round_trip_tests
+        }
     }
 }
 
+/// The bound on the body type of the `http::Request` or `http::Response` a request or response is
+/// converted from.
+///
+/// Any byte buffer can be parsed, but bodies that end up in a field as they are have to be turned
+/// into a `Vec<u8>`, which doesn't copy them if they already are one.
+fn body_bound(owned: bool) -> TokenStream {
+    if owned {
+        quote! { AsRef<[u8]> + Into<Vec<u8>> }
+    } else {
+        quote! { AsRef<[u8]> }
+    }
+}
+
+/// Adds the given attributes to an item generated by `ruma_api!`.
+fn add_attrs(item: &mut Item, attrs: &[Attribute]) {
+    let item_attrs = match item {
+        Item::Enum(item) => &mut item.attrs,
+        Item::Impl(item) => &mut item.attrs,
+        Item::Mod(item) => &mut item.attrs,
+        Item::Struct(item) => &mut item.attrs,
+        _ => unreachable!("ruma_api! only generates enums, impls, modules and structs"),
+    };
+
+    item_attrs.extend(attrs.iter().cloned());
+}
+
 /// Custom keyword macros for syn.
 mod kw {
     use syn::custom_keyword;
@@ -350,21 +1044,36 @@ mod kw {
     custom_keyword!(metadata);
     custom_keyword!(request);
     custom_keyword!(response);
+    custom_keyword!(examples);
+    custom_keyword!(compat);
 }
 
 /// The entire `ruma_api!` macro structure directly as it appears in the source code..
 pub struct RawApi {
+    /// The outer attributes before the `metadata` section.
+    pub attrs: Vec<Attribute>,
     /// The `metadata` section of the macro.
     pub metadata: RawMetadata,
     /// The `request` section of the macro.
     pub request: RawRequest,
     /// The `response` section of the macro.
     pub response: RawResponse,
+    /// The optional `examples` section of the macro.
+    pub examples: Option<RawExamples>,
+    /// The optional `compat` section of the macro.
+    pub compat: Option<RawCompat>,
 }
 
 impl Parse for RawApi {
     fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
-        Ok(Self { metadata: input.parse()?, request: input.parse()?, response: input.parse()? })
+        Ok(Self {
+            attrs: input.call(Attribute::parse_outer)?,
+            metadata: input.parse()?,
+            request: input.parse()?,
+            response: input.parse()?,
+            examples: if input.peek(kw::examples) { Some(input.parse()?) } else { None },
+            compat: if input.peek(kw::compat) { Some(input.parse()?) } else { None },
+        })
     }
 }
 
@@ -400,6 +1109,17 @@ impl Parse for RawRequest {
         let fields;
         braced!(fields in input);
 
+        // A single field without a name makes the request a tuple struct.
+        if is_unnamed_field(&fields) {
+            let field = fields.call(Field::parse_unnamed)?;
+            fields.parse::<Option<Token![,]>>()?;
+            if !fields.is_empty() {
+                return Err(fields.error("A request with an unnamed field can only have one field"));
+            }
+
+            return Ok(Self { request_kw, fields: vec![field] });
+        }
+
         Ok(Self {
             request_kw,
             fields: fields
@@ -410,6 +1130,17 @@ impl Parse for RawRequest {
     }
 }
 
+/// Whether the next field in the input has no name, i.e. is not of the form `name: Type`.
+fn is_unnamed_field(input: ParseStream<'_>) -> bool {
+    let fork = input.fork();
+    if fork.call(syn::Attribute::parse_outer).is_err() || fork.parse::<Visibility>().is_err() {
+        return false;
+    }
+
+    let is_named = fork.peek(Ident) && fork.peek2(Token![:]) && !fork.peek2(Token![::]);
+    !fork.is_empty() && !is_named
+}
+
 pub struct RawResponse {
     pub response_kw: kw::response,
     pub fields: Vec<Field>,
@@ -430,3 +1161,75 @@ impl Parse for RawResponse {
         })
     }
 }
+
+pub struct RawExamples {
+    pub examples_kw: kw::examples,
+    pub field_values: Vec<FieldValue>,
+}
+
+impl Parse for RawExamples {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let examples_kw = input.parse::<kw::examples>()?;
+        let field_values;
+        braced!(field_values in input);
+
+        Ok(Self {
+            examples_kw,
+            field_values: field_values
+                .parse_terminated::<FieldValue, Token![,]>(FieldValue::parse)?
+                .into_iter()
+                .collect(),
+        })
+    }
+}
+
+/// The `compat` section of the macro, as it appears in the source code.
+pub struct RawCompat {
+    /// The `compat` keyword.
+    pub compat_kw: kw::compat,
+    /// The module of the previous version of the endpoint.
+    pub previous: Path,
+    /// The `request` mappings, along with the name their source is bound to.
+    pub request: Option<(Ident, Vec<FieldValue>)>,
+    /// The `response` mappings, along with the name their source is bound to.
+    pub response: Option<(Ident, Vec<FieldValue>)>,
+}
+
+impl Parse for RawCompat {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let compat_kw = input.parse::<kw::compat>()?;
+        let previous = input.call(Path::parse_mod_style)?;
+        let content;
+        braced!(content in input);
+
+        let mut request = None;
+        let mut response = None;
+
+        while !content.is_empty() {
+            let (source, slot) = if content.peek(kw::request) {
+                content.parse::<kw::request>()?;
+                (Ident::new("old", Span::call_site()), &mut request)
+            } else if content.peek(kw::response) {
+                content.parse::<kw::response>()?;
+                (Ident::new("new", Span::call_site()), &mut response)
+            } else {
+                return Err(content.error("expected `request` or `response`"));
+            };
+
+            if slot.is_some() {
+                return Err(content.error("duplicate section"));
+            }
+
+            let mappings;
+            braced!(mappings in content);
+            let mappings = mappings
+                .parse_terminated::<FieldValue, Token![,]>(FieldValue::parse)?
+                .into_iter()
+                .collect();
+
+            *slot = Some((source, mappings));
+        }
+
+        Ok(Self { compat_kw, previous, request, response })
+    }
+}
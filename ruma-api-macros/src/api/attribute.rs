@@ -2,10 +2,12 @@
 
 use syn::{
     parse::{Parse, ParseStream},
-    Ident, Token,
+    punctuated::Punctuated,
+    Ident, LitStr, Token,
 };
 
-/// Like syn::MetaNameValue, but expects an identifier as the value. Also, we don't care about the
+/// Like syn::MetaNameValue, but expects an identifier as the value, which may also be written as a
+/// string literal, like `from = "body_len"`. Also, we don't care about the
 /// the span of the equals sign, so we don't have the `eq_token` field from syn::MetaNameValue.
 pub struct MetaNameValue {
     /// The part left of the equals sign
@@ -23,14 +25,12 @@ pub enum Meta {
 }
 
 impl Meta {
-    /// Check if the given attribute is a ruma_api attribute. If it is, parse it.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the given attribute is a ruma_api attribute, but fails to parse.
-    pub fn from_attribute(attr: &syn::Attribute) -> syn::Result<Option<Self>> {
+    /// Check if the given attribute is a ruma_api attribute. If it is, parse it as a
+    /// comma-separated list, like `path, encode = none` in `#[ruma_api(path, encode = none)]`.
+    pub fn list_from_attribute(attr: &syn::Attribute) -> syn::Result<Option<Vec<Self>>> {
         if attr.path.is_ident("ruma_api") {
-            attr.parse_args().map(Some)
+            attr.parse_args_with(Punctuated::<Self, Token![,]>::parse_terminated)
+                .map(|list| Some(list.into_iter().collect()))
         } else {
             Ok(None)
         }
@@ -43,7 +43,12 @@ impl Parse for Meta {
 
         if input.peek(Token![=]) {
             let _ = input.parse::<Token![=]>();
-            Ok(Meta::NameValue(MetaNameValue { name: ident, value: input.parse()? }))
+            let value = if input.peek(LitStr) {
+                input.parse::<LitStr>()?.parse()?
+            } else {
+                input.parse()?
+            };
+            Ok(Meta::NameValue(MetaNameValue { name: ident, value }))
         } else {
             Ok(Meta::Word(ident))
         }
@@ -0,0 +1,143 @@
+//! Details of the `compat` section of the procedural macro.
+
+use std::convert::TryFrom;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, FieldValue, Ident, Member, Path};
+
+use crate::api::{request::Request, RawCompat};
+
+/// The result of processing the `compat` section of the macro.
+pub struct Compat {
+    /// The module of the previous version of the endpoint.
+    previous: Path,
+    /// The mappings for the conversion from the previous version's request, if any.
+    request: Option<Vec<FieldValue>>,
+    /// The mappings for the conversion to the previous version's response, if any.
+    response: Option<Vec<FieldValue>>,
+}
+
+impl TryFrom<RawCompat> for Compat {
+    type Error = syn::Error;
+
+    fn try_from(raw: RawCompat) -> syn::Result<Self> {
+        if raw.request.is_none() && raw.response.is_none() {
+            return Err(syn::Error::new_spanned(
+                raw.compat_kw,
+                "expected at least one of `request`, `response`",
+            ));
+        }
+
+        let request = raw.request.map(check_mappings).transpose()?;
+        let response = raw.response.map(check_mappings).transpose()?;
+
+        Ok(Self { previous: raw.previous, request, response })
+    }
+}
+
+impl Compat {
+    /// Checks that the request mappings only assign fields that exist in `request`.
+    pub fn check_request_fields(&self, request: &Request) -> Vec<syn::Error> {
+        let mappings = match &self.request {
+            Some(mappings) => mappings,
+            None => return Vec::new(),
+        };
+
+        let field_names = match request.field_names() {
+            Some(field_names) => field_names,
+            None => {
+                return vec![syn::Error::new_spanned(
+                    &self.previous,
+                    "compat conversions are not supported for tuple struct requests",
+                )]
+            }
+        };
+
+        mappings
+            .iter()
+            .filter(|mapping| !field_names.iter().any(|name| is_member(&mapping.member, name)))
+            .map(|mapping| syn::Error::new_spanned(&mapping.member, "no such field in `Request`"))
+            .collect()
+    }
+
+    /// Produces the conversions from the previous version's request and to the previous
+    /// version's response.
+    pub fn conversions(&self, request: &Request) -> TokenStream {
+        let previous = &self.previous;
+
+        let request_conversion = match (&self.request, request.field_names()) {
+            (Some(mappings), Some(field_names)) => {
+                let copied = field_names
+                    .into_iter()
+                    .filter(|name| !mappings.iter().any(|mapping| is_member(&mapping.member, name)))
+                    .collect::<Vec<_>>();
+
+                quote! {
+                    impl From<#previous::Request> for Request {
+                        #[allow(unused_variables)]
+                        fn from(old: #previous::Request) -> Self {
+                            Self {
+                                #(#mappings,)*
+                                #(#copied: old.#copied,)*
+                            }
+                        }
+                    }
+                }
+            }
+            _ => TokenStream::new(),
+        };
+
+        let response_conversion = match &self.response {
+            Some(mappings) => quote! {
+                impl From<Response> for #previous::Response {
+                    #[allow(unused_variables)]
+                    fn from(new: Response) -> Self {
+                        Self {
+                            #(#mappings,)*
+                        }
+                    }
+                }
+            },
+            None => TokenStream::new(),
+        };
+
+        quote! {
+            #request_conversion
+            #response_conversion
+        }
+    }
+}
+
+/// Whether `member` is the field named `name`.
+fn is_member(member: &Member, name: &Ident) -> bool {
+    match member {
+        Member::Named(ident) => ident == name,
+        Member::Unnamed(_) => false,
+    }
+}
+
+/// Turns shorthand mappings like `field` into `field: old.field` or `field: new.field`, and
+/// rejects fields that are mapped more than once.
+fn check_mappings((source, mappings): (Ident, Vec<FieldValue>)) -> syn::Result<Vec<FieldValue>> {
+    let mut checked: Vec<FieldValue> = Vec::with_capacity(mappings.len());
+
+    for mut mapping in mappings {
+        if checked.iter().any(|other| other.member == mapping.member) {
+            return Err(syn::Error::new_spanned(
+                &mapping.member,
+                "There can only be one mapping for each field",
+            ));
+        }
+
+        if mapping.colon_token.is_none() {
+            let member = &mapping.member;
+            mapping.expr = parse_quote!(#source.#member);
+            mapping.colon_token = Some(Default::default());
+        }
+
+        checked.push(mapping);
+    }
+
+    Ok(checked)
+}
@@ -0,0 +1,143 @@
+//! Details of the `examples` section of the procedural macro.
+
+use std::convert::TryFrom;
+
+use serde_json::{Map, Value};
+use syn::{Expr, ExprLit, Field, Lit, LitStr, Member};
+
+use crate::api::{has_serde_default, has_serde_word, option_inner_type, serde_rename, RawExamples};
+
+/// The result of processing the `examples` section of the macro.
+#[derive(Default)]
+pub struct Examples {
+    /// The example request body.
+    pub request: Option<Example>,
+    /// The example response body.
+    pub response: Option<Example>,
+}
+
+impl TryFrom<RawExamples> for Examples {
+    type Error = syn::Error;
+
+    fn try_from(raw: RawExamples) -> syn::Result<Self> {
+        let mut request = None;
+        let mut response = None;
+
+        for field_value in raw.field_values {
+            let identifier = match field_value.member.clone() {
+                Member::Named(identifier) => identifier,
+                _ => panic!("expected Member::Named"),
+            };
+            let literal = match field_value.expr.clone() {
+                Expr::Lit(ExprLit { lit: Lit::Str(literal), .. }) => literal,
+                expr => return Err(syn::Error::new_spanned(expr, "expected a string literal")),
+            };
+
+            match &identifier.to_string()[..] {
+                "request" => request = Some(Example::parse(literal)?),
+                "response" => response = Some(Example::parse(literal)?),
+                _ => return Err(syn::Error::new_spanned(field_value, "unexpected field")),
+            }
+        }
+
+        if request.is_none() && response.is_none() {
+            return Err(syn::Error::new_spanned(
+                raw.examples_kw,
+                "expected at least one of `request`, `response`",
+            ));
+        }
+
+        Ok(Self { request, response })
+    }
+}
+
+/// An example body, given as a string literal containing JSON.
+pub struct Example {
+    /// The literal, as written in the macro.
+    pub json: LitStr,
+    /// The parsed JSON value.
+    value: Value,
+}
+
+impl Example {
+    /// Parses the JSON in the literal, failing with an error on the literal if it is malformed.
+    fn parse(json: LitStr) -> syn::Result<Self> {
+        match serde_json::from_str(&json.value()) {
+            Ok(value) => Ok(Self { json, value }),
+            Err(error) => {
+                Err(syn::Error::new_spanned(json, format!("example is not valid JSON: {}", error)))
+            }
+        }
+    }
+
+    /// Checks the example against the body fields it is an example for.
+    ///
+    /// If `is_struct` is true, the body is deserialized as a struct with the given fields, so the
+    /// example must be an object with a key for every required field and no unknown keys. Unknown
+    /// keys are allowed if one of the fields is flattened. Otherwise, the types of the body aren't
+    /// known to the macro and nothing is checked.
+    pub fn check_fields<'a>(
+        &self,
+        fields: impl Iterator<Item = &'a Field>,
+        is_struct: bool,
+    ) -> Vec<syn::Error> {
+        if !is_struct {
+            return Vec::new();
+        }
+
+        let object = match &self.value {
+            Value::Object(object) => object,
+            _ => return vec![self.error("example must be a JSON object")],
+        };
+
+        let mut errors = Vec::new();
+        let mut known_keys = Vec::new();
+        let mut has_flatten = false;
+
+        for field in fields {
+            if has_serde_word(field, "flatten") {
+                has_flatten = true;
+                continue;
+            }
+
+            let key = serde_rename(field).unwrap_or_else(|| {
+                field.ident.as_ref().expect("expected field to have an identifier").to_string()
+            });
+            let is_required = option_inner_type(&field.ty).is_none() && !has_serde_default(field);
+
+            if is_required && !object.contains_key(&key) {
+                errors.push(self.error(&format!("example is missing the field `{}`", key)));
+            }
+
+            known_keys.push(key);
+        }
+
+        if !has_flatten {
+            errors.extend(
+                unknown_keys(object, &known_keys).map(|key| {
+                    self.error(&format!("example contains the unknown field `{}`", key))
+                }),
+            );
+        }
+
+        errors
+    }
+
+    /// A rustdoc section showing the example.
+    pub fn doc_section(&self) -> String {
+        let pretty = serde_json::to_string_pretty(&self.value).expect("failed to print JSON");
+        format!("\n\n# Example body\n\n```json\n{}\n```", pretty)
+    }
+
+    fn error(&self, message: &str) -> syn::Error {
+        syn::Error::new_spanned(&self.json, message)
+    }
+}
+
+/// The keys of the object that aren't in `known_keys`.
+fn unknown_keys<'a>(
+    object: &'a Map<String, Value>,
+    known_keys: &'a [String],
+) -> impl Iterator<Item = &'a String> {
+    object.keys().filter(move |key| !known_keys.contains(key))
+}
@@ -1,7 +1,8 @@
 //! Details of the `metadata` section of the procedural macro.
 
-use std::convert::TryFrom;
+use std::{collections::BTreeSet, convert::TryFrom};
 
+use proc_macro2::Span;
 use syn::{Expr, ExprLit, ExprPath, Ident, Lit, LitBool, LitStr, Member};
 
 use crate::api::RawMetadata;
@@ -20,6 +21,28 @@ pub struct Metadata {
     pub rate_limited: LitBool,
     /// The description field.
     pub requires_authentication: LitBool,
+    /// The access_token_placement field, a variant of `ruma_api::AccessTokenPlacement`.
+    pub access_token_placement: Ident,
+    /// The compress_request_body field.
+    pub compress_request_body: LitBool,
+    /// The empty_object_body field.
+    pub empty_object_body: LitBool,
+    /// The patch field, whether all request body fields are wrapped in `ruma_api::Maybe`.
+    pub patch: LitBool,
+    /// The history_paths field, earlier paths of the endpoint that incoming requests may still
+    /// use.
+    pub history_paths: Vec<LitStr>,
+    /// The unstable_feature field, the identifier of the MSC an unstable endpoint belongs to.
+    pub unstable_feature: Option<LitStr>,
+    /// The validate_response field, a function validating the response after it was converted.
+    pub validate_response: Option<ExprPath>,
+    /// The error_ty field, the type the bodies of unsuccessful responses are deserialized as.
+    pub error_ty: Option<ExprPath>,
+    /// The uiaa field, whether `401 Unauthorized` responses are parsed as user-interactive
+    /// authentication information.
+    pub uiaa: LitBool,
+    /// The cfg field, a configuration predicate all generated items are gated behind.
+    pub cfg: Option<Expr>,
 }
 
 impl TryFrom<RawMetadata> for Metadata {
@@ -32,6 +55,16 @@ impl TryFrom<RawMetadata> for Metadata {
         let mut path = None;
         let mut rate_limited = None;
         let mut requires_authentication = None;
+        let mut access_token_placement = None;
+        let mut compress_request_body = None;
+        let mut empty_object_body = None;
+        let mut patch = None;
+        let mut history_paths = Vec::new();
+        let mut unstable_feature = None;
+        let mut validate_response = None;
+        let mut error_ty = None;
+        let mut uiaa = None;
+        let mut cfg = None;
 
         for field_value in raw.field_values {
             let identifier = match field_value.member.clone() {
@@ -61,6 +94,7 @@ impl TryFrom<RawMetadata> for Metadata {
                 },
                 "path" => match expr {
                     Expr::Lit(ExprLit { lit: Lit::Str(literal), .. }) => {
+                        validate_path(&literal)?;
                         path = Some(literal);
                     }
                     _ => return Err(syn::Error::new_spanned(expr, "expected a string literal")),
@@ -77,6 +111,84 @@ impl TryFrom<RawMetadata> for Metadata {
                     }
                     _ => return Err(syn::Error::new_spanned(expr, "expected a bool literal")),
                 },
+                "access_token_placement" => match expr {
+                    Expr::Path(ExprPath { ref path, .. })
+                        if path.segments.len() == 1
+                            && (path.segments[0].ident == "AuthorizationHeader"
+                                || path.segments[0].ident == "QueryString") =>
+                    {
+                        access_token_placement = Some(path.segments[0].ident.clone());
+                    }
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            expr,
+                            "expected one of `AuthorizationHeader`, `QueryString`",
+                        ))
+                    }
+                },
+                "compress_request_body" => match expr {
+                    Expr::Lit(ExprLit { lit: Lit::Bool(literal), .. }) => {
+                        compress_request_body = Some(literal);
+                    }
+                    _ => return Err(syn::Error::new_spanned(expr, "expected a bool literal")),
+                },
+                "empty_object_body" => match expr {
+                    Expr::Lit(ExprLit { lit: Lit::Bool(literal), .. }) => {
+                        empty_object_body = Some(literal);
+                    }
+                    _ => return Err(syn::Error::new_spanned(expr, "expected a bool literal")),
+                },
+                "patch" => match expr {
+                    Expr::Lit(ExprLit { lit: Lit::Bool(literal), .. }) => {
+                        patch = Some(literal);
+                    }
+                    _ => return Err(syn::Error::new_spanned(expr, "expected a bool literal")),
+                },
+                "history_paths" => match expr {
+                    Expr::Array(array) => {
+                        for element in array.elems {
+                            match element {
+                                Expr::Lit(ExprLit { lit: Lit::Str(literal), .. }) => {
+                                    validate_path(&literal)?;
+                                    history_paths.push(literal);
+                                }
+                                _ => {
+                                    return Err(syn::Error::new_spanned(
+                                        element,
+                                        "expected a string literal",
+                                    ))
+                                }
+                            }
+                        }
+                    }
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            expr,
+                            "expected an array of string literals",
+                        ))
+                    }
+                },
+                "unstable_feature" => match expr {
+                    Expr::Lit(ExprLit { lit: Lit::Str(literal), .. }) => {
+                        unstable_feature = Some(literal);
+                    }
+                    _ => return Err(syn::Error::new_spanned(expr, "expected a string literal")),
+                },
+                "validate_response" => match expr {
+                    Expr::Path(path) => validate_response = Some(path),
+                    _ => return Err(syn::Error::new_spanned(expr, "expected a path")),
+                },
+                "error_ty" => match expr {
+                    Expr::Path(path) => error_ty = Some(path),
+                    _ => return Err(syn::Error::new_spanned(expr, "expected a type path")),
+                },
+                "uiaa" => match expr {
+                    Expr::Lit(ExprLit { lit: Lit::Bool(literal), .. }) => {
+                        uiaa = Some(literal);
+                    }
+                    _ => return Err(syn::Error::new_spanned(expr, "expected a bool literal")),
+                },
+                "cfg" => cfg = Some(expr),
                 _ => return Err(syn::Error::new_spanned(field_value, "unexpected field")),
             }
         }
@@ -85,14 +197,120 @@ impl TryFrom<RawMetadata> for Metadata {
         let missing_field =
             |name| syn::Error::new_spanned(metadata_kw, format!("missing field `{}`", name));
 
+        let mut path = path.ok_or_else(|| missing_field("path"))?;
+        if let Some(feature) = &unstable_feature {
+            path = unstable_path(&path, feature)?;
+        }
+
+        // Incoming requests on a history path are parsed into the same fields.
+        let path_value = path.value();
+        let params = path_params(&path_value);
+        for history_path in &history_paths {
+            if path_params(&history_path.value()) != params {
+                return Err(syn::Error::new_spanned(
+                    history_path,
+                    "history path must have the same path parameters as `path`",
+                ));
+            }
+        }
+
         Ok(Self {
             description: description.ok_or_else(|| missing_field("description"))?,
             method: method.ok_or_else(|| missing_field("method"))?,
             name: name.ok_or_else(|| missing_field("name"))?,
-            path: path.ok_or_else(|| missing_field("path"))?,
+            path,
             rate_limited: rate_limited.ok_or_else(|| missing_field("rate_limited"))?,
             requires_authentication: requires_authentication
                 .ok_or_else(|| missing_field("requires_authentication"))?,
+            access_token_placement: access_token_placement
+                .unwrap_or_else(|| Ident::new("AuthorizationHeader", Span::call_site())),
+            compress_request_body: compress_request_body
+                .unwrap_or_else(|| LitBool { value: false, span: Span::call_site() }),
+            empty_object_body: empty_object_body
+                .unwrap_or_else(|| LitBool { value: false, span: Span::call_site() }),
+            patch: patch.unwrap_or_else(|| LitBool { value: false, span: Span::call_site() }),
+            history_paths,
+            unstable_feature,
+            validate_response,
+            error_ty,
+            uiaa: uiaa.unwrap_or_else(|| LitBool { value: false, span: Span::call_site() }),
+            cfg,
         })
     }
 }
+
+/// Checks that a `path` literal is a valid path template.
+///
+/// All problems are reported at once, on the span of the literal.
+fn validate_path(literal: &LitStr) -> syn::Result<()> {
+    let path = literal.value();
+    let mut errors = Vec::new();
+
+    if !path.starts_with('/') {
+        errors.push("path must start with `/`".to_owned());
+    }
+
+    let mut placeholders = BTreeSet::new();
+    for segment in path.trim_start_matches('/').split('/') {
+        if segment.is_empty() {
+            errors.push("path must not contain empty segments".to_owned());
+        } else if let Some(name) = segment.strip_prefix(':') {
+            if syn::parse_str::<Ident>(name).is_err() {
+                errors.push(format!("path parameter `{}` is not a valid Rust identifier", segment));
+            } else if !placeholders.insert(name) {
+                errors.push(format!("path parameter `{}` occurs more than once", segment));
+            }
+        } else if let Some(c) = segment.chars().find(|&c| !is_path_char(c)) {
+            errors
+                .push(format!("path segment `{}` contains the illegal character `{}`", segment, c));
+        }
+    }
+
+    errors.dedup();
+    let mut errors = errors.into_iter().map(|message| syn::Error::new_spanned(literal, message));
+    match errors.next() {
+        Some(mut error) => {
+            for other in errors {
+                error.combine(other);
+            }
+
+            Err(error)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Returns the names of the parameters of a path template.
+fn path_params(path: &str) -> BTreeSet<&str> {
+    path.split('/').filter_map(|segment| segment.strip_prefix(':')).collect()
+}
+
+/// Replaces the version segment of a `/_matrix/<api>/<version>/...` path with
+/// `unstable/<feature>`, where unstable endpoints of the given feature live.
+fn unstable_path(path: &LitStr, feature: &LitStr) -> syn::Result<LitStr> {
+    let feature_value = feature.value();
+    if feature_value.is_empty() || !feature_value.chars().all(|c| c != ':' && is_path_char(c)) {
+        return Err(syn::Error::new_spanned(
+            feature,
+            "unstable feature must be a non-empty path segment, e.g. `org.matrix.msc3575`",
+        ));
+    }
+
+    let path_value = path.value();
+    let segments: Vec<_> = path_value.splitn(5, '/').collect();
+    if segments.len() < 5 || segments[1] != "_matrix" {
+        return Err(syn::Error::new_spanned(
+            path,
+            "path of an endpoint with `unstable_feature` must start with `/_matrix/<api>/<version>/`",
+        ));
+    }
+
+    let unstable = format!("/_matrix/{}/unstable/{}/{}", segments[2], feature_value, segments[4]);
+    Ok(LitStr::new(&unstable, path.span()))
+}
+
+/// Whether the given character can be used in a path segment without percent-encoding, per
+/// RFC 3986.
+fn is_path_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~!$&'()*+,;=:@".contains(c)
+}
@@ -4,21 +4,32 @@ use std::{convert::TryFrom, mem};
 
 use proc_macro2::TokenStream;
 use quote::{quote, quote_spanned, ToTokens};
-use syn::{spanned::Spanned, Field, Ident};
+use syn::{parse_quote, spanned::Spanned, Field, Ident};
 
 use crate::api::{
+    add_double_option_serde_attrs, apply_flatten_attr,
     attribute::{Meta, MetaNameValue},
-    strip_serde_attrs, RawRequest,
+    has_serde_word, option_inner_type, serde_rename, strip_serde_attrs, RawRequest,
 };
 
 /// The result of processing the `request` section of the macro.
 pub struct Request {
     /// The fields of the request.
     fields: Vec<RequestField>,
+    /// The `#[ruma_api(request_id)]` field, if any.
+    request_id_field: Option<Ident>,
+    /// The function serializing the newtype body field, if it has a
+    /// `#[ruma_api(manual_body = ...)]` attribute.
+    manual_body: Option<Ident>,
+    /// Whether the newtype body field is a `#[ruma_api(raw_body)]` field.
+    raw_body: bool,
 }
 
 impl Request {
     /// Produces code to add necessary HTTP headers to an `http::Request`.
+    ///
+    /// The headers are added in the order of the fields, each with a single value, as no two
+    /// header fields can have the same header name.
     pub fn add_headers_to_request(&self) -> TokenStream {
         let append_stmts = self.header_fields().map(|request_field| {
             let (field, header_name) = match request_field {
@@ -28,12 +39,28 @@ impl Request {
 
             let field_name = &field.ident;
 
-            quote! {
-                headers.append(
-                    ruma_api::exports::http::header::#header_name,
-                    ruma_api::exports::http::header::HeaderValue::from_str(request.#field_name.as_ref())
-                        .expect("failed to convert value into HeaderValue"),
-                );
+            if option_inner_type(&field.ty).is_some() && *field_name == self.request_id_field {
+                quote! {
+                    let request_id = request
+                        .#field_name
+                        .clone()
+                        .unwrap_or_else(ruma_api::exports::generate_request_id);
+                    ruma_api::exports::insert_header(headers, #header_name, &request_id);
+                }
+            } else if option_inner_type(&field.ty).is_some() {
+                quote! {
+                    if let Some(value) = request.#field_name.as_ref() {
+                        ruma_api::exports::insert_header(headers, #header_name, value.as_ref());
+                    }
+                }
+            } else {
+                quote! {
+                    ruma_api::exports::insert_header(
+                        headers,
+                        #header_name,
+                        request.#field_name.as_ref(),
+                    );
+                }
             }
         });
 
@@ -67,14 +94,213 @@ impl Request {
         self.fields.iter().filter_map(|field| field.as_body_field())
     }
 
+    /// Changes the type of all body fields from `T` to `ruma_api::Maybe<T>` for endpoints with
+    /// `patch: true`, along with the serde attributes needed to leave out absent fields.
+    ///
+    /// Flattened fields are left as they are.
+    pub fn wrap_body_fields_in_maybe(&mut self) -> syn::Result<()> {
+        for request_field in &mut self.fields {
+            let field = match request_field {
+                RequestField::Body(field) => field,
+                _ => continue,
+            };
+
+            if has_serde_word(field, "flatten") {
+                continue;
+            }
+
+            if option_inner_type(&field.ty).and_then(option_inner_type).is_some() {
+                return Err(syn::Error::new_spanned(
+                    &field.ty,
+                    "body fields of patch endpoints are wrapped in `ruma_api::Maybe` already, \
+                     use the inner type instead of `Option<Option<T>>`",
+                ));
+            }
+
+            let ty = &field.ty;
+            field.ty = parse_quote!(ruma_api::Maybe<#ty>);
+            field.attrs.push(parse_quote! {
+                #[serde(default, skip_serializing_if = "ruma_api::Maybe::is_absent")]
+            });
+        }
+
+        Ok(())
+    }
+
     /// Produces an iterator over all the header fields.
     pub fn header_fields(&self) -> impl Iterator<Item = &RequestField> {
         self.fields.iter().filter(|field| field.is_header())
     }
 
-    /// Gets the number of path fields.
-    pub fn path_field_count(&self) -> usize {
-        self.fields.iter().filter(|field| field.is_path()).count()
+    /// Produces an iterator over all the path fields.
+    pub fn path_fields(&self) -> impl Iterator<Item = &Field> {
+        self.fields.iter().filter(|field| field.is_path()).map(RequestField::field)
+    }
+
+    /// Gets the percent-encoding of the path field with the given name.
+    pub fn path_field_encoding(&self, name: &str) -> PathEncoding {
+        self.fields
+            .iter()
+            .find_map(|request_field| match request_field {
+                RequestField::Path(field, encoding)
+                    if field.ident.as_ref().map_or(false, |ident| ident == name) =>
+                {
+                    Some(*encoding)
+                }
+                _ => None,
+            })
+            .unwrap_or(PathEncoding::Segment)
+    }
+
+    /// Whether the request consists of a single unnamed field, making it a tuple struct.
+    fn is_tuple_struct(&self) -> bool {
+        self.fields.iter().any(|request_field| request_field.field().ident.is_none())
+    }
+
+    /// Gets the names of all fields, or `None` for a tuple struct.
+    pub fn field_names(&self) -> Option<Vec<&Ident>> {
+        self.fields.iter().map(|request_field| request_field.field().ident.as_ref()).collect()
+    }
+
+    /// Gets the function serializing the newtype body field, if it is serialized manually.
+    pub fn manual_body(&self) -> Option<&Ident> {
+        self.manual_body.as_ref()
+    }
+
+    /// Whether the newtype body field is used as the body as-is.
+    pub fn has_raw_body(&self) -> bool {
+        self.raw_body
+    }
+
+    /// Whether the request can be converted from an `http::Request`, which isn't the case for
+    /// bodies serialized manually.
+    pub fn is_incoming(&self) -> bool {
+        self.manual_body.is_none()
+    }
+
+    /// Gets the names of the path fields that are inserted into the path without percent-encoding.
+    pub fn verbatim_path_params(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter_map(|request_field| match request_field {
+                RequestField::Path(field, PathEncoding::Verbatim) => {
+                    field.ident.as_ref().map(Ident::to_string)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Produces a test module checking that each path field, filled with sample values including
+    /// reserved characters, comes out of the incoming path parser like it went into the URL.
+    ///
+    /// Verbatim path fields are left out, since they are expected to be encoded already.
+    pub fn path_round_trip_tests(&self) -> TokenStream {
+        let tests = self.fields.iter().filter_map(|request_field| {
+            let (field, encode) = match request_field {
+                RequestField::Path(field, PathEncoding::Segment) => {
+                    (field, quote!(ruma_api::exports::percent_encode_path_segment))
+                }
+                RequestField::Path(field, PathEncoding::Component) => {
+                    (field, quote!(ruma_api::exports::percent_encode_component))
+                }
+                _ => return None,
+            };
+            let ident = field.ident.as_ref().expect("expected field to have an identifier");
+            let name = ident.to_string();
+            let ty = &field.ty;
+            let serde_attrs = field.attrs.iter().filter(|attr| attr.path.is_ident("serde"));
+
+            Some(quote! {
+                #[test]
+                fn #ident() {
+                    #[derive(ruma_api::exports::serde::Deserialize)]
+                    struct PathParam {
+                        #(#serde_attrs)*
+                        #ident: #ty,
+                    }
+
+                    ruma_api::exports::assert_path_round_trip(
+                        <Request as ruma_api::Endpoint>::METADATA.path,
+                        #name,
+                        #encode,
+                        |param: PathParam| param.#ident,
+                    );
+                }
+            })
+        });
+
+        quote! {
+            #[cfg(test)]
+            mod path_round_trip_tests {
+                use super::*;
+
+                #(#tests)*
+            }
+        }
+    }
+
+    /// Produces code for a `Request` initializer from the parts of an incoming request, which are
+    /// in variables named `request_path`, `request_query`, `request_body` and `http_request`.
+    pub fn incoming_request_init(&self) -> TokenStream {
+        let values = self.fields.iter().map(|request_field| {
+            let field = request_field.field();
+            let field_name = &field.ident;
+            let span = field.span();
+
+            let value = match request_field {
+                RequestField::Body(_) => quote!(request_body.#field_name),
+                RequestField::NewtypeBody(_) if self.raw_body => quote!(request_body),
+                RequestField::NewtypeBody(_) => quote!(request_body.0),
+                RequestField::Path(..) => quote!(request_path.#field_name),
+                RequestField::Query(_) => quote!(request_query.#field_name),
+                RequestField::QueryMap(_) => quote!(request_query.0),
+                RequestField::Header(_, header_name) if option_inner_type(&field.ty).is_some() => {
+                    quote! {
+                        ruma_api::exports::get_header(http_request.headers(), #header_name)?
+                    }
+                }
+                RequestField::Header(_, header_name) => quote! {
+                    ruma_api::exports::get_required_header(http_request.headers(), #header_name)?
+                },
+            };
+
+            match field_name {
+                Some(field_name) => quote_spanned!(span=> #field_name: #value),
+                None => quote_spanned!(span=> #value),
+            }
+        });
+
+        if self.fields.is_empty() {
+            quote!(Request)
+        } else if self.is_tuple_struct() {
+            quote!(Request(#(#values),*))
+        } else {
+            quote!(Request { #(#values),* })
+        }
+    }
+
+    /// Produces code reading the header fields of a variable named `http_request`, returning an
+    /// error if any of them is missing or invalid.
+    pub fn check_incoming_headers(&self) -> TokenStream {
+        let checks = self.fields.iter().filter_map(|request_field| match request_field {
+            RequestField::Header(field, header_name) => {
+                Some(if option_inner_type(&field.ty).is_some() {
+                    quote! {
+                        ruma_api::exports::get_header(http_request.headers(), #header_name)?;
+                    }
+                } else {
+                    quote! {
+                        ruma_api::exports::get_required_header(http_request.headers(), #header_name)?;
+                    }
+                })
+            }
+            _ => None,
+        });
+
+        quote! {
+            #(#checks)*
+        }
     }
 
     /// Returns the body field.
@@ -97,6 +323,67 @@ impl Request {
         self.struct_init_fields(RequestFieldKind::Path, quote!(request))
     }
 
+    /// Produces a `query_params` module with a constant for the name of each query string
+    /// parameter, or nothing if the request has no named query parameters.
+    ///
+    /// Flattened query fields are left out, as their parameter names aren't known here.
+    pub fn query_params_module(&self) -> TokenStream {
+        let consts = self
+            .fields
+            .iter()
+            .filter_map(RequestField::as_query_field)
+            .filter(|field| !has_serde_word(field, "flatten"))
+            .map(|field| {
+                let field_name =
+                    field.ident.as_ref().expect("expected field to have an identifier");
+                let wire_name = query_param_name(field);
+                let const_name = Ident::new(
+                    &field_name.to_string().trim_start_matches("r#").to_uppercase(),
+                    field_name.span(),
+                );
+                let doc = format!("The name of the `{}` query parameter.", wire_name);
+
+                quote! {
+                    #[doc = #doc]
+                    pub const #const_name: &str = #wire_name;
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if consts.is_empty() {
+            return TokenStream::new();
+        }
+
+        quote! {
+            /// The names of the query string parameters of the endpoint.
+            pub mod query_params {
+                #(#consts)*
+            }
+        }
+    }
+
+    /// Whether the names of all query string parameters the request accepts are known, which
+    /// isn't the case with a query map field or flattened query fields.
+    pub fn has_known_query_params(&self) -> bool {
+        self.query_map_field().is_none()
+            && !self
+                .fields
+                .iter()
+                .filter_map(RequestField::as_query_field)
+                .any(|field| has_serde_word(field, "flatten"))
+    }
+
+    /// The names of the query string parameters of the named query fields, leaving out flattened
+    /// ones like `query_params_module`.
+    pub fn query_param_names(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter_map(RequestField::as_query_field)
+            .filter(|field| !has_serde_word(field, "flatten"))
+            .map(query_param_name)
+            .collect()
+    }
+
     /// Produces code for a struct initializer for query string fields on a variable named `request`.
     pub fn request_query_init_fields(&self) -> TokenStream {
         self.struct_init_fields(RequestFieldKind::Query, quote!(request))
@@ -125,6 +412,95 @@ impl Request {
             #(#fields,)*
         }
     }
+
+    /// Produces the types the request is converted through, which are not part of the public API
+    /// of the endpoint.
+    ///
+    /// The types are only serializable if the `client` conversions are generated, and only
+    /// deserializable if the `server` conversions are, so field types only need to implement the
+    /// traits the generated code uses. Without either, no types are needed.
+    pub fn hidden_types(&self, client: bool, server: bool) -> TokenStream {
+        if !client && !server {
+            return TokenStream::new();
+        }
+
+        let derive_serialize =
+            if client { quote!(ruma_api::exports::serde::Serialize,) } else { TokenStream::new() };
+        let derive_deserialize = if server {
+            quote!(ruma_api::exports::serde::Deserialize,)
+        } else {
+            TokenStream::new()
+        };
+
+        let request_body_struct = if self.manual_body.is_some() || self.raw_body {
+            TokenStream::new()
+        } else if let Some(field) = self.newtype_body_field() {
+            let ty = &field.ty;
+            let serde_attrs = field.attrs.iter().filter(|attr| attr.path.is_ident("serde"));
+            let span = field.span();
+
+            quote_spanned! {span=>
+                /// Data in the request body.
+                #[derive(Debug, #derive_deserialize #derive_serialize)]
+                struct RequestBody(#(#serde_attrs)* #ty);
+            }
+        } else if self.has_body_fields() {
+            let fields = self.fields.iter().filter_map(RequestField::as_body_field);
+
+            quote! {
+                /// Data in the request body.
+                #[derive(Debug, #derive_deserialize #derive_serialize)]
+                struct RequestBody {
+                    #(#fields),*
+                }
+            }
+        } else {
+            TokenStream::new()
+        };
+
+        let request_path_struct = if self.has_path_fields() {
+            let fields = self.fields.iter().filter_map(RequestField::as_path_field);
+
+            quote! {
+                /// Data in the request path.
+                #[derive(Debug, #derive_deserialize #derive_serialize)]
+                struct RequestPath {
+                    #(#fields),*
+                }
+            }
+        } else {
+            TokenStream::new()
+        };
+
+        let request_query_struct = if let Some(field) = self.query_map_field() {
+            let ty = &field.ty;
+            let span = field.span();
+
+            quote_spanned! {span=>
+                /// Data in the request's query string.
+                #[derive(Debug, #derive_deserialize #derive_serialize)]
+                struct RequestQuery(#ty);
+            }
+        } else if self.has_query_fields() {
+            let fields = self.fields.iter().filter_map(RequestField::as_query_field);
+
+            quote! {
+                /// Data in the request's query string.
+                #[derive(Debug, #derive_deserialize #derive_serialize)]
+                struct RequestQuery {
+                    #(#fields),*
+                }
+            }
+        } else {
+            TokenStream::new()
+        };
+
+        quote! {
+            #request_body_struct
+            #request_path_struct
+            #request_query_struct
+        }
+    }
 }
 
 impl TryFrom<RawRequest> for Request {
@@ -133,23 +509,40 @@ impl TryFrom<RawRequest> for Request {
     fn try_from(raw: RawRequest) -> syn::Result<Self> {
         let mut newtype_body_field = None;
         let mut query_map_field = None;
+        let mut request_id_field = None;
+        let mut manual_body = None;
+        let mut raw_body = false;
+        let mut content_type_header = false;
 
         let fields = raw
             .fields
             .into_iter()
             .map(|mut field| {
+                apply_flatten_attr(&mut field)?;
+
                 let mut field_kind = None;
                 let mut header = None;
+                let mut path_encoding = PathEncoding::Segment;
 
-                for attr in mem::replace(&mut field.attrs, Vec::new()) {
-                    let meta = match Meta::from_attribute(&attr)? {
-                        Some(m) => m,
+                for attr in mem::take(&mut field.attrs) {
+                    let mut metas = match Meta::list_from_attribute(&attr)? {
+                        Some(metas) => metas.into_iter(),
                         None => {
                             field.attrs.push(attr);
                             continue;
                         }
                     };
 
+                    let meta = match metas.next() {
+                        Some(meta) => meta,
+                        None => {
+                            return Err(syn::Error::new_spanned(
+                                attr,
+                                "Expected a field kind in #[ruma_api] attribute",
+                            ));
+                        }
+                    };
+
                     if field_kind.is_some() {
                         return Err(syn::Error::new_spanned(
                             attr,
@@ -176,6 +569,23 @@ impl TryFrom<RawRequest> for Request {
                                     newtype_body_field = Some(field.clone());
                                     RequestFieldKind::NewtypeBody
                                 }
+                                "raw_body" => {
+                                    if let Some(f) = &newtype_body_field {
+                                        let mut error = syn::Error::new_spanned(
+                                            field,
+                                            "There can only be one newtype body field",
+                                        );
+                                        error.combine(syn::Error::new_spanned(
+                                            f,
+                                            "Previous newtype body field",
+                                        ));
+                                        return Err(error);
+                                    }
+
+                                    newtype_body_field = Some(field.clone());
+                                    raw_body = true;
+                                    RequestFieldKind::NewtypeBody
+                                }
                                 "path" => RequestFieldKind::Path,
                                 "query" => RequestFieldKind::Query,
                                 "query_map" => {
@@ -194,33 +604,107 @@ impl TryFrom<RawRequest> for Request {
                                     query_map_field = Some(field.clone());
                                     RequestFieldKind::QueryMap
                                 },
+                                "request_id" => {
+                                    if let Some(f) = &request_id_field {
+                                        let mut error = syn::Error::new_spanned(
+                                            field,
+                                            "There can only be one request ID field",
+                                        );
+                                        error.combine(syn::Error::new_spanned(
+                                            f,
+                                            "Previous request ID field",
+                                        ));
+                                        return Err(error);
+                                    }
+
+                                    request_id_field = Some(field.clone());
+                                    header = Some(quote!(ruma_api::REQUEST_ID_HEADER));
+                                    RequestFieldKind::Header
+                                }
                                 _ => {
                                     return Err(syn::Error::new_spanned(
                                         ident,
-                                        "Invalid #[ruma_api] argument, expected one of `body`, `path`, `query`, `query_map`",
+                                        "Invalid #[ruma_api] argument, expected one of `body`, `path`, `query`, `query_map`, `raw_body`, `request_id`",
                                     ));
                                 }
                             }
                         }
                         Meta::NameValue(MetaNameValue { name, value }) => {
-                            if name != "header" {
+                            if name == "manual_body" {
+                                if let Some(f) = &newtype_body_field {
+                                    let mut error = syn::Error::new_spanned(
+                                        field,
+                                        "There can only be one newtype body field",
+                                    );
+                                    error.combine(syn::Error::new_spanned(
+                                        f,
+                                        "Previous newtype body field",
+                                    ));
+                                    return Err(error);
+                                }
+
+                                newtype_body_field = Some(field.clone());
+                                manual_body = Some(value);
+                                RequestFieldKind::NewtypeBody
+                            } else if name == "header" {
+                                content_type_header |= value == "CONTENT_TYPE";
+                                header = Some(quote!(ruma_api::exports::http::header::#value));
+                                RequestFieldKind::Header
+                            } else {
                                 return Err(syn::Error::new_spanned(
                                     name,
-                                    "Invalid #[ruma_api] argument with value, expected `header`"
+                                    "Invalid #[ruma_api] argument with value, expected one of `header`, `manual_body`"
                                 ));
                             }
-
-                            header = Some(value);
-                            RequestFieldKind::Header
                         }
                     });
+
+                    for meta in metas {
+                        match meta {
+                            Meta::NameValue(MetaNameValue { name, value })
+                                if name == "encode"
+                                    && field_kind == Some(RequestFieldKind::Path) =>
+                            {
+                                path_encoding = match &value.to_string()[..] {
+                                    "component" => PathEncoding::Component,
+                                    "none" => PathEncoding::Verbatim,
+                                    _ => {
+                                        return Err(syn::Error::new_spanned(
+                                            value,
+                                            "Invalid path encoding, expected one of `component`, `none`",
+                                        ));
+                                    }
+                                };
+                            }
+                            Meta::NameValue(MetaNameValue { name, .. }) => {
+                                return Err(syn::Error::new_spanned(
+                                    name,
+                                    "Invalid #[ruma_api] argument, only path fields accept `encode`",
+                                ));
+                            }
+                            Meta::Word(ident) => {
+                                return Err(syn::Error::new_spanned(
+                                    ident,
+                                    "There can only be one field kind attribute",
+                                ));
+                            }
+                        }
+                    }
                 }
 
-                Ok(RequestField::new(
-                    field_kind.unwrap_or(RequestFieldKind::Body),
-                    field,
-                    header,
-                ))
+                let field_kind = field_kind.unwrap_or(RequestFieldKind::Body);
+                if field.ident.is_none() && field_kind != RequestFieldKind::NewtypeBody {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        "An unnamed request field must be a `#[ruma_api(body)]` field",
+                    ));
+                }
+
+                if field_kind == RequestFieldKind::Body {
+                    add_double_option_serde_attrs(&mut field);
+                }
+
+                Ok(RequestField::new(field_kind, field, header, path_encoding))
             })
             .collect::<syn::Result<Vec<_>>>()?;
 
@@ -232,6 +716,15 @@ impl TryFrom<RawRequest> for Request {
             ));
         }
 
+        if let (true, Some(field)) = (raw_body, &newtype_body_field) {
+            if !content_type_header {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "A raw body field needs a `#[ruma_api(header = CONTENT_TYPE)]` field",
+                ));
+            }
+        }
+
         if query_map_field.is_some() && fields.iter().any(|f| f.is_query()) {
             return Err(syn::Error::new_spanned(
                 // TODO: raw,
@@ -240,7 +733,24 @@ impl TryFrom<RawRequest> for Request {
             ));
         }
 
-        Ok(Self { fields })
+        let mut header_names = Vec::new();
+        for request_field in &fields {
+            if let RequestField::Header(field, header_name) = request_field {
+                let header_name = header_name.to_string();
+                if header_names.contains(&header_name) {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        "There can only be one header field for each header name",
+                    ));
+                }
+
+                header_names.push(header_name);
+            }
+        }
+
+        let request_id_field = request_id_field.and_then(|field| field.ident);
+
+        Ok(Self { fields, request_id_field, manual_body, raw_body })
     }
 }
 
@@ -253,95 +763,26 @@ impl ToTokens for Request {
 
         let request_struct_body = if self.fields.is_empty() {
             quote!(;)
-        } else {
-            let fields =
-                self.fields.iter().map(|request_field| strip_serde_attrs(request_field.field()));
-
-            quote! {
-                {
-                    #(#fields),*
-                }
-            }
-        };
-
-        let request_body_struct = if let Some(field) = self.newtype_body_field() {
-            let ty = &field.ty;
-            let span = field.span();
-
-            quote_spanned! {span=>
-                /// Data in the request body.
-                #[derive(Debug, ruma_api::exports::serde::Serialize)]
-                struct RequestBody(#ty);
-            }
-        } else if self.has_body_fields() {
-            let fields = self.fields.iter().filter_map(RequestField::as_body_field);
+        } else if self.is_tuple_struct() {
+            let field = strip_serde_attrs(self.fields[0].field());
 
             quote! {
-                /// Data in the request body.
-                #[derive(Debug, ruma_api::exports::serde::Serialize)]
-                struct RequestBody {
-                    #(#fields),*
-                }
+                (#field);
             }
         } else {
-            TokenStream::new()
-        };
-
-        let request_path_struct = if self.has_path_fields() {
-            let fields = self.fields.iter().filter_map(RequestField::as_path_field);
-
-            quote! {
-                /// Data in the request path.
-                #[derive(
-                    Debug,
-                    ruma_api::exports::serde::Deserialize,
-                    ruma_api::exports::serde::Serialize,
-                )]
-                struct RequestPath {
-                    #(#fields),*
-                }
-            }
-        } else {
-            TokenStream::new()
-        };
-
-        let request_query_struct = if let Some(field) = self.query_map_field() {
-            let ty = &field.ty;
-            let span = field.span();
-
-            quote_spanned! {span=>
-                /// Data in the request's query string.
-                #[derive(
-                    Debug,
-                    ruma_api::exports::serde::Deserialize,
-                    ruma_api::exports::serde::Serialize,
-                )]
-                struct RequestQuery(#ty);
-            }
-        } else if self.has_query_fields() {
-            let fields = self.fields.iter().filter_map(RequestField::as_query_field);
+            let fields =
+                self.fields.iter().map(|request_field| strip_serde_attrs(request_field.field()));
 
             quote! {
-                /// Data in the request's query string.
-                #[derive(
-                    Debug,
-                    ruma_api::exports::serde::Deserialize,
-                    ruma_api::exports::serde::Serialize,
-                )]
-                struct RequestQuery {
+                {
                     #(#fields),*
                 }
             }
-        } else {
-            TokenStream::new()
         };
 
         let request = quote! {
             #request_struct_header
             #request_struct_body
-            #request_body_struct
-            #request_path_struct
-            #request_query_struct
         };
 
         request.to_tokens(tokens);
@@ -352,12 +793,12 @@ impl ToTokens for Request {
 pub enum RequestField {
     /// JSON data in the body of the request.
     Body(Field),
-    /// Data in an HTTP header.
-    Header(Field, Ident),
+    /// Data in an HTTP header, along with the expression for the header's name.
+    Header(Field, TokenStream),
     /// A specific data type in the body of the request.
     NewtypeBody(Field),
     /// Data that appears in the URL path.
-    Path(Field),
+    Path(Field, PathEncoding),
     /// Data that appears in the query string.
     Query(Field),
     /// Data that appears in the query string as dynamic key-value pairs.
@@ -366,14 +807,19 @@ pub enum RequestField {
 
 impl RequestField {
     /// Creates a new `RequestField`.
-    fn new(kind: RequestFieldKind, field: Field, header: Option<Ident>) -> Self {
+    fn new(
+        kind: RequestFieldKind,
+        field: Field,
+        header: Option<TokenStream>,
+        path_encoding: PathEncoding,
+    ) -> Self {
         match kind {
             RequestFieldKind::Body => RequestField::Body(field),
             RequestFieldKind::Header => {
                 RequestField::Header(field, header.expect("missing header name"))
             }
             RequestFieldKind::NewtypeBody => RequestField::NewtypeBody(field),
-            RequestFieldKind::Path => RequestField::Path(field),
+            RequestFieldKind::Path => RequestField::Path(field, path_encoding),
             RequestFieldKind::Query => RequestField::Query(field),
             RequestFieldKind::QueryMap => RequestField::QueryMap(field),
         }
@@ -442,7 +888,7 @@ impl RequestField {
             RequestField::Body(field)
             | RequestField::Header(field, _)
             | RequestField::NewtypeBody(field)
-            | RequestField::Path(field)
+            | RequestField::Path(field, _)
             | RequestField::Query(field)
             | RequestField::QueryMap(field) => field,
         }
@@ -458,6 +904,17 @@ impl RequestField {
     }
 }
 
+/// How the value of a path field is percent-encoded.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PathEncoding {
+    /// Encode the characters that aren't allowed in a path segment. This is the default.
+    Segment,
+    /// Encode all characters except for unreserved ones, i.e. `#[ruma_api(path, encode = component)]`.
+    Component,
+    /// Insert the value as-is, i.e. `#[ruma_api(path, encode = none)]`.
+    Verbatim,
+}
+
 /// The types of fields that a request can have, without their values.
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum RequestFieldKind {
@@ -474,3 +931,11 @@ enum RequestFieldKind {
     /// See the similarly named variant of `RequestField`.
     QueryMap,
 }
+
+/// Returns the name of the query string parameter of a query field.
+fn query_param_name(field: &Field) -> String {
+    serde_rename(field).unwrap_or_else(|| {
+        let field_name = field.ident.as_ref().expect("expected field to have an identifier");
+        field_name.to_string().trim_start_matches("r#").to_owned()
+    })
+}
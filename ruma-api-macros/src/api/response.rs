@@ -7,14 +7,27 @@ use quote::{quote, quote_spanned, ToTokens};
 use syn::{spanned::Spanned, Field, Ident};
 
 use crate::api::{
+    add_double_option_serde_attrs, apply_flatten_attr,
     attribute::{Meta, MetaNameValue},
-    strip_serde_attrs, RawResponse,
+    option_inner_type, strip_serde_attrs, vec_inner_type, RawResponse,
 };
 
 /// The result of processing the `response` section of the macro.
 pub struct Response {
     /// The fields of the response.
     fields: Vec<ResponseField>,
+    /// The `Vec` body field the response can be iterated over as, if any.
+    iter_field: Option<Field>,
+    /// The fields with a `#[ruma_api(validate = ...)]` attribute, along with their validators.
+    validated_fields: Vec<(Ident, Ident)>,
+    /// The header fields with a `#[ruma_api(from = ...)]` attribute, along with the functions
+    /// computing their values.
+    computed_headers: Vec<(Ident, Ident)>,
+    /// The function deserializing the newtype body field, if it has a
+    /// `#[ruma_api(manual_body = ...)]` attribute.
+    manual_body: Option<Ident>,
+    /// Whether the newtype body field is a `#[ruma_api(raw_body)]` field.
+    raw_body: bool,
 }
 
 impl Response {
@@ -23,6 +36,11 @@ impl Response {
         self.fields.iter().any(|field| field.is_body())
     }
 
+    /// Produces an iterator over all the body fields.
+    pub fn body_fields(&self) -> impl Iterator<Item = &Field> {
+        self.fields.iter().filter_map(ResponseField::as_body_field)
+    }
+
     /// Whether or not this response has any fields.
     pub fn has_fields(&self) -> bool {
         !self.fields.is_empty()
@@ -50,15 +68,27 @@ impl Response {
                     field.ident.as_ref().expect("expected field to have an identifier");
                 let span = field.span();
 
-                quote_spanned! {span=>
-                    #field_name: headers.remove(ruma_api::exports::http::header::#header_name)
-                        .expect("response missing expected header")
-                        .to_str()
-                        .expect("failed to convert HeaderValue to str")
-                        .to_owned()
+                if option_inner_type(&field.ty).is_some() {
+                    quote_spanned! {span=>
+                        #field_name: ruma_api::exports::take_header(&mut headers, #header_name)
+                    }
+                } else if vec_inner_type(&field.ty).is_some() {
+                    quote_spanned! {span=>
+                        #field_name: ruma_api::exports::take_header_values(
+                            &mut headers,
+                            #header_name,
+                        )
+                    }
+                } else {
+                    quote_spanned! {span=>
+                        #field_name: ruma_api::exports::take_header(&mut headers, #header_name)
+                            .expect("response missing expected header")
+                    }
                 }
             }
-            ResponseField::NewtypeBody(field) => {
+            ResponseField::NewtypeBody(field)
+            | ResponseField::JsonStream(field)
+            | ResponseField::Multipart(field) => {
                 let field_name =
                     field.ident.as_ref().expect("expected field to have an identifier");
                 let span = field.span();
@@ -78,6 +108,196 @@ impl Response {
     pub fn newtype_body_field(&self) -> Option<&Field> {
         self.fields.iter().find_map(ResponseField::as_newtype_body_field)
     }
+
+    /// Produces code calling the validators of all validated fields on `response`.
+    pub fn validate_fields(&self) -> TokenStream {
+        let validations = self.validated_fields.iter().map(|(field_name, validator)| {
+            let name = field_name.to_string();
+
+            quote! {
+                if let Err(error) = #validator(&response.#field_name) {
+                    return Err(ruma_api::exports::validation_error(
+                        Some(#name),
+                        error,
+                        &response_headers,
+                    ));
+                }
+            }
+        });
+
+        quote! {
+            #(#validations)*
+        }
+    }
+
+    /// Whether any field of this response has a validator.
+    pub fn has_validated_fields(&self) -> bool {
+        !self.validated_fields.is_empty()
+    }
+
+    /// Gets the function deserializing the newtype body field, if it is deserialized manually.
+    pub fn manual_body(&self) -> Option<&Ident> {
+        self.manual_body.as_ref()
+    }
+
+    /// Whether the body is used as the newtype body field as-is.
+    pub fn has_raw_body(&self) -> bool {
+        self.raw_body
+    }
+
+    /// Whether the response can be converted to an `http::Response`, which isn't the case for
+    /// bodies deserialized manually, JSON streams and multipart bodies.
+    pub fn is_outgoing(&self) -> bool {
+        self.manual_body.is_none()
+            && self.json_stream_field().is_none()
+            && self.multipart_field().is_none()
+    }
+
+    /// Produces code creating an `http::Response` named `http_response` from the body fields of
+    /// a variable named `response`.
+    pub fn create_http_response(&self) -> TokenStream {
+        if let Some(field) = self.newtype_body_field() {
+            let field_name = field.ident.as_ref().expect("expected field to have an identifier");
+
+            if self.raw_body {
+                quote! {
+                    let mut http_response =
+                        ruma_api::exports::http::Response::new(response.#field_name);
+                }
+            } else {
+                quote! {
+                    let response_body = ResponseBody(response.#field_name);
+                    let mut http_response = ruma_api::exports::json_response(&response_body)?;
+                }
+            }
+        } else if self.has_body_fields() {
+            let fields = self.body_fields().map(|field| {
+                let field_name =
+                    field.ident.as_ref().expect("expected field to have an identifier");
+                let span = field.span();
+
+                quote_spanned! {span=>
+                    #field_name: response.#field_name
+                }
+            });
+
+            quote! {
+                let response_body = ResponseBody { #(#fields),* };
+                let mut http_response = ruma_api::exports::json_response(&response_body)?;
+            }
+        } else {
+            quote! {
+                let mut http_response = ruma_api::exports::empty_json_response();
+            }
+        }
+    }
+
+    /// Produces code replacing the values of computed header fields of a variable named `response`
+    /// before it is converted into an `http::Response`.
+    pub fn compute_headers(&self) -> TokenStream {
+        if self.computed_headers.is_empty() {
+            return TokenStream::new();
+        }
+
+        let assignments = self.computed_headers.iter().map(|(field_name, function)| {
+            quote! {
+                response.#field_name = #function(&response);
+            }
+        });
+
+        quote! {
+            let mut response = response;
+            #(#assignments)*
+        }
+    }
+
+    /// Produces code to add the header fields of a variable named `response` to `headers`.
+    ///
+    /// The values are appended in the order the fields are declared in, so several fields, or a
+    /// single `Vec` field, can make up a header with multiple values like `Set-Cookie`.
+    pub fn add_headers_to_response(&self) -> TokenStream {
+        let mut seen_header_names = Vec::new();
+        let insert_stmts = self.fields.iter().filter_map(|response_field| {
+            let (field, header_name) = match response_field {
+                ResponseField::Header(field, header_name) => (field, header_name),
+                _ => return None,
+            };
+            let field_name = &field.ident;
+
+            let name = header_name.to_string();
+            let replace = !seen_header_names.contains(&name);
+            if replace {
+                seen_header_names.push(name);
+            }
+
+            let values = if option_inner_type(&field.ty).is_some() {
+                quote! { response.#field_name.as_ref().map(|value| value.as_ref()) }
+            } else if vec_inner_type(&field.ty).is_some() {
+                quote! { response.#field_name.iter().map(|value| value.as_ref()) }
+            } else {
+                quote! { std::iter::once(response.#field_name.as_ref()) }
+            };
+
+            Some(quote! {
+                ruma_api::exports::append_header(headers, #header_name, #values, #replace);
+            })
+        });
+
+        quote! {
+            #(#insert_stmts)*
+        }
+    }
+
+    /// Gets the JSON stream field, if this response has one.
+    pub fn json_stream_field(&self) -> Option<&Field> {
+        self.fields.iter().find_map(ResponseField::as_json_stream_field)
+    }
+
+    /// Gets the multipart field, if this response has one.
+    pub fn multipart_field(&self) -> Option<&Field> {
+        self.fields.iter().find_map(ResponseField::as_multipart_field)
+    }
+
+    /// Produces the types the response is converted through, which are not part of the public API
+    /// of the endpoint.
+    ///
+    /// The response body is only deserializable if the `client` conversions are generated, and
+    /// only serializable if the `server` conversions are, see `Request::hidden_types`.
+    pub fn hidden_types(&self, client: bool, server: bool) -> TokenStream {
+        let derive_deserialize = if client {
+            quote!(ruma_api::exports::serde::Deserialize,)
+        } else {
+            TokenStream::new()
+        };
+        let derive_serialize =
+            if server { quote!(ruma_api::exports::serde::Serialize,) } else { TokenStream::new() };
+
+        if (!client && !server) || self.manual_body.is_some() || self.raw_body {
+            TokenStream::new()
+        } else if let Some(field) = self.newtype_body_field() {
+            let ty = &field.ty;
+            let serde_attrs = field.attrs.iter().filter(|attr| attr.path.is_ident("serde"));
+            let span = field.span();
+
+            quote_spanned! {span=>
+                /// Data in the response body.
+                #[derive(Debug, #derive_deserialize #derive_serialize)]
+                struct ResponseBody(#(#serde_attrs)* #ty);
+            }
+        } else if self.has_body_fields() {
+            let fields = self.fields.iter().filter_map(ResponseField::as_body_field);
+
+            quote! {
+                /// Data in the response body.
+                #[derive(Debug, #derive_deserialize #derive_serialize)]
+                struct ResponseBody {
+                    #(#fields),*
+                }
+            }
+        } else {
+            TokenStream::new()
+        }
+    }
 }
 
 impl TryFrom<RawResponse> for Response {
@@ -85,74 +305,208 @@ impl TryFrom<RawResponse> for Response {
 
     fn try_from(raw: RawResponse) -> syn::Result<Self> {
         let mut newtype_body_field = None;
+        let mut json_stream_field = None;
+        let mut multipart_field = None;
+        let mut iter_field = None;
+        let mut validated_fields = Vec::new();
+        let mut computed_headers = Vec::new();
+        let mut manual_body = None;
+        let mut raw_body = false;
 
         let fields = raw
             .fields
             .into_iter()
             .map(|mut field| {
+                apply_flatten_attr(&mut field)?;
+
                 let mut field_kind = None;
                 let mut header = None;
+                let mut computed_header = None;
 
-                for attr in mem::replace(&mut field.attrs, Vec::new()) {
-                    let meta = match Meta::from_attribute(&attr)? {
-                        Some(m) => m,
+                for attr in mem::take(&mut field.attrs) {
+                    let metas = match Meta::list_from_attribute(&attr)? {
+                        Some(metas) => metas,
                         None => {
                             field.attrs.push(attr);
                             continue;
                         }
                     };
 
-                    if field_kind.is_some() {
-                        return Err(syn::Error::new_spanned(
-                            attr,
-                            "There can only be one field kind attribute",
-                        ));
-                    }
-
-                    field_kind = Some(match meta {
-                        Meta::Word(ident) => {
-                            if ident != "body" {
-                                return Err(syn::Error::new_spanned(
-                                    ident,
-                                    "Invalid #[ruma_api] argument with value, expected `body`",
-                                ));
+                    for meta in metas {
+                        if let Meta::NameValue(MetaNameValue { name, value }) = &meta {
+                            if name == "validate" {
+                                let field_name =
+                                    field.ident.clone().expect("expected field to have an identifier");
+                                validated_fields.push((field_name, value.clone()));
+                                continue;
                             }
 
-                            if let Some(f) = &newtype_body_field {
-                                let mut error = syn::Error::new_spanned(
-                                    field,
-                                    "There can only be one newtype body field",
-                                );
-                                error.combine(syn::Error::new_spanned(
-                                    f,
-                                    "Previous newtype body field",
-                                ));
-                                return Err(error);
+                            if name == "from" {
+                                computed_header = Some(value.clone());
+                                continue;
                             }
+                        }
 
-                            newtype_body_field = Some(field.clone());
-                            ResponseFieldKind::NewtypeBody
+                        if field_kind.is_some() {
+                            return Err(syn::Error::new_spanned(
+                                attr,
+                                "There can only be one field kind attribute",
+                            ));
                         }
-                        Meta::NameValue(MetaNameValue { name, value }) => {
-                            if name != "header" {
-                                return Err(syn::Error::new_spanned(
-                                    name,
-                                    "Invalid #[ruma_api] argument with value, expected `header`",
-                                ));
+
+                        field_kind = Some(match meta {
+                            Meta::Word(ident) => match &ident.to_string()[..] {
+                                "body" => {
+                                    if let Some(f) = &newtype_body_field {
+                                        let mut error = syn::Error::new_spanned(
+                                            field,
+                                            "There can only be one newtype body field",
+                                        );
+                                        error.combine(syn::Error::new_spanned(
+                                            f,
+                                            "Previous newtype body field",
+                                        ));
+                                        return Err(error);
+                                    }
+
+                                    newtype_body_field = Some(field.clone());
+                                    ResponseFieldKind::NewtypeBody
+                                }
+                                "raw_body" => {
+                                    if let Some(f) = &newtype_body_field {
+                                        let mut error = syn::Error::new_spanned(
+                                            field,
+                                            "There can only be one newtype body field",
+                                        );
+                                        error.combine(syn::Error::new_spanned(
+                                            f,
+                                            "Previous newtype body field",
+                                        ));
+                                        return Err(error);
+                                    }
+
+                                    newtype_body_field = Some(field.clone());
+                                    raw_body = true;
+                                    ResponseFieldKind::NewtypeBody
+                                }
+                                "json_stream" => {
+                                    if let Some(f) = &json_stream_field {
+                                        let mut error = syn::Error::new_spanned(
+                                            field,
+                                            "There can only be one JSON stream field",
+                                        );
+                                        error.combine(syn::Error::new_spanned(
+                                            f,
+                                            "Previous JSON stream field",
+                                        ));
+                                        return Err(error);
+                                    }
+
+                                    json_stream_field = Some(field.clone());
+                                    ResponseFieldKind::JsonStream
+                                }
+                                "multipart" => {
+                                    if let Some(f) = &multipart_field {
+                                        let mut error = syn::Error::new_spanned(
+                                            field,
+                                            "There can only be one multipart field",
+                                        );
+                                        error.combine(syn::Error::new_spanned(
+                                            f,
+                                            "Previous multipart field",
+                                        ));
+                                        return Err(error);
+                                    }
+
+                                    multipart_field = Some(field.clone());
+                                    ResponseFieldKind::Multipart
+                                }
+                                "request_id" => {
+                                    header = Some(quote!(ruma_api::REQUEST_ID_HEADER));
+                                    ResponseFieldKind::Header
+                                }
+                                "iter" => {
+                                    if let Some(f) = &iter_field {
+                                        let mut error = syn::Error::new_spanned(
+                                            field,
+                                            "There can only be one iter field",
+                                        );
+                                        error.combine(syn::Error::new_spanned(f, "Previous iter field"));
+                                        return Err(error);
+                                    }
+
+                                    if vec_inner_type(&field.ty).is_none() {
+                                        return Err(syn::Error::new_spanned(
+                                            &field.ty,
+                                            "iter fields must be of type `Vec<T>`",
+                                        ));
+                                    }
+
+                                    iter_field = Some(field.clone());
+                                    ResponseFieldKind::Body
+                                }
+                                _ => {
+                                    return Err(syn::Error::new_spanned(
+                                        ident,
+                                        "Invalid #[ruma_api] argument, expected one of `body`, `iter`, `json_stream`, `multipart`, `raw_body`, `request_id`",
+                                    ));
+                                }
+                            },
+                            Meta::NameValue(MetaNameValue { name, value }) => {
+                                if name == "manual_body" {
+                                    if let Some(f) = &newtype_body_field {
+                                        let mut error = syn::Error::new_spanned(
+                                            field,
+                                            "There can only be one newtype body field",
+                                        );
+                                        error.combine(syn::Error::new_spanned(
+                                            f,
+                                            "Previous newtype body field",
+                                        ));
+                                        return Err(error);
+                                    }
+
+                                    newtype_body_field = Some(field.clone());
+                                    manual_body = Some(value);
+                                    ResponseFieldKind::NewtypeBody
+                                } else if name == "header" {
+                                    header = Some(quote!(ruma_api::exports::http::header::#value));
+                                    ResponseFieldKind::Header
+                                } else {
+                                    return Err(syn::Error::new_spanned(
+                                        name,
+                                        "Invalid #[ruma_api] argument with value, expected one of `from`, `header`, `manual_body`, `validate`",
+                                    ));
+                                }
                             }
+                        });
+                    }
+                }
 
-                            header = Some(value);
-                            ResponseFieldKind::Header
-                        }
-                    });
+                if let Some(function) = computed_header {
+                    if !matches!(field_kind, Some(ResponseFieldKind::Header)) {
+                        return Err(syn::Error::new_spanned(
+                            function,
+                            "Only header fields can be computed with `from`",
+                        ));
+                    }
+
+                    let field_name =
+                        field.ident.clone().expect("expected field to have an identifier");
+                    computed_headers.push((field_name, function));
                 }
 
                 Ok(match field_kind.unwrap_or(ResponseFieldKind::Body) {
-                    ResponseFieldKind::Body => ResponseField::Body(field),
+                    ResponseFieldKind::Body => {
+                        add_double_option_serde_attrs(&mut field);
+                        ResponseField::Body(field)
+                    }
                     ResponseFieldKind::Header => {
                         ResponseField::Header(field, header.expect("missing header name"))
                     }
                     ResponseFieldKind::NewtypeBody => ResponseField::NewtypeBody(field),
+                    ResponseFieldKind::JsonStream => ResponseField::JsonStream(field),
+                    ResponseFieldKind::Multipart => ResponseField::Multipart(field),
                 })
             })
             .collect::<syn::Result<Vec<_>>>()?;
@@ -165,7 +519,28 @@ impl TryFrom<RawResponse> for Response {
             ));
         }
 
-        Ok(Self { fields })
+        if json_stream_field.is_some()
+            && (newtype_body_field.is_some() || fields.iter().any(|f| f.is_body()))
+        {
+            return Err(syn::Error::new_spanned(
+                // TODO: raw,
+                raw.response_kw,
+                "Can't have both a JSON stream field and body fields",
+            ));
+        }
+
+        if multipart_field.is_some()
+            && (json_stream_field.is_some()
+                || newtype_body_field.is_some()
+                || fields.iter().any(|f| f.is_body()))
+        {
+            return Err(syn::Error::new_spanned(
+                raw.response_kw,
+                "Can't have both a multipart field and body fields",
+            ));
+        }
+
+        Ok(Self { fields, iter_field, validated_fields, computed_headers, manual_body, raw_body })
     }
 }
 
@@ -189,33 +564,75 @@ impl ToTokens for Response {
             }
         };
 
-        let response_body_struct = if let Some(field) = self.newtype_body_field() {
-            let ty = &field.ty;
-            let span = field.span();
+        let response_into_inner = match &self.fields[..] {
+            [ResponseField::Body(field)]
+            | [ResponseField::NewtypeBody(field)]
+            | [ResponseField::JsonStream(field)]
+            | [ResponseField::Multipart(field)] => {
+                let field_name =
+                    field.ident.as_ref().expect("expected field to have an identifier");
+                let ty = &field.ty;
 
-            quote_spanned! {span=>
-                /// Data in the response body.
-                #[derive(Debug, ruma_api::exports::serde::Deserialize)]
-                struct ResponseBody(#ty);
+                quote! {
+                    impl Response {
+                        /// Consumes the response and returns its only field.
+                        pub fn into_inner(self) -> #ty {
+                            self.#field_name
+                        }
+                    }
+
+                    impl From<Response> for #ty {
+                        fn from(response: Response) -> Self {
+                            response.into_inner()
+                        }
+                    }
+                }
             }
-        } else if self.has_body_fields() {
-            let fields = self.fields.iter().filter_map(ResponseField::as_body_field);
+            _ => TokenStream::new(),
+        };
 
-            quote! {
-                /// Data in the response body.
-                #[derive(Debug, ruma_api::exports::serde::Deserialize)]
-                struct ResponseBody {
-                    #(#fields),*
+        let response_iter = match &self.iter_field {
+            Some(field) => {
+                let field_name =
+                    field.ident.as_ref().expect("expected field to have an identifier");
+                let item = vec_inner_type(&field.ty).expect("expected iter field to be a `Vec`");
+
+                quote! {
+                    impl std::ops::Deref for Response {
+                        type Target = [#item];
+
+                        fn deref(&self) -> &Self::Target {
+                            &self.#field_name
+                        }
+                    }
+
+                    impl IntoIterator for Response {
+                        type Item = #item;
+                        type IntoIter = std::vec::IntoIter<#item>;
+
+                        fn into_iter(self) -> Self::IntoIter {
+                            self.#field_name.into_iter()
+                        }
+                    }
+
+                    impl<'a> IntoIterator for &'a Response {
+                        type Item = &'a #item;
+                        type IntoIter = std::slice::Iter<'a, #item>;
+
+                        fn into_iter(self) -> Self::IntoIter {
+                            self.#field_name.iter()
+                        }
+                    }
                 }
             }
-        } else {
-            TokenStream::new()
+            None => TokenStream::new(),
         };
 
         let response = quote! {
             #response_struct_header
             #response_struct_body
-            #response_body_struct
+            #response_into_inner
+            #response_iter
         };
 
         response.to_tokens(tokens);
@@ -226,10 +643,14 @@ impl ToTokens for Response {
 pub enum ResponseField {
     /// JSON data in the body of the response.
     Body(Field),
-    /// Data in an HTTP header.
-    Header(Field, Ident),
+    /// Data in an HTTP header, along with the expression for the header's name.
+    Header(Field, TokenStream),
     /// A specific data type in the body of the response.
     NewtypeBody(Field),
+    /// A lazily deserialized sequence of JSON values making up the body of the response.
+    JsonStream(Field),
+    /// A `multipart/*` body of JSON metadata and content.
+    Multipart(Field),
 }
 
 impl ResponseField {
@@ -238,7 +659,9 @@ impl ResponseField {
         match self {
             ResponseField::Body(field)
             | ResponseField::Header(field, _)
-            | ResponseField::NewtypeBody(field) => field,
+            | ResponseField::NewtypeBody(field)
+            | ResponseField::JsonStream(field)
+            | ResponseField::Multipart(field) => field,
         }
     }
 
@@ -249,10 +672,7 @@ impl ResponseField {
 
     /// Whether or not this response field is a header kind.
     fn is_header(&self) -> bool {
-        match self {
-            ResponseField::Header(..) => true,
-            _ => false,
-        }
+        matches!(self, ResponseField::Header(..))
     }
 
     /// Return the contained field if this response field is a body kind.
@@ -270,6 +690,22 @@ impl ResponseField {
             _ => None,
         }
     }
+
+    /// Return the contained field if this response field is a JSON stream kind.
+    fn as_json_stream_field(&self) -> Option<&Field> {
+        match self {
+            ResponseField::JsonStream(field) => Some(field),
+            _ => None,
+        }
+    }
+
+    /// Return the contained field if this response field is a multipart kind.
+    fn as_multipart_field(&self) -> Option<&Field> {
+        match self {
+            ResponseField::Multipart(field) => Some(field),
+            _ => None,
+        }
+    }
 }
 
 /// The types of fields that a response can have, without their values.
@@ -280,4 +716,8 @@ enum ResponseFieldKind {
     Header,
     /// See the similarly named variant of `ResponseField`.
     NewtypeBody,
+    /// See the similarly named variant of `ResponseField`.
+    JsonStream,
+    /// See the similarly named variant of `ResponseField`.
+    Multipart,
 }
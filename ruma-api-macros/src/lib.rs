@@ -9,8 +9,6 @@
     // missing_docs, # Uncomment when https://github.com/rust-lang/rust/pull/60562 is released.
 )]
 #![allow(clippy::cognitive_complexity)]
-// Since we support Rust 1.34.2, we can't apply this suggestion yet
-#![allow(clippy::use_self)]
 #![recursion_limit = "256"]
 
 extern crate proc_macro;
@@ -30,6 +28,9 @@ mod api;
 ///
 /// ```ignore
 /// ruma_api! {
+///     // Optional, added to all generated items.
+///     #[allow(clippy::exhaustive_structs)]
+///
 ///     metadata {
 ///         description: &'static str
 ///         method: http::Method,
@@ -37,6 +38,24 @@ mod api;
 ///         path: &'static str,
 ///         rate_limited: bool,
 ///         requires_authentication: bool,
+///         // Optional, defaults to `AuthorizationHeader`.
+///         access_token_placement: ruma_api::AccessTokenPlacement,
+///         // Optional, defaults to `false`.
+///         compress_request_body: bool,
+///         // Optional, defaults to `false`.
+///         empty_object_body: bool,
+///         // Optional, defaults to `false`.
+///         patch: bool,
+///         // Optional, no unstable feature by default.
+///         unstable_feature: "org.matrix.msc3575",
+///         // Optional, no validator by default.
+///         validate_response: path::to::validator,
+///         // Optional, no error type by default.
+///         error_ty: path::to::ErrorType,
+///         // Optional, defaults to `false`.
+///         uiaa: bool,
+///         // Optional, no configuration predicate by default.
+///         cfg: feature = "unstable-foo",
 ///     }
 ///
 ///     request {
@@ -48,6 +67,22 @@ mod api;
 ///         // Struct fields for each piece of data expected
 ///         // in the response from this API endpoint.
 ///     }
+///
+///     // Optional.
+///     examples {
+///         request: r#"{ "foo": "bar" }"#,
+///         response: r#"{ "baz": 1 }"#,
+///     }
+///
+///     // Optional.
+///     compat super::v1 {
+///         request {
+///             // Mappings from the previous version's request.
+///         }
+///         response {
+///             // Mappings to the previous version's response.
+///         }
+///     }
 /// }
 /// ```
 ///
@@ -56,7 +91,45 @@ mod api;
 /// implementations to convert the request into a `http::Request` and to create a response from a
 /// `http::Response` and vice versa.
 ///
-/// The details of each of the three sections of the macros are documented below.
+/// `Request` implements `ruma_api::OutgoingRequest` for clients, and `ruma_api::IncomingRequest`
+/// for servers, which converts it from an `http::Request` and the response to an
+/// `http::Response`. `IncomingRequest` isn't implemented if the request has a `manual_body`
+/// field or the response has a `manual_body`, `json_stream` or `multipart` field, as those are
+/// only converted in one direction. Otherwise, all body types have to implement both
+/// `Serialize` and `Deserialize`. Path fields with `encode = none` can't contain `/` when they are
+/// received. Servers that add headers of their own, e.g. for CORS or caching, can convert the
+/// response with `Response::try_into_http_response_with`, which adds the given headers unless the
+/// response already sets them.
+///
+/// The client half of the conversions is only generated with the `client` feature of ruma-api,
+/// and the server half only with its `server` feature. Both are enabled by default; crates that
+/// are only a client or only a server can disable the other one to save compile time. Without
+/// one of the features, the hidden body types also don't derive the traits only it needs.
+///
+/// The request and response can be converted from an `http::Request<B>` and `http::Response<B>`
+/// with any body type `B: AsRef<[u8]>`, e.g. `Vec<u8>`, `&[u8]` or `bytes::Bytes`. If a `raw_body`
+/// or `json_stream` field takes the body as it is, `B` also has to implement `Into<Vec<u8>>`.
+///
+/// With the `bytes-bodies` feature of ruma-api, the request can also be converted into an
+/// `http::Request<bytes::Bytes>` and the response into an `http::Response<bytes::Bytes>`. The
+/// serialized body is moved into the `Bytes` without being copied.
+///
+/// With the `path-round-trip-tests` feature of ruma-api, every endpoint with path fields also
+/// gets a `#[cfg(test)]` test per path field that isn't `verbatim`. It fills the field with
+/// sample values that are valid for its type, including reserved characters and Matrix
+/// identifiers, and checks that they come out of the incoming request path like they went into
+/// the URL built for `METADATA.path`. The feature is meant for the tests of API crates, e.g.
+/// `cargo test --features ruma-api/path-round-trip-tests`.
+///
+/// `Request` and `Response` also get a `metadata` method returning a `&'static Metadata`, so the
+/// metadata can be reached from values in generic code and trait objects.
+///
+/// Apart from `Request` and `Response`, all generated items are placed in a private module named
+/// `__ruma_api`, so they don't collide with other items in the module the macro is used in. The
+/// private module glob-imports its parent module, so types used in field definitions and paths in
+/// `#[serde(...)]` attributes resolve as usual, except for paths starting with `super::`.
+///
+/// The details of each of the sections of the macros are documented below.
 ///
 /// ## Metadata
 ///
@@ -71,8 +144,58 @@ mod api;
 ///     identifier prefixed with a colon, e.g. `/foo/:some_parameter`.
 ///     A corresponding query string parameter will be expected in the request struct (see below
 ///     for details).
+///     The path is checked at compile time: it has to start with `/`, can't contain empty segments
+///     or characters that need percent-encoding, and every parameter has to occur only once and
+///     match a `#[ruma_api(path)]` field of the request.
 /// *   `rate_limited`: Whether or not the endpoint enforces rate limiting on requests.
 /// *   `requires_authentication`: Whether or not the endpoint requires a valid access token.
+/// *   `access_token_placement` (optional): Where the access token is sent, either
+///     `AuthorizationHeader` (the default) or `QueryString` for endpoints that only accept the
+///     `access_token` query parameter.
+/// *   `compress_request_body` (optional): Whether the request body is compressed with gzip and sent
+///     with a `Content-Encoding: gzip` header. Requests without body fields are never compressed.
+///     Setting this to `true` requires the `gzip` feature of ruma-api.
+/// *   `empty_object_body` (optional): Whether requests without body fields are sent with `{}` as
+///     their body instead of an empty one, for servers that insist on a JSON body, e.g. for
+///     `DELETE` requests. Either is accepted as the body of a response.
+/// *   `patch` (optional): Whether the endpoint is a partial update. If `true`, the type of every
+///     request body field changes from `T` to `ruma_api::Maybe<T>`, which can be absent (left out
+///     of the body), `Null` or a value, and defaults to absent. Flattened fields are left as they
+///     are.
+/// *   `history_paths` (optional): An array of earlier paths of the endpoint, e.g. from older
+///     versions of the specification, which servers still accept for incoming requests. They
+///     are checked like `path` and must have the same path parameters. Clients always send
+///     requests to `path`. The paths are available as `Metadata::history_paths`.
+/// *   `unstable_feature` (optional): The identifier of the Matrix spec change (MSC) this endpoint
+///     is an unstable implementation of. The version segment of the path is replaced with
+///     `unstable/<feature>`, so `path: "/_matrix/client/r0/sync"` becomes
+///     `/_matrix/client/unstable/org.matrix.msc3575/sync`, and the identifier is available as
+///     `Metadata::unstable_feature`.
+/// *   `validate_response` (optional): The path of a function with the signature
+///     `fn(&Response) -> Result<(), E>` for some `E: Display`, which is called after a successful
+///     response has been converted, to check invariants involving several fields. If it returns
+///     an error, converting the response fails with an error for which
+///     `FromHttpResponseError::is_validation_error` is true.
+/// *   `error_ty` (optional): The path of a type implementing `Deserialize`, `Debug`, `Send` and
+///     `Sync`, e.g. an error body specific to the endpoint. When a response has an unsuccessful
+///     status code, its body is deserialized as this type and can be retrieved from the error
+///     with `FromHttpResponseError::error_body`. If the body can't be deserialized, the error
+///     only carries the status code. Generic types have to be written with a turbofish, e.g.
+///     `ErrorBody::<Extra>`.
+/// *   `uiaa` (optional): Whether the endpoint uses user-interactive authentication. Defaults to
+///     `false`. If `true`, a `401 Unauthorized` response whose body lists the authentication
+///     flows is converted into an error for which `FromHttpResponseError::uiaa_info` returns the
+///     parsed `ruma_api::UiaaInfo`, instead of an error with just the status code.
+/// *   `cfg` (optional): A configuration predicate, as it would be written inside `#[cfg(...)]`.
+///     All items generated for the endpoint are only compiled if it holds, e.g.
+///     `cfg: feature = "unstable-foo"` or `cfg: all(feature = "unstable-foo", not(test))`.
+///
+/// ## Attributes
+///
+/// Outer attributes written before the `metadata` section are added to every item the macro
+/// generates, e.g. `#[allow(clippy::exhaustive_structs)]` for crates with strict lint
+/// configurations, or `#[doc(hidden)]`. Since the generated items include `impl` blocks, only
+/// attributes that are valid on structs, enums, modules and `impl` blocks can be used.
 ///
 /// ## Request
 ///
@@ -85,16 +208,36 @@ mod api;
 ///     headers on the request.
 ///     The value must implement `AsRef<str>`.
 ///     Generally this is a `String`.
+///     If the field is an `Option`, the header is only sent when it is `Some`.
 ///     The attribute value shown above as `HEADER_NAME` must be a header name constant from
 ///     `http::header`, e.g. `CONTENT_TYPE`.
+///     Each header name can only be used by one field, so every header has a single value.
+///     Header fields are added to the `http::Request` in the order they are declared in, followed
+///     by the default headers of the global `ruma_api::Config` that aren't set by a field, and
+///     finally `Content-Encoding` and `Content-Length` where applicable. This order is
+///     deterministic, so requests can be signed and compared against fixtures.
 /// *   `#[ruma_api(path)]`: Fields with this attribute will be inserted into the matching path
 ///     component of the request URL.
+///     The value must implement `Display` and is percent-encoded, so identifier types from
+///     ruma-identifiers like `RoomAliasId` or `UserId` can be used directly.
+///     The encoding can be changed with `#[ruma_api(path, encode = component)]`, which encodes
+///     all characters except for unreserved ones (letters, digits, `-`, `.`, `_` and `~`), or
+///     `#[ruma_api(path, encode = none)]`, which inserts the value as-is for values that are
+///     already percent-encoded. Note that with `encode = none`, a `/` in the value ends up as a
+///     path separator.
 /// *   `#[ruma_api(query)]`: Fields with this attribute will be inserting into the URL's query
 ///     string.
+///     The value must implement `Serialize`, which includes the ruma-identifiers types.
+///     The names of these parameters are available as constants in a generated `query_params`
+///     module, e.g. `query_params::LIMIT` for a field `limit`.
 /// *   `#[ruma_api(query_map)]`: Instead of individual query fields, one query_map field, of any
 ///     type that implements `IntoIterator<Item = (String, String)>` (e.g.
 ///     `HashMap<String, String>`, can be used for cases where an endpoint supports arbitrary query
 ///     parameters.
+/// *   `#[ruma_api(request_id)]`: A header field for the `X-Request-Id` header
+///     (`ruma_api::REQUEST_ID_HEADER`), which correlates a request with its response in the logs
+///     of clients, servers and proxies. If the field is an `Option<String>` that is `None`, a new
+///     ID is generated.
 ///
 /// Any field that does not include one of these attributes will be part of the request's JSON
 /// body.
@@ -103,18 +246,131 @@ mod api;
 ///
 /// Like the request block, the response block consists of normal struct field definitions.
 /// Doc comments and attributes are allowed as normal.
-/// There is also a special attribute available to control how the struct is created from a
+/// There are also a few special attributes available to control how the struct is created from a
 /// `http::Request`:
 ///
 /// *   `#[ruma_api(header = HEADER_NAME)]`: Fields with this attribute will be treated as HTTP
 ///     headers on the response.
 ///     The value must implement `AsRef<str>`.
 ///     Generally this is a `String`.
+///     If the field is an `Option<String>`, a missing header is `None` instead of a panic.
+///     If the field is a `Vec<String>`, it receives all values of the header, like the cookies
+///     of `Set-Cookie`, in the order they were received in.
+///     When a response is converted into an `http::Response`, header fields are added in the
+///     order they are declared in, with all values of a `Vec` field appended in order, replacing
+///     a header that comes with the body, like its `Content-Type`.
 ///     The attribute value shown above as `HEADER_NAME` must be a header name constant from
 ///     `http::header`, e.g. `CONTENT_TYPE`.
+///     The `Content-Type` of a response is never checked before its body is parsed as JSON, so an
+///     optional `CONTENT_TYPE` header field can be used to notice servers sending JSON with a
+///     wrong or missing content type.
+/// *   `#[ruma_api(header = HEADER_NAME, from = "function")]`: A header field whose value is
+///     computed from the rest of the response when it is converted into an `http::Response`, e.g.
+///     an `ETag` derived from body fields. `function` is the name of a function in scope with the
+///     signature `fn(&Response) -> T` for the field type `T`, and its result replaces the value
+///     the field was given. A received response reads the field from its header as usual.
+///
+/// *   `#[ruma_api(json_stream)]`: A field with this attribute, of type `ruma_api::JsonStream<T>`,
+///     receives the entire response body as a stream of JSON values of type `T`, either
+///     newline-delimited or as a top-level JSON array. The values are only deserialized when
+///     iterating over the stream, so very large lists don't have to be held in memory all at once.
+///     It is an error to have a JSON stream field and body fields within the same struct.
+/// *   `#[ruma_api(multipart)]`: A field with this attribute, of type `ruma_api::Multipart<T>`,
+///     receives a `multipart/*` response body, like those of federation media endpoints, whose
+///     first part is JSON metadata of type `T` and whose second part is the content, along with
+///     its headers. It is an error to have a multipart field and body fields within the same
+///     struct.
+/// *   `#[ruma_api(iter)]`: A regular body field of type `Vec<T>`, which the response can be used
+///     as directly: `Response` implements `Deref<Target = [T]>` as well as `IntoIterator` by value
+///     and by reference, for iterating over list results without naming the field.
+///     There can only be one such field.
+/// *   `#[ruma_api(request_id)]`: A header field for the `X-Request-Id` header the server
+///     responded with, usually an `Option<String>`.
+/// *   `#[ruma_api(validate = validator)]`: Can be added to any field in addition to the above.
+///     `validator` is the name of a function in scope with the signature
+///     `fn(&T) -> Result<(), E>` for the field type `T` and some `E: Display`, which is called
+///     once the response has been converted. If it returns an error, converting the response
+///     fails, like with `validate_response`.
+///
+/// Any field that does not include one of these attributes will be expected in the response's
+/// JSON body.
+///
+/// If the response consists of a single body field, `Response::into_inner` and a `From<Response>`
+/// implementation for the type of that field are generated as well.
+///
+/// ## Examples section
+///
+/// The optional `examples` section contains example bodies for `request` and / or `response`, as
+/// string literals containing JSON. They are shown in the documentation of `Request` and
+/// `Response`, and are available as `Endpoint::EXAMPLE_REQUEST_BODY` and
+/// `Endpoint::EXAMPLE_RESPONSE_BODY`, e.g. as default responses of mock servers.
+///
+/// Examples are checked at compile time: they must be valid JSON, and for bodies made of regular
+/// body fields, an object containing every field that isn't an `Option` or `#[serde(default)]`,
+/// and no fields that don't exist (unless a field is flattened). The types of the values can't be
+/// checked by the macro; deserializing `EXAMPLE_RESPONSE_BODY` in a test covers them.
+///
+/// ## Compat section
+///
+/// The optional `compat` section generates conversions from the previous version of the endpoint,
+/// given as the path of the module it was defined in, e.g. for servers that support both versions
+/// of an endpoint with a single implementation:
+///
+/// *   `request` generates `From<previous::Request> for Request`. Each mapping assigns a field of
+///     `Request` from an expression that can use the previous request as `old`, e.g.
+///     `user_id: old.user`. Fields without a mapping are moved from the field of the same name.
+/// *   `response` generates `From<Response> for previous::Response`. Since the macro doesn't know
+///     the fields of the previous response, it needs a mapping for each of them, which can use the
+///     response as `new`. A field name on its own, e.g. `banned`, is short for `banned: new.banned`.
+///
+/// ## Serde attributes
+///
+/// `#[serde(...)]` attributes on fields are not applied to the generated `Request` and `Response`
+/// structs, but they are carried over to the structs that are used to (de)serialize the body and
+/// query string. This includes newtype body fields, so e.g.
+/// `#[serde(skip_serializing_if = "Option::is_none")]` or `#[serde(with = "...")]` work as usual.
+///
+/// ## Flattened fields
+///
+/// Body fields whose members are shared between several endpoints, like pagination parameters, can
+/// be defined once as an ordinary struct implementing `Serialize` and/or `Deserialize` and used in
+/// a field with the `#[ruma_api(body, flatten)]` attribute. The members of such a field are
+/// flattened into the top-level JSON object of the body, next to the other body fields. This is
+/// equivalent to a body field with `#[serde(flatten)]`.
+///
+/// The same works for query parameters with `#[ruma_api(query, flatten)]`, so parameters like
+/// `from`, `to` and `limit` don't have to be repeated in every endpoint that takes them. The type
+/// of such a field has to implement both `Serialize` and `Deserialize`.
+///
+/// When parsing incoming requests, serde hands the members of a flattened query field to its
+/// `Deserialize` implementation as strings, which fails for numbers and booleans. Members that
+/// aren't strings need `#[serde(with = "ruma_api::serde::number_or_string")]`, or
+/// `ruma_api::serde::opt_number_or_string` along with `default` for `Option`s:
 ///
-/// Any field that does not include the above attribute will be expected in the response's JSON
-/// body.
+/// ```ignore
+/// #[derive(Deserialize, Serialize)]
+/// pub struct PaginationParams {
+///     #[serde(skip_serializing_if = "Option::is_none")]
+///     pub from: Option<String>,
+///     #[serde(
+///         default,
+///         skip_serializing_if = "Option::is_none",
+///         with = "ruma_api::serde::opt_number_or_string"
+///     )]
+///     pub limit: Option<u32>,
+/// }
+/// ```
+///
+/// ## Nullable body fields
+///
+/// Body fields of type `Option<Option<T>>` distinguish between the field being absent from the
+/// JSON body (`None`), being explicitly set to `null` (`Some(None)`) and having a value
+/// (`Some(Some(value))`). This is needed for endpoints like `PUT` requests that clear a value by
+/// setting it to `null`. The macro adds the necessary serde attributes automatically, unless the
+/// field already has `#[serde(...)]` attributes of its own.
+///
+/// For endpoints where every body field works like this, `patch: true` in the metadata wraps all
+/// of them in `ruma_api::Maybe<T>` instead, which names the three states.
 ///
 /// ## Newtype bodies
 ///
@@ -125,6 +381,28 @@ mod api;
 /// each struct can be marked with this attribute. It is an error to have a newtype body field and
 /// normal body fields within the same struct.
 ///
+/// For bodies that aren't JSON or can't be expressed with serde, `#[ruma_api(manual_body = f)]`
+/// can be used instead of `#[ruma_api(body)]`. The macro then still handles the path, query string,
+/// headers and metadata, but the body is converted by `f`, the name of a function in scope:
+/// `fn(&T) -> Result<Vec<u8>, E>` for requests and `fn(&[u8]) -> Result<T, E>` for responses, for
+/// the field type `T` and any error type `E` that converts into
+/// `Box<dyn std::error::Error + Send + Sync>`, e.g. `String`.
+///
+/// Binary request bodies, like the file of a media upload, use `#[ruma_api(raw_body)]` on a field
+/// of type `Vec<u8>`, which becomes the body of the `http::Request` as-is. Since the body isn't
+/// JSON, the request must also have a `#[ruma_api(header = CONTENT_TYPE)]` field. Likewise, a
+/// `#[ruma_api(raw_body)]` field of type `Vec<u8>` in a response, like the file of a media
+/// download, receives the body of the `http::Response` without parsing it.
+///
+/// If the request consists of nothing but the body, the field can also be left unnamed, as in
+/// `request { #[ruma_api(body)] pub Vec<String> }`. `Request` is then generated as a tuple struct,
+/// `pub struct Request(pub Vec<String>);`.
+///
+/// Bodies that can take one of several shapes, like the different identifier types accepted by
+/// `/login`, are declared as a newtype body field whose type is an enum deriving serde's traits.
+/// The enum's own serde attributes (e.g. `#[serde(tag = "type")]`) determine its representation,
+/// and deserializing an unknown variant fails with an error listing the expected variants.
+///
 /// # Examples
 ///
 /// ```rust,ignore
@@ -0,0 +1,198 @@
+//! A macro for routing incoming requests to a group of endpoints.
+
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+};
+
+use http::Method;
+
+use crate::FromHttpRequestError;
+
+/// Generates an enum with one variant per endpoint, holding the endpoint's request, along with a
+/// `route` function converting an incoming `http::Request` into the variant of the endpoint it is
+/// for.
+///
+/// Each variant is followed by the request type of its endpoint in parentheses, which has to
+/// implement `IncomingRequest`. Matching on the enum in a request handler makes sure at compile
+/// time that every endpoint of the group is handled. The enum derives `Debug` and gets these
+/// items:
+///
+/// * `route(http::Request<Vec<u8>>) -> Result<Self, RouteError>`, which picks the first endpoint
+///   whose path matches the request (see `Metadata::matches_path`) and whose method is the
+///   request's, and converts the request,
+/// * `metadata(&self) -> &'static Metadata`, the metadata of the endpoint of the request.
+///
+/// ```
+/// # mod create_alias {
+/// #     ruma_api::ruma_api! {
+/// #         metadata {
+/// #             description: "Add an alias to a room.",
+/// #             method: PUT,
+/// #             name: "create_alias",
+/// #             path: "/_matrix/client/r0/directory/room/:room_alias",
+/// #             rate_limited: false,
+/// #             requires_authentication: true,
+/// #         }
+/// #         request {
+/// #             #[ruma_api(path)]
+/// #             pub room_alias: String,
+/// #         }
+/// #         response {}
+/// #     }
+/// # }
+/// # mod delete_alias {
+/// #     ruma_api::ruma_api! {
+/// #         metadata {
+/// #             description: "Remove an alias from a room.",
+/// #             method: DELETE,
+/// #             name: "delete_alias",
+/// #             path: "/_matrix/client/r0/directory/room/:room_alias",
+/// #             rate_limited: false,
+/// #             requires_authentication: true,
+/// #         }
+/// #         request {
+/// #             #[ruma_api(path)]
+/// #             pub room_alias: String,
+/// #         }
+/// #         response {}
+/// #     }
+/// # }
+/// use ruma_api::RouteError;
+///
+/// ruma_api::any_incoming_request! {
+///     /// A request to one of the endpoints of the room directory.
+///     pub enum AnyDirectoryRequest {
+///         /// Adds an alias to a room.
+///         CreateAlias(create_alias::Request),
+///         /// Removes an alias from a room.
+///         DeleteAlias(delete_alias::Request),
+///     }
+/// }
+///
+/// let http_request = http::Request::builder()
+///     .method("DELETE")
+///     .uri("/_matrix/client/r0/directory/room/%23room:example.org")
+///     .body(Vec::new())?;
+/// match AnyDirectoryRequest::route(http_request)? {
+///     AnyDirectoryRequest::CreateAlias(_) => unreachable!(),
+///     AnyDirectoryRequest::DeleteAlias(request) => {
+///         assert_eq!(request.room_alias, "#room:example.org");
+///     }
+/// }
+///
+/// let http_request = http::Request::builder()
+///     .method("GET")
+///     .uri("/_matrix/client/r0/directory/room/%23room:example.org")
+///     .body(Vec::new())?;
+/// match AnyDirectoryRequest::route(http_request) {
+///     Err(RouteError::MethodNotAllowed(methods)) => {
+///         assert_eq!(methods, [http::Method::PUT, http::Method::DELETE]);
+///     }
+///     _ => unreachable!(),
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[macro_export]
+macro_rules! any_incoming_request {
+    (
+        $(#[$attr:meta])*
+        $vis:vis enum $name:ident {
+            $(
+                $(#[$variant_attr:meta])*
+                $variant:ident($request:ty)
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug)]
+        $vis enum $name {
+            $(
+                $(#[$variant_attr])*
+                $variant($request),
+            )*
+        }
+
+        impl $name {
+            /// Converts an incoming request into the request of the endpoint it is for.
+            pub fn route(
+                request: $crate::exports::http::Request<Vec<u8>>,
+            ) -> Result<Self, $crate::RouteError> {
+                let mut allowed_methods = Vec::new();
+
+                $(
+                    let metadata = &<$request as $crate::Endpoint>::METADATA;
+                    if metadata.matches_path(request.uri().path()) {
+                        if *request.method() == metadata.method {
+                            return <$request as std::convert::TryFrom<_>>::try_from(request)
+                                .map($name::$variant)
+                                .map_err($crate::RouteError::Request);
+                        }
+
+                        allowed_methods.push(metadata.method.clone());
+                    }
+                )*
+
+                if allowed_methods.is_empty() {
+                    Err($crate::RouteError::NotFound)
+                } else {
+                    Err($crate::RouteError::MethodNotAllowed(allowed_methods))
+                }
+            }
+
+            /// The metadata of the endpoint of the request.
+            pub fn metadata(&self) -> &'static $crate::Metadata {
+                match self {
+                    $(
+                        $name::$variant(_) => {
+                            static METADATA: $crate::Metadata =
+                                <$request as $crate::Endpoint>::METADATA;
+                            &METADATA
+                        }
+                    )*
+                }
+            }
+        }
+    };
+}
+
+/// An error routing an incoming request with a function generated by `any_incoming_request!`.
+#[derive(Debug)]
+pub enum RouteError {
+    /// No endpoint has the path of the request, usually answered with `404 Not Found`.
+    NotFound,
+
+    /// Endpoints have the path of the request, but with other methods, which are listed in the
+    /// order the endpoints were declared in, e.g. for the `Allow` header of a
+    /// `405 Method Not Allowed` response.
+    MethodNotAllowed(Vec<Method>),
+
+    /// The request is for an endpoint, but couldn't be converted into its request type.
+    Request(FromHttpRequestError),
+}
+
+impl Display for RouteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::NotFound => f.write_str("No endpoint matches the path of the request."),
+            RouteError::MethodNotAllowed(methods) => {
+                let methods: Vec<_> = methods.iter().map(Method::as_str).collect();
+                write!(
+                    f,
+                    "The endpoints matching the request path only allow {}.",
+                    methods.join(", ")
+                )
+            }
+            RouteError::Request(error) => Display::fmt(error, f),
+        }
+    }
+}
+
+impl StdError for RouteError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            RouteError::Request(error) => Some(error),
+            _ => None,
+        }
+    }
+}
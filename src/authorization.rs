@@ -0,0 +1,223 @@
+//! Typed credentials from the `Authorization` header or the `access_token` query parameter.
+
+use std::{
+    error::Error as StdError,
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+};
+
+use http::header::AUTHORIZATION;
+
+/// The credentials of a request, parsed from its `Authorization` header or its `access_token`
+/// query parameter.
+///
+/// Servers can use this to authenticate incoming `http::Request`s:
+///
+/// ```
+/// use ruma_api::Authorization;
+///
+/// let mut request = http::Request::new(Vec::<u8>::new());
+/// request.headers_mut().insert(http::header::AUTHORIZATION, "Bearer abc123".parse().unwrap());
+///
+/// assert_eq!(
+///     Authorization::from_request(&request).unwrap(),
+///     Some(Authorization::Bearer("abc123".to_owned())),
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Authorization {
+    /// An access token of a client, as sent to client-server endpoints.
+    Bearer(String),
+
+    /// The signature of a homeserver, as sent to server-server endpoints.
+    XMatrix {
+        /// The server name of the sending homeserver.
+        origin: String,
+
+        /// The ID of the key the request was signed with, e.g. `ed25519:1`.
+        key: String,
+
+        /// The unpadded base64 signature of the request.
+        sig: String,
+    },
+}
+
+impl Authorization {
+    /// Parses the `Authorization` header of a request.
+    ///
+    /// Without the header, the access token is taken from the `access_token` query parameter,
+    /// where endpoints with `AccessTokenPlacement::QueryString` receive it. Returns `Ok(None)` if
+    /// the request has neither.
+    pub fn from_request<T>(
+        request: &http::Request<T>,
+    ) -> Result<Option<Self>, InvalidAuthorization> {
+        match request.headers().get(AUTHORIZATION) {
+            Some(value) => value
+                .to_str()
+                .map_err(|_| InvalidAuthorization("the header value is not valid UTF-8"))?
+                .parse()
+                .map(Some),
+            None => access_token_from_query(request.uri().query().unwrap_or("")),
+        }
+    }
+}
+
+impl FromStr for Authorization {
+    type Err = InvalidAuthorization;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (scheme, credentials) = match s.find(' ') {
+            Some(index) => (&s[..index], s[index + 1..].trim()),
+            None => (s, ""),
+        };
+
+        if scheme.eq_ignore_ascii_case("Bearer") {
+            if credentials.is_empty() || credentials.contains(char::is_whitespace) {
+                return Err(InvalidAuthorization("the access token is missing or malformed"));
+            }
+
+            Ok(Authorization::Bearer(credentials.to_owned()))
+        } else if scheme.eq_ignore_ascii_case("X-Matrix") {
+            parse_x_matrix(credentials)
+        } else {
+            Err(InvalidAuthorization("the scheme is neither `Bearer` nor `X-Matrix`"))
+        }
+    }
+}
+
+impl Display for Authorization {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Authorization::Bearer(token) => write!(f, "Bearer {}", token),
+            Authorization::XMatrix { origin, key, sig } => {
+                write!(f, "X-Matrix origin={},key=\"{}\",sig=\"{}\"", origin, key, sig)
+            }
+        }
+    }
+}
+
+/// Takes an access token from the `access_token` parameter of a query string.
+fn access_token_from_query(query: &str) -> Result<Option<Authorization>, InvalidAuthorization> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_str(query)
+        .map_err(|_| InvalidAuthorization("the query string is malformed"))?;
+
+    match pairs.into_iter().find(|(name, _)| name == "access_token") {
+        Some((_, token)) if token.is_empty() || token.contains(char::is_whitespace) => {
+            Err(InvalidAuthorization("the access token is missing or malformed"))
+        }
+        Some((_, token)) => Ok(Some(Authorization::Bearer(token))),
+        None => Ok(None),
+    }
+}
+
+/// Parses the comma-separated parameters of `X-Matrix` credentials.
+///
+/// Values may be quoted, unknown parameters are ignored.
+fn parse_x_matrix(credentials: &str) -> Result<Authorization, InvalidAuthorization> {
+    let mut origin = None;
+    let mut key = None;
+    let mut sig = None;
+
+    for param in credentials.split(',') {
+        let mut parts = param.trim().splitn(2, '=');
+        let name = parts.next().unwrap_or_default().trim();
+        let value = parts
+            .next()
+            .ok_or(InvalidAuthorization("an `X-Matrix` parameter has no value"))?
+            .trim();
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            &value[1..value.len() - 1]
+        } else {
+            value
+        };
+
+        match name {
+            "origin" => origin = Some(value.to_owned()),
+            "key" => key = Some(value.to_owned()),
+            "sig" => sig = Some(value.to_owned()),
+            _ => {}
+        }
+    }
+
+    match (origin, key, sig) {
+        (Some(origin), Some(key), Some(sig)) => Ok(Authorization::XMatrix { origin, key, sig }),
+        _ => Err(InvalidAuthorization("`X-Matrix` credentials need `origin`, `key` and `sig`")),
+    }
+}
+
+/// An error when parsing malformed credentials from an `Authorization` header or an
+/// `access_token` query parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidAuthorization(&'static str);
+
+impl Display for InvalidAuthorization {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Invalid credentials: {}", self.0)
+    }
+}
+
+impl StdError for InvalidAuthorization {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Authorization, InvalidAuthorization};
+
+    fn from_request(
+        uri: &str,
+        authorization: Option<&str>,
+    ) -> Result<Option<Authorization>, InvalidAuthorization> {
+        let mut request = http::Request::builder();
+        request.uri(uri);
+        if let Some(authorization) = authorization {
+            request.header(http::header::AUTHORIZATION, authorization);
+        }
+        Authorization::from_request(&request.body(()).unwrap())
+    }
+
+    #[test]
+    fn access_token_query_parameter() {
+        let bearer = |token: &str| Ok(Some(Authorization::Bearer(token.to_owned())));
+
+        assert_eq!(from_request("/sync?since=s1&access_token=abc%2B123", None), bearer("abc+123"));
+        assert_eq!(from_request("/sync?access_token=abc", Some("Bearer def")), bearer("def"));
+        assert_eq!(from_request("/sync?since=s1", None), Ok(None));
+        assert_eq!(from_request("/sync", None), Ok(None));
+        assert!(from_request("/sync?access_token=", None).is_err());
+        assert!(from_request("/sync?access_token=abc+123", None).is_err());
+    }
+
+    #[test]
+    fn bearer() {
+        assert_eq!("Bearer abc123".parse(), Ok(Authorization::Bearer("abc123".to_owned())));
+        assert_eq!("bearer  abc123 ".parse(), Ok(Authorization::Bearer("abc123".to_owned())));
+        assert!("Bearer".parse::<Authorization>().is_err());
+        assert!("Bearer abc 123".parse::<Authorization>().is_err());
+        assert!("Basic dXNlcjpwYXNz".parse::<Authorization>().is_err());
+    }
+
+    #[test]
+    fn x_matrix() {
+        let authorization = Authorization::XMatrix {
+            origin: "origin.example".to_owned(),
+            key: "ed25519:1".to_owned(),
+            sig: "ABCDEF".to_owned(),
+        };
+
+        assert_eq!(
+            r#"X-Matrix origin=origin.example,key="ed25519:1",sig="ABCDEF""#.parse(),
+            Ok(authorization.clone())
+        );
+        assert_eq!(
+            r#"X-Matrix sig=ABCDEF, key="ed25519:1", destination="x", origin="origin.example""#
+                .parse(),
+            Ok(authorization.clone())
+        );
+        assert_eq!(authorization.to_string().parse(), Ok(authorization));
+
+        assert!("X-Matrix origin=origin.example,key=\"ed25519:1\""
+            .parse::<Authorization>()
+            .is_err());
+        assert!("X-Matrix origin".parse::<Authorization>().is_err());
+    }
+}
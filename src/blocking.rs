@@ -0,0 +1,769 @@
+//! A synchronous way of sending requests to an endpoint, for programs that don't want to pull in
+//! an async runtime.
+//!
+//! This module is only available with the `blocking` feature.
+
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    env,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    io::Read,
+    sync::{mpsc, Arc, Condvar, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use http::StatusCode;
+
+use crate::{
+    encoding, error::InnerError, AccessTokenPlacement, Config, Error, Metadata, OutgoingRequest,
+    RequestSigner, REQUEST_ID_HEADER,
+};
+
+/// Sending requests using a synchronous HTTP client.
+///
+/// This trait is implemented for all `OutgoingRequest`s.
+pub trait SendBlocking: OutgoingRequest {
+    /// Sends the request to the homeserver at `base_url` and waits for the response.
+    ///
+    /// If the endpoint requires authentication, `access_token` is sent in the `Authorization`
+    /// header or the query string, depending on the endpoint's `access_token_placement`.
+    ///
+    /// Responses with an unsuccessful status code are converted like any other, so they result in
+    /// the same error that converting the `http::Response` would.
+    ///
+    /// The response body is limited to the `max_response_body_size` of the global `Config`, if
+    /// any.
+    fn send_blocking(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+    ) -> Result<Self::IncomingResponse, Error>;
+
+    /// Like `send_blocking`, but fails without reading further if the response body is larger
+    /// than `max_body_size` bytes.
+    ///
+    /// This protects programs with limited memory from servers that return unexpectedly large or
+    /// unbounded bodies.
+    fn send_blocking_with_limit(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+        max_body_size: usize,
+    ) -> Result<Self::IncomingResponse, Error>;
+
+    /// Like `send_blocking`, but lets `signer` sign the request before it is sent.
+    fn send_blocking_signed(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+        signer: &dyn RequestSigner,
+    ) -> Result<Self::IncomingResponse, Error>;
+
+    /// Like `send_blocking`, but takes the access token from `tokens`.
+    ///
+    /// If the server rejects the access token with an `M_UNKNOWN_TOKEN` error, the token is
+    /// refreshed and the request is sent once more with the new token. Other `401 Unauthorized`
+    /// responses, such as a request for user-interactive authentication, are returned as they are.
+    fn send_blocking_with_token_provider(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        tokens: &dyn TokenProvider,
+    ) -> Result<Self::IncomingResponse, Error>;
+
+    /// Like `send_blocking`, but reports the size of the request and response bodies to
+    /// `observer` once the response has been received.
+    fn send_blocking_observed(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+        observer: &dyn BodySizeObserver,
+    ) -> Result<Self::IncomingResponse, Error>;
+}
+
+impl<E: OutgoingRequest> SendBlocking for E {
+    fn send_blocking(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+    ) -> Result<Self::IncomingResponse, Error> {
+        send(self, client, base_url, access_token, None)
+    }
+
+    fn send_blocking_with_limit(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+        max_body_size: usize,
+    ) -> Result<Self::IncomingResponse, Error> {
+        send(self, client, base_url, access_token, Some(max_body_size))
+    }
+
+    fn send_blocking_signed(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+        signer: &dyn RequestSigner,
+    ) -> Result<Self::IncomingResponse, Error> {
+        let http_request = self.try_into_signed_http_request(signer)?;
+        send_http::<Self>(http_request, client, base_url, access_token, None)
+    }
+
+    fn send_blocking_with_token_provider(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        tokens: &dyn TokenProvider,
+    ) -> Result<Self::IncomingResponse, Error> {
+        let http_request: http::Request<Vec<u8>> = self.try_into()?;
+        if !Self::METADATA.requires_authentication {
+            return send_http::<Self>(http_request, client, base_url, None, None);
+        }
+
+        let retry_request = clone_http_request(&http_request);
+        let access_token = tokens.access_token();
+        match send_http::<Self>(http_request, client, base_url, access_token.as_deref(), None) {
+            Err(error) if is_unknown_token(&error) => {
+                match tokens.refresh(access_token.as_deref()) {
+                    Some(access_token) => send_http::<Self>(
+                        retry_request,
+                        client,
+                        base_url,
+                        Some(&access_token),
+                        None,
+                    ),
+                    None => Err(error),
+                }
+            }
+            result => result,
+        }
+    }
+
+    fn send_blocking_observed(
+        self,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+        observer: &dyn BodySizeObserver,
+    ) -> Result<Self::IncomingResponse, Error> {
+        let http_request: http::Request<Vec<u8>> = self.try_into()?;
+        let request_body_size = http_request.body().len();
+
+        let http_response = fetch::<Self>(http_request, client, base_url, access_token, None)?;
+        observer.observe(&Self::METADATA, request_body_size, http_response.body().len());
+
+        Ok(Self::IncomingResponse::try_from(http_response)?)
+    }
+}
+
+/// Whether the server rejected the access token, as opposed to asking for user-interactive
+/// authentication or denying access for some other reason.
+fn is_unknown_token(error: &Error) -> bool {
+    error.uiaa_info().is_none()
+        && error.matrix_error().map_or(false, |error| error.errcode == "M_UNKNOWN_TOKEN")
+}
+
+/// A source of access tokens for `SendBlocking::send_blocking_with_token_provider`, so token
+/// rotation and refresh logic lives in one place instead of at every call site.
+///
+/// Refreshing happens on the calling thread, blocking the request until `refresh` returns.
+pub trait TokenProvider {
+    /// The access token to send with the next request, if there is one.
+    fn access_token(&self) -> Option<String>;
+
+    /// Called when the server rejected the access token `rejected` with an `M_UNKNOWN_TOKEN`
+    /// error.
+    ///
+    /// Returns the new access token to retry the request with, or `None` if the request should
+    /// fail instead.
+    fn refresh(&self, rejected: Option<&str>) -> Option<String>;
+}
+
+/// Receives the size of the request and response bodies of requests sent with
+/// `SendBlocking::send_blocking_observed`, e.g. to enforce bandwidth quotas per endpoint.
+pub trait BodySizeObserver {
+    /// Called with the size in bytes of the request body as sent and of the response body as
+    /// received, before the response is converted.
+    fn observe(&self, metadata: &Metadata, request_body_size: usize, response_body_size: usize);
+}
+
+/// Sends all `requests` to the homeserver at `base_url` and returns the results in the same order.
+///
+/// At most `max_concurrency` requests are in flight at the same time, each sent from its own
+/// thread. All of them go through clones of `client`, so they share its connection pool. A
+/// `max_concurrency` of zero is treated as one.
+///
+/// This is useful for sending many requests of the same kind, such as looking up the profiles of
+/// all members of a room.
+pub fn send_all_blocking<E, I>(
+    client: &ureq::Agent,
+    base_url: &str,
+    access_token: Option<&str>,
+    requests: I,
+    max_concurrency: usize,
+) -> Vec<Result<E::IncomingResponse, Error>>
+where
+    I: IntoIterator<Item = E>,
+    E: OutgoingRequest + Send + 'static,
+    E::IncomingResponse: Send + 'static,
+{
+    let queue: VecDeque<_> = requests.into_iter().enumerate().collect();
+    let request_count = queue.len();
+    let queue = Arc::new(Mutex::new(queue));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers: Vec<_> = (0..max_concurrency.max(1).min(request_count))
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let sender = sender.clone();
+            let client = client.clone();
+            let base_url = base_url.to_owned();
+            let access_token = access_token.map(ToOwned::to_owned);
+
+            thread::spawn(move || loop {
+                let next = queue.lock().expect("request queue poisoned").pop_front();
+                let (index, request) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+
+                let result = request.send_blocking(&client, &base_url, access_token.as_deref());
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut results: Vec<_> = (0..request_count).map(|_| None).collect();
+    for (index, result) in receiver {
+        results[index] = Some(result);
+    }
+
+    for worker in workers {
+        if let Err(panic) = worker.join() {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    results.into_iter().map(|result| result.expect("missing result for request")).collect()
+}
+
+/// Stops sending requests to endpoints that keep failing, so a homeserver that is down isn't
+/// flooded with requests.
+///
+/// Endpoints are tracked separately, by `Metadata::name`. Once `failure_threshold` consecutive
+/// requests to an endpoint have failed, its circuit opens: further requests fail right away
+/// without being sent. After `open_duration`, requests are let through again, and the first
+/// success closes the circuit while another failure opens it for another `open_duration`.
+///
+/// Only errors that indicate a problem with the server count as failures, i.e. errors sending the
+/// request and responses with a 5xx status code.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// The number of consecutive failures after which a circuit opens.
+    failure_threshold: u32,
+    /// How long a circuit stays open.
+    open_duration: Duration,
+    /// The state of the circuit of each endpoint that has been used.
+    circuits: Mutex<HashMap<&'static str, Circuit>>,
+}
+
+/// The state of the circuit of a single endpoint.
+#[derive(Debug, Default)]
+struct Circuit {
+    /// The number of consecutive failed requests.
+    failures: u32,
+    /// When the circuit was last opened.
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Creates a new `CircuitBreaker` with all circuits closed.
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self { failure_threshold, open_duration, circuits: Mutex::new(HashMap::new()) }
+    }
+
+    /// Sends the request like `SendBlocking::send_blocking`, unless the endpoint's circuit is
+    /// open.
+    pub fn send_blocking<E: OutgoingRequest>(
+        &self,
+        request: E,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+    ) -> Result<E::IncomingResponse, Error> {
+        let name = E::METADATA.name;
+
+        if self.is_open(name) {
+            return Err(Error(InnerError::CircuitOpen(name)));
+        }
+
+        let result = request.send_blocking(client, base_url, access_token);
+
+        let mut circuits = self.circuits.lock().expect("circuit breaker state poisoned");
+        let circuit = circuits.entry(name).or_default();
+        if result.as_ref().err().map_or(false, is_server_failure) {
+            circuit.failures = circuit.failures.saturating_add(1);
+            if circuit.failures >= self.failure_threshold {
+                circuit.opened_at = Some(Instant::now());
+            }
+        } else {
+            *circuit = Circuit::default();
+        }
+
+        result
+    }
+
+    /// Whether the circuit of the endpoint with the given name is currently open, i.e. requests
+    /// to it fail without being sent.
+    pub fn is_open(&self, name: &str) -> bool {
+        let circuits = self.circuits.lock().expect("circuit breaker state poisoned");
+        circuits
+            .get(name)
+            .and_then(|circuit| circuit.opened_at)
+            .map_or(false, |opened_at| opened_at.elapsed() < self.open_duration)
+    }
+}
+
+/// Lets identical requests that are sent at the same time share a single network call.
+///
+/// This only applies to requests to safe endpoints (see `Endpoint::IS_SAFE`), which are identical
+/// if they go to the same endpoint with the same path, query string, headers and access token.
+/// The request ID header (see `REQUEST_ID_HEADER`) is left out, as it is usually unique per
+/// request, so the waiting requests share the ID of the one that was sent. While such a request
+/// is in flight, identical requests wait for it to finish and get a clone of its response. If it
+/// fails or panics, the waiting requests are sent separately, as errors can't be shared.
+///
+/// Requests to other endpoints are always sent.
+#[derive(Debug, Default)]
+pub struct RequestCoalescer {
+    /// The requests currently in flight.
+    in_flight: Mutex<HashMap<String, Arc<InFlight>>>,
+}
+
+/// A request that is currently in flight.
+#[derive(Debug, Default)]
+struct InFlight {
+    /// The response once the request has finished, `Some(None)` if it failed.
+    response: Mutex<Option<Option<Box<dyn Any + Send>>>>,
+    /// Notifies the waiting requests when the request has finished.
+    finished: Condvar,
+}
+
+impl RequestCoalescer {
+    /// Creates a new `RequestCoalescer` without requests in flight.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends the request like `SendBlocking::send_blocking`, unless an identical request is
+    /// already in flight.
+    pub fn send_blocking<E>(
+        &self,
+        request: E,
+        client: &ureq::Agent,
+        base_url: &str,
+        access_token: Option<&str>,
+    ) -> Result<E::IncomingResponse, Error>
+    where
+        E: OutgoingRequest,
+        E::IncomingResponse: Clone + Send + 'static,
+    {
+        if !E::IS_SAFE {
+            return request.send_blocking(client, base_url, access_token);
+        }
+
+        let http_request: http::Request<Vec<u8>> = request.try_into()?;
+        let key = coalescing_key::<E>(&http_request, access_token);
+
+        let (in_flight, is_leader) = {
+            let mut requests = self.in_flight.lock().expect("in-flight requests poisoned");
+            match requests.get(&key) {
+                Some(in_flight) => (Arc::clone(in_flight), false),
+                None => {
+                    let in_flight = Arc::new(InFlight::default());
+                    requests.insert(key.clone(), Arc::clone(&in_flight));
+                    (in_flight, true)
+                }
+            }
+        };
+
+        if !is_leader {
+            let mut response = in_flight.response.lock().expect("in-flight request poisoned");
+            while response.is_none() {
+                response = in_flight.finished.wait(response).expect("in-flight request poisoned");
+            }
+
+            let shared = response.as_ref().and_then(Option::as_ref);
+            if let Some(response) = shared.and_then(|r| r.downcast_ref::<E::IncomingResponse>()) {
+                return Ok(response.clone());
+            }
+
+            drop(response);
+            return send_http::<E>(http_request, client, base_url, access_token, None);
+        }
+
+        let mut finish = FinishInFlight { coalescer: self, key, in_flight, response: None };
+        let result = send_http::<E>(http_request, client, base_url, access_token, None);
+        finish.response = result.as_ref().ok().map(|r| Box::new(r.clone()) as Box<dyn Any + Send>);
+
+        result
+    }
+}
+
+/// The key under which identical requests are coalesced: one line each for the endpoint, the path
+/// and query string, the access token and every header except the request ID.
+fn coalescing_key<E: OutgoingRequest>(
+    http_request: &http::Request<Vec<u8>>,
+    access_token: Option<&str>,
+) -> String {
+    let mut key = format!(
+        "{}\n{}\n{}",
+        E::METADATA.name,
+        http_request.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/"),
+        access_token.unwrap_or_default(),
+    );
+
+    let mut headers: Vec<_> = http_request
+        .headers()
+        .iter()
+        .filter(|(name, _)| *name != REQUEST_ID_HEADER)
+        .map(|(name, value)| (name.as_str(), String::from_utf8_lossy(value.as_bytes())))
+        .collect();
+    headers.sort();
+    for (name, value) in headers {
+        key.push('\n');
+        key.push_str(name);
+        key.push_str(": ");
+        key.push_str(&value);
+    }
+
+    key
+}
+
+/// Finishes the request of the leader of an in-flight request when dropped, so the waiting
+/// requests are woken up even if sending the request or converting its response panics.
+struct FinishInFlight<'a> {
+    coalescer: &'a RequestCoalescer,
+    key: String,
+    in_flight: Arc<InFlight>,
+    /// The response to share with the waiting requests, `None` if the request failed.
+    response: Option<Box<dyn Any + Send>>,
+}
+
+impl Drop for FinishInFlight<'_> {
+    fn drop(&mut self) {
+        // Poisoned locks are ignored, as this may run while unwinding.
+        let mut requests = self.coalescer.in_flight.lock().unwrap_or_else(PoisonError::into_inner);
+        requests.remove(&self.key);
+        drop(requests);
+
+        let mut response = self.in_flight.response.lock().unwrap_or_else(PoisonError::into_inner);
+        *response = Some(self.response.take());
+        drop(response);
+
+        self.in_flight.finished.notify_all();
+    }
+}
+
+/// Sends requests to a set of registered endpoints to a live homeserver and checks that the
+/// responses convert, as a typed smoke test of the server's compliance with the specification.
+///
+/// ```no_run
+/// # use ruma_api::blocking::SmokeTest;
+/// # fn requests(smoke_test: &mut SmokeTest) {}
+/// let mut smoke_test = SmokeTest::from_env().expect("RUMA_API_SMOKE_TEST_URL not set");
+/// requests(&mut smoke_test); // Calls `smoke_test.register(...)` for each endpoint.
+///
+/// for result in smoke_test.run(&ureq::agent()) {
+///     println!("{}: {:?}", result.name, result.outcome);
+/// }
+/// ```
+pub struct SmokeTest {
+    /// The URL of the homeserver.
+    base_url: String,
+    /// The access token sent to endpoints that require authentication.
+    access_token: Option<String>,
+    /// The names of the endpoints to run, or `None` to run all of them.
+    only: Option<Vec<String>>,
+    /// The registered endpoints, in order of registration.
+    cases: Vec<SmokeTestCase>,
+}
+
+/// A registered endpoint of a `SmokeTest`.
+struct SmokeTestCase {
+    /// The name of the endpoint.
+    name: &'static str,
+    /// Sends a request to the endpoint and converts the response.
+    run: Box<SmokeTestRun>,
+}
+
+/// Sends a request of a `SmokeTestCase` with the given client, base URL and access token.
+type SmokeTestRun = dyn Fn(&ureq::Agent, &str, Option<&str>) -> Result<(), Error>;
+
+impl SmokeTest {
+    /// Creates a new `SmokeTest` against the homeserver at `base_url`, without an access token and
+    /// without registered endpoints.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self { base_url: base_url.into(), access_token: None, only: None, cases: Vec::new() }
+    }
+
+    /// Creates a new `SmokeTest` configured from environment variables, or `None` if
+    /// `RUMA_API_SMOKE_TEST_URL` isn't set, so the smoke test can be skipped in that case.
+    ///
+    /// *   `RUMA_API_SMOKE_TEST_URL`: The URL of the homeserver.
+    /// *   `RUMA_API_SMOKE_TEST_TOKEN` (optional): The access token.
+    /// *   `RUMA_API_SMOKE_TEST_ENDPOINTS` (optional): A comma-separated list of the names of the
+    ///     endpoints to run. All registered endpoints are run if it isn't set.
+    pub fn from_env() -> Option<Self> {
+        let mut smoke_test = Self::new(env::var("RUMA_API_SMOKE_TEST_URL").ok()?);
+
+        if let Ok(access_token) = env::var("RUMA_API_SMOKE_TEST_TOKEN") {
+            smoke_test = smoke_test.with_access_token(access_token);
+        }
+
+        if let Ok(endpoints) = env::var("RUMA_API_SMOKE_TEST_ENDPOINTS") {
+            let names: Vec<_> = endpoints.split(',').map(str::trim).collect();
+            smoke_test = smoke_test.with_only(&names);
+        }
+
+        Some(smoke_test)
+    }
+
+    /// Sets the access token sent to endpoints that require authentication.
+    pub fn with_access_token(mut self, access_token: impl Into<String>) -> Self {
+        self.access_token = Some(access_token.into());
+        self
+    }
+
+    /// Restricts the endpoints that are run to the ones with the given names.
+    pub fn with_only(mut self, names: &[&str]) -> Self {
+        self.only = Some(names.iter().map(|&name| name.to_owned()).collect());
+        self
+    }
+
+    /// Registers an endpoint. `request` is called to create the request each time the smoke test
+    /// is run.
+    pub fn register<E, F>(&mut self, request: F)
+    where
+        E: OutgoingRequest,
+        F: Fn() -> E + 'static,
+    {
+        self.cases.push(SmokeTestCase {
+            name: E::METADATA.name,
+            run: Box::new(move |client, base_url, access_token| {
+                send(request(), client, base_url, access_token, None).map(|_| ())
+            }),
+        });
+    }
+
+    /// Sends a request to each of the selected endpoints in order of registration, and returns
+    /// their results in the same order.
+    pub fn run(&self, client: &ureq::Agent) -> Vec<SmokeTestResult> {
+        let access_token = self.access_token.as_deref();
+
+        self.cases
+            .iter()
+            .filter(|case| {
+                self.only.as_ref().map_or(true, |only| only.iter().any(|name| name == case.name))
+            })
+            .map(|case| SmokeTestResult {
+                name: case.name,
+                outcome: match (case.run)(client, &self.base_url, access_token) {
+                    Ok(()) => SmokeTestOutcome::Passed,
+                    Err(error) => SmokeTestOutcome::from_error(error),
+                },
+            })
+            .collect()
+    }
+}
+
+impl Debug for SmokeTest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("SmokeTest")
+            .field("base_url", &self.base_url)
+            .field("endpoints", &self.cases.iter().map(|case| case.name).collect::<Vec<_>>())
+            .field("only", &self.only)
+            .finish()
+    }
+}
+
+/// The result of running a single endpoint of a `SmokeTest`.
+#[derive(Debug)]
+pub struct SmokeTestResult {
+    /// The name of the endpoint.
+    pub name: &'static str,
+    /// What happened when the endpoint was run.
+    pub outcome: SmokeTestOutcome,
+}
+
+/// What happened when an endpoint of a `SmokeTest` was run.
+#[derive(Debug)]
+pub enum SmokeTestOutcome {
+    /// The server responded successfully, and the response converted to the endpoint's response
+    /// type.
+    Passed,
+
+    /// The server responded successfully, but the response didn't convert to the endpoint's
+    /// response type. This usually means the server or the endpoint definition doesn't match the
+    /// specification; `Error::json_pointer` points to the offending value.
+    ParseFailure(Error),
+
+    /// The server responded with an unsuccessful status code.
+    ErrorResponse(Error),
+
+    /// The request couldn't be created or sent.
+    RequestFailure(Error),
+}
+
+impl SmokeTestOutcome {
+    /// Sorts a failed run into the kind of failure.
+    fn from_error(error: Error) -> Self {
+        if error.status_code().is_some() {
+            SmokeTestOutcome::ErrorResponse(error)
+        } else if error.headers().is_some() {
+            SmokeTestOutcome::ParseFailure(error)
+        } else {
+            SmokeTestOutcome::RequestFailure(error)
+        }
+    }
+
+    /// Whether the endpoint passed.
+    pub fn is_passed(&self) -> bool {
+        matches!(self, SmokeTestOutcome::Passed)
+    }
+}
+
+/// Whether the error indicates that the server failed to handle the request.
+fn is_server_failure(error: &Error) -> bool {
+    match error.0 {
+        InnerError::Transport(_) => true,
+        _ => error.status_code().map_or(false, |code| code.is_server_error()),
+    }
+}
+
+/// Copies an `http::Request`, which doesn't implement `Clone`.
+fn clone_http_request(request: &http::Request<Vec<u8>>) -> http::Request<Vec<u8>> {
+    let mut clone = http::Request::new(request.body().clone());
+    *clone.method_mut() = request.method().clone();
+    *clone.uri_mut() = request.uri().clone();
+    *clone.headers_mut() = request.headers().clone();
+    clone
+}
+
+/// Sends the request and converts the response, reading at most `max_body_size` bytes of the
+/// response body if given.
+fn send<E: OutgoingRequest>(
+    request: E,
+    client: &ureq::Agent,
+    base_url: &str,
+    access_token: Option<&str>,
+    max_body_size: Option<usize>,
+) -> Result<E::IncomingResponse, Error> {
+    send_http::<E>(request.try_into()?, client, base_url, access_token, max_body_size)
+}
+
+/// Like `send`, for a request that has already been converted to an `http::Request`.
+fn send_http<E: OutgoingRequest>(
+    http_request: http::Request<Vec<u8>>,
+    client: &ureq::Agent,
+    base_url: &str,
+    access_token: Option<&str>,
+    max_body_size: Option<usize>,
+) -> Result<E::IncomingResponse, Error> {
+    let http_response = fetch::<E>(http_request, client, base_url, access_token, max_body_size)?;
+    Ok(E::IncomingResponse::try_from(http_response)?)
+}
+
+/// Sends the request and returns the response without converting it.
+fn fetch<E: OutgoingRequest>(
+    http_request: http::Request<Vec<u8>>,
+    client: &ureq::Agent,
+    base_url: &str,
+    access_token: Option<&str>,
+    max_body_size: Option<usize>,
+) -> Result<http::Response<Vec<u8>>, Error> {
+    let path_and_query = http_request.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let mut url = format!("{}{}", base_url.trim_end_matches('/'), path_and_query);
+
+    let access_token = if E::METADATA.requires_authentication { access_token } else { None };
+    let placement = E::METADATA.access_token_placement;
+
+    if let (Some(access_token), AccessTokenPlacement::QueryString) = (access_token, placement) {
+        url.push(if http_request.uri().query().is_some() { '&' } else { '?' });
+        url.push_str("access_token=");
+        url.push_str(&encoding::component(access_token));
+    }
+
+    let mut request = client.request(http_request.method().as_str(), &url);
+    for name in http_request.headers().keys() {
+        // `set` replaces earlier values of a header, so all values are sent as a single list.
+        let separator = if name == http::header::COOKIE { "; " } else { ", " };
+        let values: Vec<_> = http_request
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|value| String::from_utf8_lossy(value.as_bytes()))
+            .collect();
+        request = request.set(name.as_str(), &values.join(separator));
+    }
+
+    if let (Some(access_token), AccessTokenPlacement::AuthorizationHeader) =
+        (access_token, placement)
+    {
+        request = request.set("Authorization", &format!("Bearer {}", access_token));
+    }
+
+    let response = match request.send_bytes(http_request.body()) {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(ureq::Error::Transport(transport)) => {
+            return Err(Error(InnerError::Transport(Box::new(transport))))
+        }
+    };
+
+    let mut http_response = http::Response::builder();
+    http_response.status(StatusCode::from_u16(response.status()).map_err(http::Error::from)?);
+    for name in response.headers_names() {
+        for value in response.all(&name) {
+            http_response.header(name.as_str(), value);
+        }
+    }
+
+    let max_body_size =
+        max_body_size.or_else(|| Config::with_global(|config| config.max_response_body_size));
+
+    let mut body = Vec::new();
+    match max_body_size {
+        Some(max_body_size) => {
+            let content_length =
+                response.header("Content-Length").and_then(|len| len.parse::<u64>().ok());
+            if content_length.map_or(false, |len| len > max_body_size as u64) {
+                return Err(Error(InnerError::ResponseTooLarge(max_body_size)));
+            }
+
+            // Read one byte more than allowed to detect bodies without a `Content-Length`
+            // that exceed the limit.
+            response.into_reader().take(max_body_size as u64 + 1).read_to_end(&mut body)?;
+            if body.len() > max_body_size {
+                return Err(Error(InnerError::ResponseTooLarge(max_body_size)));
+            }
+        }
+        None => {
+            response.into_reader().read_to_end(&mut body)?;
+        }
+    }
+
+    Ok(http_response.body(body)?)
+}
@@ -0,0 +1,168 @@
+//! Process-wide defaults for converting and sending requests.
+
+use std::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use http::HeaderMap;
+
+/// The global configuration, null until it is set.
+static GLOBAL: AtomicPtr<Config> = AtomicPtr::new(ptr::null_mut());
+
+/// Defaults consulted by the conversion code of all endpoints, and by the blocking client.
+///
+/// A configuration can be made global once with `Config::set_global`, typically at the start of
+/// `main`. Until then, and if it is never set, `Config::default()` is used.
+///
+/// ```
+/// use ruma_api::Config;
+///
+/// let mut config = Config::default();
+/// config.default_headers.insert(http::header::USER_AGENT, "my-client/1.0".parse().unwrap());
+/// config.max_response_body_size = Some(10 * 1024 * 1024);
+///
+/// config.set_global().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Headers added to every request created from an endpoint's request type, unless the
+    /// endpoint sets a header of the same name itself.
+    pub default_headers: HeaderMap,
+
+    /// Whether a response body whose length differs from its `Content-Length` header is rejected.
+    ///
+    /// Bodies with a `Content-Encoding` header aren't checked, since the header announces the
+    /// length of the encoded body, which the HTTP client may have decoded already.
+    ///
+    /// Defaults to `false`.
+    pub strict_content_length: bool,
+
+    /// The maximum size of response bodies read by the blocking client, for requests that don't
+    /// specify a limit themselves.
+    ///
+    /// Defaults to `None`, i.e. no limit.
+    pub max_response_body_size: Option<usize>,
+
+    /// The maximum size of gzip-compressed incoming request bodies after decompression, for
+    /// endpoints with `compress_request_body: true`.
+    ///
+    /// A few kilobytes of gzip can decompress to gigabytes, so decompression stops at this size
+    /// and the request is rejected.
+    ///
+    /// Defaults to 10 MiB. `None` means no limit.
+    pub max_request_body_size: Option<usize>,
+
+    /// Whether an incoming request whose path has more segments than the endpoint's path
+    /// template is rejected, instead of ignoring the extra segments.
+    ///
+    /// Ignoring them accepts paths with a trailing slash, like `/_matrix/client/r0/sync/`, from
+    /// clients that add one. But a router that picks the endpoint by a prefix of the path then
+    /// relies on the conversion to reject everything else: `/_matrix/client/r0/rooms/{id}/leave`
+    /// with any suffix is handled as a `leave` request, and a reverse proxy or access rule that
+    /// only allows or denies exact paths no longer sees the same endpoint as the server. Only
+    /// turn this off if the router matches exact paths itself.
+    ///
+    /// Defaults to `true`.
+    pub strict_request_path: bool,
+
+    /// Whether empty segments in the path of an incoming request are ignored, i.e. duplicate
+    /// slashes are treated like a single one and a trailing slash is dropped.
+    ///
+    /// Reverse proxies that join path prefixes commonly introduce both, e.g.
+    /// `/_matrix//client/r0/sync/`. Without this, such a path fails to match its endpoint. With
+    /// it, path parameters can't be empty, since an empty segment is skipped rather than matched
+    /// against the parameter.
+    ///
+    /// Defaults to `false`.
+    pub collapse_request_path_slashes: bool,
+
+    /// Whether the query string keys of an incoming request match the query fields of the
+    /// endpoint regardless of their case, for proxies and legacy clients that change it, e.g.
+    /// `Since=s1` for a `since` field.
+    ///
+    /// Outgoing requests always use the names of the fields. Flattened query fields and query
+    /// maps still match their keys exactly.
+    ///
+    /// Defaults to `false`.
+    pub case_insensitive_query_keys: bool,
+
+    /// Whether the non-empty JSON body of an incoming request is rejected if the request's
+    /// `Content-Type` isn't `application/json`, instead of parsing the body regardless.
+    ///
+    /// The error's `FromHttpRequestError::errcode` is `M_NOT_JSON`.
+    ///
+    /// Defaults to `false`.
+    pub strict_request_content_type: bool,
+
+    /// Called for every response with a JSON body whose `Content-Type` is missing or isn't
+    /// `application/json`, e.g. `text/plain` from a misconfigured reverse proxy.
+    ///
+    /// Such bodies are parsed as JSON regardless. This hook makes the misconfiguration visible,
+    /// e.g. by logging a warning.
+    ///
+    /// Defaults to `None`.
+    pub on_content_type_anomaly: Option<fn(ContentTypeAnomaly)>,
+}
+
+/// A response with a JSON body but an unexpected `Content-Type`, as passed to
+/// `Config::on_content_type_anomaly`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentTypeAnomaly {
+    /// The name of the endpoint the response is for.
+    pub endpoint: &'static str,
+
+    /// The `Content-Type` of the response, or `None` if it has none.
+    pub content_type: Option<String>,
+}
+
+impl Config {
+    /// Makes this configuration the global one.
+    ///
+    /// This only succeeds once per process. If a global configuration has been set already, it
+    /// stays in place and the given configuration is returned as the error.
+    // Returning the configuration lets the caller inspect or reuse it, its size doesn't matter
+    // for a call that happens once.
+    #[allow(clippy::result_large_err)]
+    pub fn set_global(self) -> Result<(), Config> {
+        let config = Box::into_raw(Box::new(self));
+
+        match GLOBAL.compare_exchange(ptr::null_mut(), config, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => Ok(()),
+            // SAFETY: The pointer was created by `Box::into_raw` above and never shared.
+            Err(_) => Err(*unsafe { Box::from_raw(config) }),
+        }
+    }
+
+    /// The global configuration, if one has been set with `Config::set_global`.
+    pub fn global() -> Option<&'static Config> {
+        // SAFETY: A non-null pointer was leaked from a `Box` by `set_global` and is never freed
+        // or replaced.
+        unsafe { GLOBAL.load(Ordering::Acquire).as_ref() }
+    }
+
+    /// Calls `f` with the global configuration, or with the default one if none has been set.
+    pub(crate) fn with_global<T>(f: impl FnOnce(&Config) -> T) -> T {
+        match Config::global() {
+            Some(config) => f(config),
+            None => f(&Config::default()),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_headers: HeaderMap::new(),
+            strict_content_length: false,
+            max_response_body_size: None,
+            max_request_body_size: Some(10 * 1024 * 1024),
+            strict_request_path: true,
+            collapse_request_path_slashes: false,
+            case_insensitive_query_keys: false,
+            strict_request_content_type: false,
+            on_content_type_anomaly: None,
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Helpers for logging request and response bodies.
+
+use serde_json::Value;
+
+/// The keys whose values are redacted by `preview`, wherever they appear in a JSON body.
+pub const SENSITIVE_KEYS: &[&str] =
+    &["access_token", "login_token", "new_password", "password", "refresh_token", "token"];
+
+/// The replacement for the values of sensitive keys.
+const REDACTED: &str = "<redacted>";
+
+/// The size above which `preview` doesn't parse a body as JSON.
+pub const MAX_PARSED_LEN: usize = 64 * 1024;
+
+/// Returns a preview of a request or response body of at most `max` bytes, plus a suffix noting
+/// the full length if the body was truncated.
+///
+/// If the body is JSON, the values of all keys in `SENSITIVE_KEYS` are replaced by
+/// `"<redacted>"` first, and the preview is of the compact JSON. Other bodies are previewed as
+/// text, with invalid UTF-8 replaced by `U+FFFD`. Truncation always happens at a character
+/// boundary.
+///
+/// Parsing takes time and memory proportional to the whole body, so bodies larger than
+/// `MAX_PARSED_LEN` aren't parsed. Only their first `max` bytes are previewed as text, in which
+/// the values of sensitive keys are found by scanning for the keys and redacted as well.
+///
+/// ```
+/// use ruma_api::debug::preview;
+///
+/// let body = br#"{"user":"alice","password":"hunter2"}"#;
+/// assert_eq!(preview(body, 100), r#"{"password":"<redacted>","user":"alice"}"#);
+/// assert_eq!(preview("ünïcödé".as_bytes(), 4), "ün… (11 bytes)");
+/// ```
+pub fn preview(body: &[u8], max: usize) -> String {
+    let (text, is_complete) = if body.len() > MAX_PARSED_LEN {
+        let prefix = &body[..max.min(body.len())];
+        (redact_text(&String::from_utf8_lossy(prefix)), prefix.len() == body.len())
+    } else {
+        let text = match serde_json::from_slice::<Value>(body) {
+            Ok(mut json) => {
+                redact(&mut json);
+                json.to_string()
+            }
+            Err(_) => String::from_utf8_lossy(body).into_owned(),
+        };
+        (text, true)
+    };
+
+    if is_complete && text.len() <= max {
+        return text;
+    }
+
+    let mut end = max.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}… ({} bytes)", &text[..end], body.len())
+}
+
+/// Replaces the values of sensitive keys in `value` and all nested objects and arrays.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if SENSITIVE_KEYS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.to_owned());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Replaces the values of sensitive keys in text that may be the truncated start of a JSON body,
+/// without parsing it.
+///
+/// Values that are cut off are redacted up to the end of the text.
+fn redact_text(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('"') {
+        let len = match string_len(&rest[start..]) {
+            Some(len) => start + len,
+            None => break,
+        };
+        let (string, after) = rest.split_at(len);
+        redacted.push_str(string);
+        rest = after;
+
+        let key = &string[start + 1..len - 1];
+        let after_key = rest.trim_start();
+        if SENSITIVE_KEYS.contains(&key) && after_key.starts_with(':') {
+            let value = after_key[1..].trim_start();
+            redacted.push_str(&rest[..rest.len() - value.len()]);
+            redacted.push('"');
+            redacted.push_str(REDACTED);
+            redacted.push('"');
+            rest = &value[value_len(value)..];
+        }
+    }
+
+    redacted.push_str(rest);
+    redacted
+}
+
+/// The length of the JSON string at the start of `text`, including both quotes, or `None` if it
+/// doesn't end.
+fn string_len(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(i + 1),
+            _ => escaped = false,
+        }
+    }
+
+    None
+}
+
+/// The length of the JSON value at the start of `text`, or of all of `text` if it doesn't end.
+fn value_len(text: &str) -> usize {
+    let mut depth = 0_usize;
+    let mut i = 0;
+
+    while let Some(c) = text[i..].chars().next() {
+        match c {
+            '"' => match string_len(&text[i..]) {
+                Some(len) if depth == 0 => return i + len,
+                Some(len) => {
+                    i += len;
+                    continue;
+                }
+                None => break,
+            },
+            '{' | '[' => depth += 1,
+            '}' | ']' if depth == 1 => return i + 1,
+            '}' | ']' if depth > 1 => depth -= 1,
+            '}' | ']' | ',' if depth == 0 => return i,
+            c if c.is_whitespace() && depth == 0 => return i,
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preview, MAX_PARSED_LEN};
+
+    #[test]
+    fn nested_sensitive_keys() {
+        let body = br#"{"auth":{"type":"m.login.password","password":"x"},"tokens":[{"token":1}]}"#;
+
+        assert_eq!(
+            preview(body, 1000),
+            r#"{"auth":{"password":"<redacted>","type":"m.login.password"},"tokens":[{"token":"<redacted>"}]}"#
+        );
+    }
+
+    #[test]
+    fn truncation() {
+        assert_eq!(preview(b"hello", 5), "hello");
+        assert_eq!(preview(b"hello world", 5), "hello… (11 bytes)");
+        assert_eq!(preview("日本語".as_bytes(), 4), "日… (9 bytes)");
+        assert_eq!(preview("日本語".as_bytes(), 2), "… (9 bytes)");
+        assert_eq!(preview(&[b'a', 0xff, b'b'], 10), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn large_bodies_are_not_parsed() {
+        let padding = "a".repeat(MAX_PARSED_LEN);
+
+        let body =
+            format!(r#"{{"auth": {{"password" : "x\"y", "type": 1}}, "data": "{}"}}"#, padding);
+        assert_eq!(
+            preview(body.as_bytes(), 60),
+            format!(
+                r#"{{"auth": {{"password" : "<redacted>", "type": 1}}, "data": "aa… ({} bytes)"#,
+                body.len()
+            )
+        );
+
+        let body = format!(r#"{{"access_token":"secret","data":"{}"}}"#, padding);
+        assert_eq!(
+            preview(body.as_bytes(), 20),
+            format!(r#"{{"access_token":"<re… ({} bytes)"#, body.len())
+        );
+        assert!(!preview(body.as_bytes(), 22).contains("sec"));
+
+        let body = format!(r#"{{"token":{{"a":["s]", 1]}},"note":"{}"}}"#, padding);
+        assert_eq!(
+            preview(body.as_bytes(), 50),
+            format!(r#"{{"token":"<redacted>","note":"aaaaaaaaaaaaaaaaa… ({} bytes)"#, body.len())
+        );
+    }
+}
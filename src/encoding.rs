@@ -0,0 +1,51 @@
+//! Percent-encoding of values that are inserted into request URLs.
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC};
+
+/// The characters that are percent-encoded in a path segment.
+///
+/// This matches the set used by `url::PathSegmentsMut::push`.
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// The characters that are percent-encoded in a component: everything but the characters that
+/// RFC 3986 calls "unreserved".
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// Percent-encodes a value for use as a single path segment.
+pub(crate) fn path_segment(value: &str) -> String {
+    utf8_percent_encode(value, PATH_SEGMENT).to_string()
+}
+
+/// Percent-encodes all characters of a value except for unreserved ones.
+pub(crate) fn component(value: &str) -> String {
+    utf8_percent_encode(value, COMPONENT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{component, path_segment};
+
+    #[test]
+    fn encode_path_segment() {
+        assert_eq!(path_segment("#room/alias?:example.org"), "%23room%2Falias%3F:example.org");
+        assert_eq!(path_segment("100%"), "100%25");
+        assert_eq!(path_segment("!room:example.org"), "!room:example.org");
+    }
+
+    #[test]
+    fn encode_component() {
+        assert_eq!(component("!room:example.org"), "%21room%3Aexample.org");
+        assert_eq!(component("a-b.c_d~e f"), "a-b.c_d~e%20f");
+    }
+}
@@ -0,0 +1,91 @@
+//! A macro for enums identifying a group of endpoints.
+
+/// Generates an enum with one unit variant per endpoint, for identifying endpoints in permission
+/// tables, metrics or configuration files without passing their names around as strings.
+///
+/// Each variant is followed by the request type of its endpoint. The enum derives `Clone`, `Copy`,
+/// `Debug`, `PartialEq`, `Eq`, `Hash`, `PartialOrd` and `Ord`, and gets these items:
+///
+/// * `ALL`, a slice of all variants in the order they are declared,
+/// * `metadata(self) -> &'static Metadata`, the metadata of the endpoint,
+/// * `name(self) -> &'static str`, the name of the endpoint from its metadata,
+/// * `from_name(&str) -> Option<Self>`, the variant of the endpoint with the given name.
+///
+/// ```
+/// # mod create_alias {
+/// #     ruma_api::ruma_api! {
+/// #         metadata {
+/// #             description: "Add an alias to a room.",
+/// #             method: PUT,
+/// #             name: "create_alias",
+/// #             path: "/_matrix/client/r0/directory/room/:room_alias",
+/// #             rate_limited: false,
+/// #             requires_authentication: true,
+/// #         }
+/// #         request {
+/// #             #[ruma_api(path)]
+/// #             pub room_alias: String,
+/// #         }
+/// #         response {}
+/// #     }
+/// # }
+/// ruma_api::endpoint_kind! {
+///     /// The endpoints of the room directory.
+///     pub enum DirectoryEndpoint {
+///         /// Adds an alias to a room.
+///         CreateAlias => create_alias::Request,
+///     }
+/// }
+///
+/// assert_eq!(DirectoryEndpoint::from_name("create_alias"), Some(DirectoryEndpoint::CreateAlias));
+/// assert_eq!(DirectoryEndpoint::CreateAlias.metadata().method, http::Method::PUT);
+/// ```
+#[macro_export]
+macro_rules! endpoint_kind {
+    (
+        $(#[$attr:meta])*
+        $vis:vis enum $name:ident {
+            $(
+                $(#[$variant_attr:meta])*
+                $variant:ident => $request:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        $vis enum $name {
+            $(
+                $(#[$variant_attr])*
+                $variant,
+            )*
+        }
+
+        impl $name {
+            /// All endpoint kinds, in the order they are declared.
+            pub const ALL: &'static [$name] = &[$($name::$variant),*];
+
+            /// The metadata of the endpoint.
+            pub fn metadata(self) -> &'static $crate::Metadata {
+                match self {
+                    $(
+                        $name::$variant => {
+                            static METADATA: $crate::Metadata =
+                                <$request as $crate::Endpoint>::METADATA;
+                            &METADATA
+                        }
+                    )*
+                }
+            }
+
+            /// The name of the endpoint.
+            pub fn name(self) -> &'static str {
+                self.metadata().name
+            }
+
+            /// The endpoint kind with the given endpoint name, if any.
+            pub fn from_name(name: &str) -> Option<Self> {
+                $name::ALL.iter().cloned().find(|kind| kind.name() == name)
+            }
+        }
+    };
+}
@@ -0,0 +1,654 @@
+//! Error types for converting `Endpoint` requests and responses to and from the corresponding types
+//! from the `http` crate, and for sending requests.
+
+use std::{
+    any::Any,
+    error::Error as StdError,
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+    io,
+    time::Duration,
+};
+
+use http::{HeaderMap, StatusCode};
+
+use crate::{retry_after, MatrixError, UiaaInfo};
+
+/// An error when converting an `Endpoint` request to an `http::Request`, or its response to an
+/// `http::Response`.
+#[derive(Debug)]
+pub struct IntoHttpError(pub(crate) InnerError);
+
+impl Display for IntoHttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for IntoHttpError {}
+
+impl From<http::Error> for IntoHttpError {
+    fn from(error: http::Error) -> Self {
+        Self(InnerError::Http(error))
+    }
+}
+
+impl From<io::Error> for IntoHttpError {
+    fn from(error: io::Error) -> Self {
+        Self(InnerError::Io(error))
+    }
+}
+
+impl From<serde_json::Error> for IntoHttpError {
+    fn from(error: serde_json::Error) -> Self {
+        Self(InnerError::SerdeJson(error))
+    }
+}
+
+impl From<serde_urlencoded::ser::Error> for IntoHttpError {
+    fn from(error: serde_urlencoded::ser::Error) -> Self {
+        Self(InnerError::SerdeUrlEncodedSer(error))
+    }
+}
+
+/// An error when converting an `http::Response` to the response type of an `Endpoint`.
+///
+/// This includes responses with an unsuccessful status code.
+#[derive(Debug)]
+pub struct FromHttpResponseError(pub(crate) InnerError);
+
+impl FromHttpResponseError {
+    /// Creates the error for a response with an unsuccessful status code, including how long to
+    /// wait before retrying if the server specified it, and the body if it's a `MatrixError`.
+    ///
+    /// This is meant to be used in the `TryFrom<http::Response<_>>` implementation of an
+    /// endpoint's response type.
+    pub fn from_error_response<B: AsRef<[u8]>>(response: &http::Response<B>) -> Self {
+        Self::from_error_response_with_body(response, None)
+    }
+
+    /// Like `from_error_response`, but also deserializes the body of the response as the error
+    /// type `T` of the endpoint, which is then available through `error_body`.
+    ///
+    /// If the body can't be deserialized as `T`, the error is created without it.
+    #[cfg(feature = "serde")]
+    pub fn from_error_response_as<T, B>(response: &http::Response<B>) -> Self
+    where
+        T: serde::de::DeserializeOwned + Debug + Send + Sync + 'static,
+        B: AsRef<[u8]>,
+    {
+        let body = serde_json::from_slice::<T>(response.body().as_ref()).ok();
+        Self::from_error_response_with_body(
+            response,
+            body.map(|body| Box::new(body) as Box<dyn ErrorBody>),
+        )
+    }
+
+    /// Creates the error for a `401 Unauthorized` response whose body describes how to complete
+    /// user-interactive authentication, or `None` for any other response.
+    ///
+    /// This is meant to be used in the `TryFrom<http::Response<_>>` implementation of an
+    /// endpoint's response type, before falling back to `from_error_response`.
+    pub fn from_uiaa_response<B: AsRef<[u8]>>(response: &http::Response<B>) -> Option<Self> {
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return None;
+        }
+
+        let info = UiaaInfo::from_body(response.body().as_ref())?;
+        Some(Self(InnerError::Uiaa(Box::new(info))).with_headers(response.headers()))
+    }
+
+    fn from_error_response_with_body<B: AsRef<[u8]>>(
+        response: &http::Response<B>,
+        body: Option<Box<dyn ErrorBody>>,
+    ) -> Self {
+        let retry_after = retry_after::from_response(response);
+        let matrix_error = MatrixError::from_body(response.body().as_ref());
+        Self(InnerError::StatusCode(response.status(), retry_after, matrix_error, body))
+            .with_headers(response.headers())
+    }
+
+    /// Attaches the headers of the response that failed to convert to the error.
+    pub(crate) fn with_headers(self, headers: &HeaderMap) -> Self {
+        Self(InnerError::WithHeaders(Box::new(self.0), headers.clone()))
+    }
+
+    /// The headers of the response that failed to convert, if the error happened after they were
+    /// available, e.g. because the body couldn't be deserialized.
+    ///
+    /// This way, headers like `Retry-After` or `X-Request-Id` can be inspected even if the body of
+    /// the response is malformed.
+    pub fn headers(&self) -> Option<&HeaderMap> {
+        self.0.headers()
+    }
+
+    /// The HTTP status code of the response, if this error was caused by a response with an
+    /// unsuccessful status code.
+    pub fn status_code(&self) -> Option<StatusCode> {
+        self.0.status_code()
+    }
+
+    /// A JSON pointer (RFC 6901) to the value in the body that failed to deserialize, if this
+    /// error was caused by deserializing a body generated by `ruma_api!`.
+    ///
+    /// This is meant to be included in bug reports, to find out which field didn't match the
+    /// specification. The empty string refers to the body as a whole.
+    pub fn json_pointer(&self) -> Option<&str> {
+        self.0.json_pointer()
+    }
+
+    /// Whether the response was converted, but rejected by a validator declared with
+    /// `#[ruma_api(validate = ...)]` or `validate_response`.
+    pub fn is_validation_error(&self) -> bool {
+        self.0.is_validation_error()
+    }
+
+    /// How long the server asked the client to wait before retrying the request.
+    ///
+    /// This is taken from the `Retry-After` header if present, or from the `retry_after_ms` field
+    /// of the error body otherwise.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.0.retry_after()
+    }
+
+    /// The deserialized body of a response with an unsuccessful status code, if the endpoint
+    /// declares its error type `T` with `error_ty` and the body could be deserialized as it.
+    pub fn error_body<T: 'static>(&self) -> Option<&T> {
+        self.0.error_body()
+    }
+
+    /// The body of a response with an unsuccessful status code, if it's a standard Matrix error
+    /// with an `errcode`.
+    pub fn matrix_error(&self) -> Option<&MatrixError> {
+        self.0.matrix_error()
+    }
+
+    /// How to complete user-interactive authentication, if the endpoint declares `uiaa: true` and
+    /// the server asked for it.
+    pub fn uiaa_info(&self) -> Option<&UiaaInfo> {
+        self.0.uiaa_info()
+    }
+}
+
+impl Display for FromHttpResponseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for FromHttpResponseError {}
+
+impl From<serde_json::Error> for FromHttpResponseError {
+    fn from(error: serde_json::Error) -> Self {
+        Self(InnerError::SerdeJson(error))
+    }
+}
+
+impl From<StatusCode> for FromHttpResponseError {
+    fn from(error: StatusCode) -> Self {
+        Self(InnerError::StatusCode(error, None, None, None))
+    }
+}
+
+/// An error when converting an `http::Request` to the request type of an `Endpoint`, e.g. because
+/// its path doesn't match the endpoint's or a required header is missing.
+#[derive(Debug)]
+pub struct FromHttpRequestError(pub(crate) InnerError);
+
+impl FromHttpRequestError {
+    /// A JSON pointer (RFC 6901) to the value in the body that failed to deserialize, if this
+    /// error was caused by deserializing a body generated by `ruma_api!`.
+    pub fn json_pointer(&self) -> Option<&str> {
+        self.0.json_pointer()
+    }
+
+    /// The Matrix error code to respond with if this error was caused by the request body:
+    /// `M_NOT_JSON` for a body that isn't JSON, or `M_BAD_JSON` for JSON that doesn't match the
+    /// endpoint's body.
+    pub fn errcode(&self) -> Option<&'static str> {
+        self.0.errcode()
+    }
+
+    /// The name of the endpoint whose newtype body field (`#[ruma_api(body)]`) failed to
+    /// deserialize, if that's what caused this error.
+    pub fn newtype_body_endpoint(&self) -> Option<&'static str> {
+        match self.0 {
+            InnerError::NewtypeBody(name, _) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl Display for FromHttpRequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for FromHttpRequestError {}
+
+impl From<io::Error> for FromHttpRequestError {
+    fn from(error: io::Error) -> Self {
+        Self(InnerError::Io(error))
+    }
+}
+
+impl From<serde_json::Error> for FromHttpRequestError {
+    fn from(error: serde_json::Error) -> Self {
+        Self(InnerError::SerdeJson(error))
+    }
+}
+
+impl From<serde_urlencoded::de::Error> for FromHttpRequestError {
+    fn from(error: serde_urlencoded::de::Error) -> Self {
+        Self(InnerError::SerdeUrlEncodedDe(error))
+    }
+}
+
+/// An error in either direction of the conversion between `Endpoint` types and `http` types, or
+/// while sending a request.
+///
+/// This is the error type of the blocking client, which does both conversions.
+#[derive(Debug)]
+pub struct Error(pub(crate) InnerError);
+
+impl Error {
+    /// The HTTP status code of the response, if this error was caused by a response with an
+    /// unsuccessful status code.
+    pub fn status_code(&self) -> Option<StatusCode> {
+        self.0.status_code()
+    }
+
+    /// A JSON pointer (RFC 6901) to the value in the body that failed to deserialize, if this
+    /// error was caused by deserializing a body generated by `ruma_api!`.
+    ///
+    /// See `FromHttpResponseError::json_pointer`.
+    pub fn json_pointer(&self) -> Option<&str> {
+        self.0.json_pointer()
+    }
+
+    /// Whether the response was converted, but rejected by a validator.
+    ///
+    /// See `FromHttpResponseError::is_validation_error`.
+    pub fn is_validation_error(&self) -> bool {
+        self.0.is_validation_error()
+    }
+
+    /// How long the server asked the client to wait before retrying the request.
+    ///
+    /// See `FromHttpResponseError::retry_after`.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.0.retry_after()
+    }
+
+    /// The deserialized body of a response with an unsuccessful status code.
+    ///
+    /// See `FromHttpResponseError::error_body`.
+    pub fn error_body<T: 'static>(&self) -> Option<&T> {
+        self.0.error_body()
+    }
+
+    /// See `FromHttpResponseError::matrix_error`.
+    pub fn matrix_error(&self) -> Option<&MatrixError> {
+        self.0.matrix_error()
+    }
+
+    /// See `FromHttpResponseError::uiaa_info`.
+    pub fn uiaa_info(&self) -> Option<&UiaaInfo> {
+        self.0.uiaa_info()
+    }
+
+    /// The headers of the response, if this error happened while converting a response.
+    ///
+    /// See `FromHttpResponseError::headers`.
+    pub fn headers(&self) -> Option<&HeaderMap> {
+        self.0.headers()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for Error {}
+
+impl From<IntoHttpError> for Error {
+    fn from(error: IntoHttpError) -> Self {
+        Self(error.0)
+    }
+}
+
+impl From<FromHttpResponseError> for Error {
+    fn from(error: FromHttpResponseError) -> Self {
+        Self(error.0)
+    }
+}
+
+impl From<FromHttpRequestError> for Error {
+    fn from(error: FromHttpRequestError) -> Self {
+        Self(error.0)
+    }
+}
+
+impl From<http::Error> for Error {
+    fn from(error: http::Error) -> Self {
+        Self(InnerError::Http(error))
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self(InnerError::Io(error))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Self(InnerError::SerdeJson(error))
+    }
+}
+
+impl From<serde_urlencoded::de::Error> for Error {
+    fn from(error: serde_urlencoded::de::Error) -> Self {
+        Self(InnerError::SerdeUrlEncodedDe(error))
+    }
+}
+
+impl From<serde_urlencoded::ser::Error> for Error {
+    fn from(error: serde_urlencoded::ser::Error) -> Self {
+        Self(InnerError::SerdeUrlEncodedSer(error))
+    }
+}
+
+impl From<ruma_identifiers::Error> for Error {
+    fn from(error: ruma_identifiers::Error) -> Self {
+        Self(InnerError::RumaIdentifiers(error))
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(error: StatusCode) -> Self {
+        Self(InnerError::StatusCode(error, None, None, None))
+    }
+}
+
+/// Internal representation of errors.
+// The wrapped errors are currently only read through the `Debug` implementation.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub(crate) enum InnerError {
+    /// An HTTP error.
+    Http(http::Error),
+
+    /// A I/O error.
+    Io(io::Error),
+
+    /// A Serde JSON error.
+    SerdeJson(serde_json::Error),
+
+    /// A Serde JSON error while deserializing the value at the given JSON pointer.
+    JsonAt(String, serde_json::Error),
+
+    /// A Serde URL decoding error.
+    SerdeUrlEncodedDe(serde_urlencoded::de::Error),
+
+    /// A Serde URL encoding error.
+    SerdeUrlEncodedSer(serde_urlencoded::ser::Error),
+
+    /// A Ruma Identitifiers error.
+    RumaIdentifiers(ruma_identifiers::Error),
+
+    /// An HTTP status code indicating error, how long the server asked the client to wait before
+    /// retrying, the body if it's a standard Matrix error, and the deserialized error body if the
+    /// endpoint has an error type.
+    StatusCode(StatusCode, Option<Duration>, Option<MatrixError>, Option<Box<dyn ErrorBody>>),
+
+    /// A `401 Unauthorized` response asking for user-interactive authentication.
+    Uiaa(Box<UiaaInfo>),
+
+    /// A response body whose length differs from its `Content-Length` header, which announced the
+    /// first length.
+    ContentLength(u64, usize),
+
+    /// A response that was rejected by the validator of the given field, or of the whole response
+    /// if no field is given, with the validator's message.
+    Validation(Option<&'static str>, String),
+
+    /// An incoming request whose path doesn't match the path of the endpoint.
+    PathMismatch(String),
+
+    /// A URL built for an endpoint whose path has the first number of parameters, from the second
+    /// number of path arguments.
+    PathArgs(usize, usize),
+
+    /// A request URI assembled from a string that isn't a valid URI, e.g. an incoming request
+    /// assembled from its parts or an outgoing one for an invalid base URL.
+    InvalidUri(String),
+
+    /// An incoming request without the header of a required header field.
+    MissingHeader(String),
+
+    /// An incoming request with a header value that isn't valid UTF-8.
+    InvalidHeader(String),
+
+    /// An incoming request body that decompressed to more than the configured maximum size.
+    RequestTooLarge(usize),
+
+    /// An incoming request with a JSON body but a different `Content-Type`, if any.
+    NotJson(Option<String>),
+
+    /// An error deserializing the newtype body field of an incoming request for the named
+    /// endpoint.
+    NewtypeBody(&'static str, Box<InnerError>),
+
+    /// A malformed `multipart/*` response body, with a description of the problem.
+    Multipart(&'static str),
+
+    /// An error in a function given in `#[ruma_api(manual_body = ...)]`.
+    ManualBody(Box<dyn StdError + Send + Sync>),
+
+    /// An error signing a request with a `RequestSigner`.
+    Signing(Box<dyn StdError + Send + Sync>),
+
+    /// An error converting a response, along with the headers of the response.
+    WithHeaders(Box<InnerError>, HeaderMap),
+
+    /// An error sending a request or receiving its response.
+    #[cfg(feature = "blocking")]
+    Transport(Box<ureq::Transport>),
+
+    /// A response body larger than the configured maximum size.
+    #[cfg(feature = "blocking")]
+    ResponseTooLarge(usize),
+
+    /// A request that wasn't sent because previous requests to the endpoint kept failing.
+    #[cfg(feature = "blocking")]
+    CircuitOpen(&'static str),
+}
+
+impl InnerError {
+    /// The error without the response headers attached to it.
+    fn without_headers(&self) -> &InnerError {
+        match *self {
+            InnerError::WithHeaders(ref inner, _) => inner,
+            ref inner => inner,
+        }
+    }
+
+    fn status_code(&self) -> Option<StatusCode> {
+        match *self.without_headers() {
+            InnerError::StatusCode(code, ..) => Some(code),
+            InnerError::Uiaa(_) => Some(StatusCode::UNAUTHORIZED),
+            _ => None,
+        }
+    }
+
+    fn json_pointer(&self) -> Option<&str> {
+        match *self.without_headers() {
+            InnerError::JsonAt(ref pointer, _) => Some(pointer),
+            InnerError::NewtypeBody(_, ref inner) => inner.json_pointer(),
+            _ => None,
+        }
+    }
+
+    fn errcode(&self) -> Option<&'static str> {
+        let json_error = match *self {
+            InnerError::SerdeJson(ref error) | InnerError::JsonAt(_, ref error) => error,
+            InnerError::NotJson(_) => return Some("M_NOT_JSON"),
+            InnerError::RequestTooLarge(_) => return Some("M_TOO_LARGE"),
+            InnerError::NewtypeBody(_, ref inner) => return inner.errcode(),
+            _ => return None,
+        };
+
+        if json_error.is_data() {
+            Some("M_BAD_JSON")
+        } else {
+            Some("M_NOT_JSON")
+        }
+    }
+
+    fn is_validation_error(&self) -> bool {
+        matches!(*self.without_headers(), InnerError::Validation(..))
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match *self.without_headers() {
+            InnerError::StatusCode(_, retry_after, ..) => retry_after,
+            _ => None,
+        }
+    }
+
+    fn error_body<T: 'static>(&self) -> Option<&T> {
+        match *self.without_headers() {
+            // The box implements `ErrorBody` itself, so it has to be dereferenced first.
+            InnerError::StatusCode(_, _, _, Some(ref body)) => (**body).as_any().downcast_ref(),
+            _ => None,
+        }
+    }
+
+    fn matrix_error(&self) -> Option<&MatrixError> {
+        match *self.without_headers() {
+            InnerError::StatusCode(_, _, ref matrix_error, _) => matrix_error.as_ref(),
+            InnerError::Uiaa(ref info) => info.auth_error.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn uiaa_info(&self) -> Option<&UiaaInfo> {
+        match *self.without_headers() {
+            InnerError::Uiaa(ref info) => Some(info),
+            _ => None,
+        }
+    }
+
+    fn headers(&self) -> Option<&HeaderMap> {
+        match *self {
+            InnerError::WithHeaders(_, ref headers) => Some(headers),
+            _ => None,
+        }
+    }
+}
+
+impl Display for InnerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let message = match *self {
+            InnerError::Http(_) => "An error converting to or from `http` types occurred.".into(),
+            InnerError::Io(_) => "An I/O error occurred.".into(),
+            InnerError::SerdeJson(_) => "A JSON error occurred.".into(),
+            InnerError::JsonAt(ref pointer, ref error) if pointer.is_empty() => {
+                format!("A JSON error occurred: {}", error)
+            }
+            InnerError::JsonAt(ref pointer, ref error) => {
+                format!("A JSON error occurred at `{}`: {}", pointer, error)
+            }
+            InnerError::SerdeUrlEncodedDe(_) => {
+                "A URL encoding deserialization error occurred.".into()
+            }
+            InnerError::SerdeUrlEncodedSer(_) => {
+                "A URL encoding serialization error occurred.".into()
+            }
+            InnerError::RumaIdentifiers(_) => "A ruma-identifiers error occurred.".into(),
+            InnerError::StatusCode(code, _, Some(ref error), _) => {
+                format!("A HTTP {} error occurred: {}", code, error)
+            }
+            InnerError::StatusCode(code, ..) => format!("A HTTP {} error occurred.", code),
+            InnerError::Uiaa(ref info) => match info.auth_error {
+                Some(ref error) => {
+                    format!("User-interactive authentication failed, try again: {}", error)
+                }
+                None => "The server requires user-interactive authentication.".into(),
+            },
+            InnerError::ContentLength(expected, actual) => format!(
+                "The response body has {} bytes, but its `Content-Length` header announced {}.",
+                actual, expected
+            ),
+            InnerError::Validation(Some(field), ref message) => {
+                format!("The response field `{}` is invalid: {}", field, message)
+            }
+            InnerError::Validation(None, ref message) => {
+                format!("The response is invalid: {}", message)
+            }
+            InnerError::PathMismatch(ref path) => {
+                format!("The request path `{}` doesn't match the path of the endpoint.", path)
+            }
+            InnerError::PathArgs(expected, actual) => format!(
+                "The endpoint's path has {} parameters, but {} path arguments were given.",
+                expected, actual
+            ),
+            InnerError::InvalidUri(ref uri) => format!("The request URI `{}` is invalid.", uri),
+            InnerError::MissingHeader(ref name) => {
+                format!("The request is missing the required header `{}`.", name)
+            }
+            InnerError::InvalidHeader(ref name) => {
+                format!("The value of the request header `{}` isn't valid UTF-8.", name)
+            }
+            InnerError::RequestTooLarge(max) => {
+                format!("The decompressed request body exceeded the maximum size of {} bytes.", max)
+            }
+            InnerError::NotJson(Some(ref content_type)) => format!(
+                "The request body must be JSON, but its Content-Type is `{}`.",
+                content_type
+            ),
+            InnerError::NotJson(None) => {
+                "The request body must be JSON, but it has no Content-Type.".into()
+            }
+            InnerError::NewtypeBody(name, ref inner) => {
+                format!("The body of the `{}` request couldn't be deserialized: {}", name, inner)
+            }
+            InnerError::Multipart(message) => {
+                format!("The multipart response body is invalid: {}", message)
+            }
+            InnerError::WithHeaders(ref inner, _) => inner.to_string(),
+            InnerError::ManualBody(ref error) => {
+                format!("An error occurred in a custom body conversion: {}", error)
+            }
+            InnerError::Signing(ref error) => {
+                format!("An error occurred while signing the request: {}", error)
+            }
+            #[cfg(feature = "blocking")]
+            InnerError::Transport(_) => "An error occurred while sending the request.".into(),
+            #[cfg(feature = "blocking")]
+            InnerError::ResponseTooLarge(max) => {
+                format!("The response body exceeded the maximum size of {} bytes.", max)
+            }
+            #[cfg(feature = "blocking")]
+            InnerError::CircuitOpen(name) => {
+                format!("Requests to the `{}` endpoint are failing, not sending another one.", name)
+            }
+        };
+
+        write!(f, "{}", message)
+    }
+}
+
+/// The deserialized body of an error response, whose type is only known to the endpoint.
+pub(crate) trait ErrorBody: Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any + Debug + Send + Sync> ErrorBody for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,207 @@
+//! Lazily deserialized streams of JSON values in a response body.
+
+use std::{
+    fmt::{Debug, Formatter, Result as FmtResult},
+    marker::PhantomData,
+};
+
+use serde::de::{DeserializeOwned, Error as _};
+
+use crate::FromHttpResponseError;
+
+/// A response body consisting of a sequence of JSON values of type `T`.
+///
+/// Both newline-delimited JSON (or more generally, whitespace-separated JSON values) and a
+/// top-level JSON array are supported. A body starting with `[` is always treated as a top-level
+/// array, so a newline-delimited stream of arrays is not supported.
+///
+/// The values are only deserialized one at a time while iterating, so large list responses don't
+/// have to be materialized as a `Vec<T>` at once.
+pub struct JsonStream<T> {
+    /// The raw response body.
+    body: Vec<u8>,
+    /// The type of the values in the stream.
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T> JsonStream<T> {
+    /// Creates a new `JsonStream` from a raw response body.
+    pub fn new(body: Vec<u8>) -> Self {
+        Self { body, _item: PhantomData }
+    }
+
+    /// Returns the raw response body.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Consumes the stream and returns the raw response body.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.body
+    }
+}
+
+impl<T: DeserializeOwned> JsonStream<T> {
+    /// Returns an iterator deserializing the values in the stream one at a time.
+    ///
+    /// Once a value fails to deserialize, the iterator yields the error and ends.
+    pub fn iter(&self) -> JsonStreamIter<'_, T> {
+        let body = skip_whitespace(&self.body);
+        let (remaining, is_array) = match body.first() {
+            Some(b'[') => (&body[1..], true),
+            _ => (body, false),
+        };
+
+        JsonStreamIter { remaining, is_array, seen_item: false, done: false, _item: PhantomData }
+    }
+}
+
+impl<T> Clone for JsonStream<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.body.clone())
+    }
+}
+
+impl<T> Debug for JsonStream<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("JsonStream").field("len", &self.body.len()).finish()
+    }
+}
+
+impl<T> From<Vec<u8>> for JsonStream<T> {
+    fn from(body: Vec<u8>) -> Self {
+        Self::new(body)
+    }
+}
+
+impl<'a, T: DeserializeOwned> IntoIterator for &'a JsonStream<T> {
+    type Item = Result<T, FromHttpResponseError>;
+    type IntoIter = JsonStreamIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the values of a `JsonStream`.
+pub struct JsonStreamIter<'a, T> {
+    /// The part of the body that hasn't been deserialized yet.
+    remaining: &'a [u8],
+    /// Whether the body is a top-level JSON array rather than a sequence of JSON values.
+    is_array: bool,
+    /// Whether at least one value has been deserialized already.
+    seen_item: bool,
+    /// Whether the end of the stream or an error has been reached.
+    done: bool,
+    /// The type of the values in the stream.
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T> Debug for JsonStreamIter<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("JsonStreamIter")
+            .field("remaining", &self.remaining.len())
+            .field("is_array", &self.is_array)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for JsonStreamIter<'_, T> {
+    type Item = Result<T, FromHttpResponseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut rest = skip_whitespace(self.remaining);
+
+        if self.is_array {
+            match rest.first() {
+                Some(b']') => {
+                    self.done = true;
+                    if skip_whitespace(&rest[1..]).is_empty() {
+                        return None;
+                    }
+
+                    let error = serde_json::Error::custom("trailing characters after JSON array");
+                    return Some(Err(error.into()));
+                }
+                Some(b',') if self.seen_item => rest = skip_whitespace(&rest[1..]),
+                _ if !self.seen_item => {}
+                _ => {
+                    self.done = true;
+                    return Some(Err(serde_json::Error::custom("expected `,` or `]`").into()));
+                }
+            }
+        } else if rest.is_empty() {
+            self.done = true;
+            return None;
+        }
+
+        let mut values = serde_json::Deserializer::from_slice(rest).into_iter::<T>();
+        match values.next() {
+            Some(Ok(value)) => {
+                self.remaining = &rest[values.byte_offset()..];
+                self.seen_item = true;
+                Some(Ok(value))
+            }
+            Some(Err(error)) => {
+                self.done = true;
+                Some(Err(error.into()))
+            }
+            None => {
+                self.done = true;
+                Some(Err(serde_json::Error::custom("unexpected end of JSON array").into()))
+            }
+        }
+    }
+}
+
+/// Returns the given slice without leading JSON whitespace.
+fn skip_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| !b" \t\r\n".contains(b)).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonStream;
+
+    fn collect(body: &[u8]) -> Vec<Result<u32, String>> {
+        JsonStream::<u32>::new(body.to_vec())
+            .iter()
+            .map(|result| result.map_err(|error| error.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn newline_delimited_values() {
+        assert_eq!(collect(b"1\n2\n3\n"), vec![Ok(1), Ok(2), Ok(3)]);
+        assert_eq!(collect(b""), vec![]);
+    }
+
+    #[test]
+    fn top_level_array() {
+        assert_eq!(collect(b" [1, 2,3 ] "), vec![Ok(1), Ok(2), Ok(3)]);
+        assert_eq!(collect(b"[]"), vec![]);
+
+        let values = collect(b"[1, 2] 3");
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[..2], [Ok(1), Ok(2)]);
+        assert!(values[2].is_err());
+        assert_eq!(collect(b"[1]\n\t "), vec![Ok(1)]);
+    }
+
+    #[test]
+    fn stops_after_first_error() {
+        let values = collect(b"[1, \"two\", 3]");
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0], Ok(1));
+        assert!(values[1].is_err());
+
+        assert_eq!(collect(b"[1 2]").len(), 2);
+        assert_eq!(collect(b"[1,").len(), 2);
+    }
+}
@@ -8,142 +8,1032 @@
 //! An implementation of `Endpoint` contains all the information about the HTTP method, the path and
 //! input parameters for requests, and the structure of a successful response.
 //! Such types can then be used by client code to make requests, and by server code to fulfill
-//! those requests.
+//! those requests: client code sends requests implementing `OutgoingRequest`, server code receives
+//! requests implementing `IncomingRequest`.
+//!
+//! Endpoints defined with `ruma_api!` only implement `OutgoingRequest` with the `client` feature,
+//! and `IncomingRequest` with the `server` feature. Both are enabled by default. With the
+//! `bytes-bodies` feature, they can also be converted into `http` types with `bytes::Bytes`
+//! bodies. The `path-round-trip-tests` feature generates tests for the path fields of endpoints,
+//! see `ruma_api!`.
 
 #![warn(rust_2018_idioms)]
 #![deny(missing_copy_implementations, missing_debug_implementations, missing_docs)]
-// Since we support Rust 1.34.2, we can't apply this suggestion yet
-#![allow(clippy::use_self)]
 
 use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::BTreeMap,
     convert::{TryFrom, TryInto},
     error::Error as StdError,
-    fmt::{Display, Formatter, Result as FmtResult},
-    io,
+    fmt::Display,
+    hash::{Hash, Hasher},
 };
 
-use http::{self, Method, StatusCode};
-use ruma_identifiers;
-use serde_json;
-use serde_urlencoded;
+use http::{self, Method};
 
 #[cfg(feature = "with-ruma-api-macros")]
 pub use ruma_api_macros::ruma_api;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod debug;
+pub mod proxy;
+#[cfg(feature = "serde")]
+pub mod serde;
+
+#[cfg(feature = "server")]
+mod any_incoming_request;
+mod authorization;
+mod config;
+mod encoding;
+mod endpoint_kind;
+mod error;
+#[cfg(feature = "serde")]
+mod json_stream;
+mod matrix_error;
+#[cfg(feature = "serde")]
+mod maybe;
+#[cfg(feature = "serde")]
+mod multipart;
+mod partial_request;
+mod retry_after;
+mod uiaa;
+
+#[cfg(feature = "server")]
+pub use any_incoming_request::RouteError;
+pub use authorization::{Authorization, InvalidAuthorization};
+pub use config::{Config, ContentTypeAnomaly};
+use error::InnerError;
+pub use error::{Error, FromHttpRequestError, FromHttpResponseError, IntoHttpError};
+#[cfg(feature = "serde")]
+pub use json_stream::{JsonStream, JsonStreamIter};
+pub use matrix_error::MatrixError;
+#[cfg(feature = "serde")]
+pub use maybe::Maybe;
+#[cfg(feature = "serde")]
+pub use multipart::Multipart;
+pub use partial_request::PartialRequest;
+pub use uiaa::{AuthFlow, UiaaInfo};
+
 #[cfg(feature = "with-ruma-api-macros")]
 #[doc(hidden)]
 /// This module is used to support the generated code from ruma-api-macros.
 /// It is not considered part of ruma-api's public API.
 pub mod exports {
+    #[cfg(feature = "bytes-bodies")]
+    pub use bytes;
     pub use http;
     pub use serde;
     pub use serde_json;
     pub use serde_urlencoded;
     pub use url;
+
+    use std::{borrow::Cow, error::Error as StdError, fmt::Display};
+
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use crate::{
+        Config, ContentTypeAnomaly, FromHttpRequestError, FromHttpResponseError, InnerError,
+        IntoHttpError,
+    };
+
+    // The generated code goes through these functions rather than calling serde_json and
+    // serde_urlencoded directly, so the serializers can be upgraded without changing the output
+    // of ruma-api-macros.
+
+    /// Serializes a request or response body as JSON.
+    pub fn to_json_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, IntoHttpError> {
+        serde_json::to_vec(value).map_err(IntoHttpError::from)
+    }
+
+    /// Deserializes a request or response body from JSON.
+    ///
+    /// If deserialization fails, the error records where in the body it failed.
+    pub fn from_json_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, FromHttpResponseError> {
+        let deserializer = &mut serde_json::Deserializer::from_slice(bytes);
+        let value = serde_path_to_error::deserialize(deserializer).map_err(|error| {
+            let pointer = json_pointer(error.path());
+            FromHttpResponseError(InnerError::JsonAt(pointer, error.into_inner()))
+        })?;
+
+        Ok(value)
+    }
+
+    /// Converts the path to a value that failed to deserialize into a JSON pointer (RFC 6901).
+    fn json_pointer(path: &serde_path_to_error::Path) -> String {
+        use serde_path_to_error::Segment;
+
+        let mut pointer = String::new();
+        for segment in path {
+            let token = match segment {
+                Segment::Seq { index } => index.to_string(),
+                Segment::Map { key } | Segment::Enum { variant: key } => {
+                    key.replace('~', "~0").replace('/', "~1")
+                }
+                Segment::Unknown => continue,
+            };
+            pointer.push('/');
+            pointer.push_str(&token);
+        }
+
+        pointer
+    }
+
+    /// Serializes the fields of a request's query string directly into the query of `url`.
+    ///
+    /// If no fields are serialized, e.g. because all of them are `None`, `url` is left without a
+    /// query string.
+    pub fn append_query_string<T: Serialize>(
+        url: &mut url::Url,
+        value: T,
+    ) -> Result<(), IntoHttpError> {
+        value.serialize(serde_urlencoded::Serializer::new(&mut url.query_pairs_mut()))?;
+
+        if url.query() == Some("") {
+            url.set_query(None);
+        }
+
+        Ok(())
+    }
+
+    /// Percent-encodes a path parameter for use as a single path segment.
+    pub fn percent_encode_path_segment(value: &str) -> String {
+        crate::encoding::path_segment(value)
+    }
+
+    /// Percent-encodes all characters of a path parameter except for unreserved ones.
+    pub fn percent_encode_component(value: &str) -> String {
+        crate::encoding::component(value)
+    }
+
+    /// Generates an ID for a request that doesn't have one yet, unique within the process and very
+    /// likely unique across processes.
+    pub fn generate_request_id() -> String {
+        use std::{
+            process,
+            sync::atomic::{AtomicUsize, Ordering},
+            time::{SystemTime, UNIX_EPOCH},
+        };
+
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        format!(
+            "{:x}{:08x}-{:x}-{:x}",
+            time.as_secs(),
+            time.subsec_nanos(),
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+
+    /// Sets the `Content-Length` header of a request with a body.
+    pub fn set_content_length(request: &mut http::Request<Vec<u8>>) {
+        if !request.body().is_empty() {
+            let len = http::header::HeaderValue::from(request.body().len());
+            request.headers_mut().insert(http::header::CONTENT_LENGTH, len);
+        }
+    }
+
+    // The following functions hold the parts of the generated conversions that don't depend on
+    // the endpoint, so they are compiled once here instead of once per `ruma_api!` invocation.
+
+    /// Returns the URL the path and query string of a request are written to.
+    ///
+    /// `Url` only supports absolute URLs, so this uses a placeholder host. The whole URL ends up in
+    /// the `http::Request`, placeholder included, and the calling code replaces the scheme and
+    /// host with those of the homeserver URL, e.g. with `try_into_http_request_for`.
+    pub fn placeholder_url() -> url::Url {
+        url::Url::parse("http://invalid-host-please-change/").unwrap()
+    }
+
+    /// Sets the method of a request and its URI to `url`, placeholder host included.
+    pub fn set_method_and_uri(
+        request: &mut http::Request<Vec<u8>>,
+        method: http::Method,
+        url: &url::Url,
+    ) {
+        *request.method_mut() = method;
+        *request.uri_mut() = url.as_str().parse().unwrap();
+    }
+
+    /// Sets a header of a request from a header field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't a valid header value.
+    pub fn insert_header(
+        headers: &mut http::HeaderMap,
+        name: impl http::header::IntoHeaderName,
+        value: &str,
+    ) {
+        let value = http::header::HeaderValue::from_str(value)
+            .expect("failed to convert value into HeaderValue");
+        headers.insert(name, value);
+    }
+
+    /// Adds the values of a header field of a response to its headers, in order.
+    ///
+    /// With `replace`, which is set for the first header field of each name, the values the
+    /// response got when it was created, like its `Content-Type`, are removed first if there are
+    /// any values to add. Later fields of the same name append to those of the first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value isn't a valid header value.
+    pub fn append_header<'a>(
+        headers: &mut http::HeaderMap,
+        name: impl http::header::IntoHeaderName + Clone,
+        values: impl IntoIterator<Item = &'a str>,
+        replace: bool,
+    ) {
+        let mut values = values.into_iter().peekable();
+        if replace && values.peek().is_some() {
+            insert_header(headers, name.clone(), values.next().unwrap());
+        }
+
+        for value in values {
+            let value = http::header::HeaderValue::from_str(value)
+                .expect("failed to convert value into HeaderValue");
+            headers.append(name.clone(), value);
+        }
+    }
+
+    /// Removes all values of a header of a response for a multi-valued header field, in the
+    /// order they were received in.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a header value isn't valid UTF-8.
+    pub fn take_header_values(
+        headers: &mut http::HeaderMap,
+        name: impl http::header::AsHeaderName + Clone,
+    ) -> Vec<String> {
+        let values = headers
+            .get_all(name.clone())
+            .iter()
+            .map(|value| value.to_str().expect("failed to convert HeaderValue to str").to_owned())
+            .collect();
+        headers.remove(name);
+        values
+    }
+
+    /// Adds `extra` headers to a response converted with `try_into_http_response_with`, except
+    /// for those the response already has, like the default headers of requests.
+    pub fn add_extra_headers(headers: &mut http::HeaderMap, extra: http::HeaderMap) {
+        let mut name = None;
+        for (next_name, value) in extra {
+            // Further values of the same header come without a name.
+            if next_name.is_some() {
+                name = next_name.filter(|name| !headers.contains_key(name));
+            }
+
+            if let Some(name) = &name {
+                headers.append(name, value);
+            }
+        }
+    }
+
+    /// Removes a header of a response for a header field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the header value isn't valid UTF-8.
+    pub fn take_header(
+        headers: &mut http::HeaderMap,
+        name: impl http::header::AsHeaderName,
+    ) -> Option<String> {
+        headers
+            .remove(name)
+            .map(|value| value.to_str().expect("failed to convert HeaderValue to str").to_owned())
+    }
+
+    /// Checks that a response has a successful status code and the body length announced in its
+    /// `Content-Length` header, before the response is converted.
+    pub fn check_response<B: AsRef<[u8]>>(
+        response: &http::Response<B>,
+    ) -> Result<(), FromHttpResponseError> {
+        if !response.status().is_success() {
+            return Err(FromHttpResponseError::from_error_response(response));
+        }
+
+        check_content_length(response)
+    }
+
+    /// Returns the error for a response asking for user-interactive authentication, for endpoints
+    /// declaring `uiaa: true`. Other responses are left to `check_response`.
+    pub fn check_uiaa_response<B: AsRef<[u8]>>(
+        response: &http::Response<B>,
+    ) -> Result<(), FromHttpResponseError> {
+        match FromHttpResponseError::from_uiaa_response(response) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Like `check_response`, but attaches the body of an unsuccessful response to the error,
+    /// deserialized as the endpoint's error type `T`.
+    pub fn check_response_as<T, B>(
+        response: &http::Response<B>,
+    ) -> Result<(), FromHttpResponseError>
+    where
+        T: DeserializeOwned + std::fmt::Debug + Send + Sync + 'static,
+        B: AsRef<[u8]>,
+    {
+        if !response.status().is_success() {
+            return Err(FromHttpResponseError::from_error_response_as::<T, _>(response));
+        }
+
+        check_content_length(response)
+    }
+
+    /// Adds the `default_headers` of the global `Config` that aren't set on the request already.
+    pub fn apply_default_headers(request: &mut http::Request<Vec<u8>>) {
+        Config::with_global(|config| {
+            for name in config.default_headers.keys() {
+                if !request.headers().contains_key(name) {
+                    for value in config.default_headers.get_all(name) {
+                        request.headers_mut().append(name, value.clone());
+                    }
+                }
+            }
+        });
+    }
+
+    /// Deserializes the body of a response as JSON.
+    ///
+    /// An empty body is deserialized like `{}`, as servers disagree on which of the two to send
+    /// for responses without (required) fields. On failure, the headers of the response are
+    /// attached to the error.
+    pub fn from_json_response<T: DeserializeOwned, B: AsRef<[u8]>>(
+        response: &http::Response<B>,
+    ) -> Result<T, FromHttpResponseError> {
+        from_json_body(response.body().as_ref())
+            .map_err(|error| error.with_headers(response.headers()))
+    }
+
+    /// Deserializes the body of an incoming request as JSON.
+    ///
+    /// Like for responses, an empty body is deserialized like `{}`. If
+    /// `Config::strict_request_content_type` is set, other bodies have to be declared as JSON.
+    pub fn from_json_request<T: DeserializeOwned, B: AsRef<[u8]>>(
+        request: &http::Request<B>,
+    ) -> Result<T, FromHttpRequestError> {
+        check_json_content_type(request)?;
+        from_json_body(request.body().as_ref()).map_err(|error| FromHttpRequestError(error.0))
+    }
+
+    /// Checks that an incoming request with a non-empty body has a JSON `Content-Type`, if the
+    /// global `Config` asks for it.
+    fn check_json_content_type<B: AsRef<[u8]>>(
+        request: &http::Request<B>,
+    ) -> Result<(), FromHttpRequestError> {
+        if request.body().as_ref().iter().all(u8::is_ascii_whitespace)
+            || !Config::with_global(|config| config.strict_request_content_type)
+        {
+            return Ok(());
+        }
+
+        if is_json_content_type(request.headers()) {
+            Ok(())
+        } else {
+            Err(FromHttpRequestError(InnerError::NotJson(content_type(request.headers()))))
+        }
+    }
+
+    /// Reports a non-empty response body with a `Content-Type` other than `application/json` to
+    /// the `on_content_type_anomaly` hook of the global `Config`, before it's parsed as JSON.
+    pub fn report_content_type_anomaly<B: AsRef<[u8]>>(
+        response: &http::Response<B>,
+        endpoint: &'static str,
+    ) {
+        if response.body().as_ref().iter().all(u8::is_ascii_whitespace)
+            || is_json_content_type(response.headers())
+        {
+            return;
+        }
+
+        if let Some(hook) = Config::with_global(|config| config.on_content_type_anomaly) {
+            hook(ContentTypeAnomaly { endpoint, content_type: content_type(response.headers()) });
+        }
+    }
+
+    /// Whether the `Content-Type` header is `application/json`, with or without parameters.
+    fn is_json_content_type(headers: &http::HeaderMap) -> bool {
+        headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map_or(false, |mime| mime.trim().eq_ignore_ascii_case("application/json"))
+    }
+
+    /// The `Content-Type` header, with invalid UTF-8 replaced.
+    fn content_type(headers: &http::HeaderMap) -> Option<String> {
+        headers
+            .get(http::header::CONTENT_TYPE)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+    }
+
+    /// Deserializes a JSON body, treating an empty body like `{}`.
+    fn from_json_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, FromHttpResponseError> {
+        let body = if body.iter().all(u8::is_ascii_whitespace) { &b"{}"[..] } else { body };
+        from_json_slice(body)
+    }
+
+    /// Deserializes the path parameters of an incoming request, after checking that its path
+    /// matches the endpoint's path template.
+    ///
+    /// If the path doesn't match `template`, the `history` paths of the endpoint are tried in
+    /// order. The parameters named in `verbatim` are taken as they are, all others are
+    /// percent-decoded.
+    /// Segments after the end of the template are rejected, unless
+    /// `Config::strict_request_path` is turned off, and empty segments are skipped if
+    /// `Config::collapse_request_path_slashes` is turned on.
+    pub fn parse_request_path<T: DeserializeOwned, B>(
+        request: &http::Request<B>,
+        template: &str,
+        history: &[&str],
+        verbatim: &[&str],
+    ) -> Result<T, FromHttpRequestError> {
+        let path = request.uri().path();
+        let mismatch = || FromHttpRequestError(InnerError::PathMismatch(path.to_owned()));
+        let segments = std::iter::once(template)
+            .chain(history.iter().cloned())
+            .find_map(|template| crate::match_path(path, template))
+            .ok_or_else(mismatch)?;
+
+        // The parameters are deserialized like a query string, so their values can be parsed as
+        // numbers or identifiers just like query fields.
+        let mut params = url::form_urlencoded::Serializer::new(String::new());
+        for (name, segment) in segments {
+            if verbatim.contains(&name) {
+                params.append_pair(name, segment);
+            } else {
+                let value = percent_encoding::percent_decode_str(segment)
+                    .decode_utf8()
+                    .map_err(|_| mismatch())?;
+                params.append_pair(name, &value);
+            }
+        }
+
+        Ok(serde_urlencoded::from_str(&params.finish())?)
+    }
+
+    /// Checks that the values of the path parameter `name` come out of `parse_request_path` like
+    /// they went into the path `template`, for the tests generated with the
+    /// `path-round-trip-tests` feature.
+    ///
+    /// The values are the samples that deserialize as the field type `T`, including reserved
+    /// characters and Matrix identifiers, encoded with `encode` like when the request URL is
+    /// built. `field` takes the value out of the deserialized path parameters.
+    pub fn assert_path_round_trip<P: DeserializeOwned, T: DeserializeOwned + Display>(
+        template: &str,
+        name: &str,
+        encode: fn(&str) -> String,
+        field: fn(P) -> T,
+    ) {
+        const SAMPLES: &[&str] = &[
+            "value",
+            "42",
+            "with space",
+            "a/b",
+            "?query#fragment",
+            "[]@!$&'()*+,;=",
+            "100%",
+            "%2F",
+            "\u{fc}n\u{ef}c\u{f6}d\u{e9}",
+            "!room:example.org",
+            "@user:example.org",
+            "#alias:example.org",
+            "$event:example.org",
+        ];
+
+        for sample in SAMPLES {
+            let form = url::form_urlencoded::Serializer::new(String::new())
+                .append_pair(name, sample)
+                .finish();
+            let value = match serde_urlencoded::from_str::<Vec<(String, T)>>(&form) {
+                Ok(mut pairs) => pairs.remove(0).1.to_string(),
+                Err(_) => continue,
+            };
+
+            let mut path = String::new();
+            for segment in template.split('/').filter(|segment| !segment.is_empty()) {
+                path.push('/');
+                match segment.strip_prefix(':') {
+                    Some(param) if param == name => path.push_str(&encode(&value)),
+                    Some(_) => path.push('x'),
+                    None => path.push_str(segment),
+                }
+            }
+
+            let request =
+                http::Request::builder().uri(path.as_str()).body(()).unwrap_or_else(|error| {
+                    panic!("`{}` = {:?}: invalid path: {}", name, value, error)
+                });
+            let param = parse_request_path::<P, _>(&request, template, &[], &[])
+                .unwrap_or_else(|error| panic!("`{}` = {:?}: {}", name, value, error));
+            assert_eq!(field(param).to_string(), value, "path `{}`", path);
+        }
+    }
+
+    /// Deserializes the query string of an incoming request.
+    ///
+    /// If `Config::case_insensitive_query_keys` is turned on, keys matching one of `names` except
+    /// for their case are replaced by that name first.
+    pub fn parse_query_string<T: DeserializeOwned, B>(
+        request: &http::Request<B>,
+        names: &[&str],
+    ) -> Result<T, FromHttpRequestError> {
+        let query = request.uri().query().unwrap_or("");
+        if names.is_empty() || !Config::with_global(|config| config.case_insensitive_query_keys) {
+            return Ok(serde_urlencoded::from_str(query)?);
+        }
+
+        let pairs = url::form_urlencoded::parse(query.as_bytes()).map(|(key, value)| {
+            match names.iter().find(|name| name.eq_ignore_ascii_case(&key)) {
+                Some(name) => (Cow::Borrowed(*name), value),
+                None => (key, value),
+            }
+        });
+        let query =
+            url::form_urlencoded::Serializer::new(String::new()).extend_pairs(pairs).finish();
+        Ok(serde_urlencoded::from_str(&query)?)
+    }
+
+    /// Reads a header of an incoming request for an optional header field.
+    pub fn get_header(
+        headers: &http::HeaderMap,
+        name: impl AsRef<str>,
+    ) -> Result<Option<String>, FromHttpRequestError> {
+        let name = name.as_ref();
+
+        match headers.get(name) {
+            Some(value) => match value.to_str() {
+                Ok(value) => Ok(Some(value.to_owned())),
+                Err(_) => Err(FromHttpRequestError(InnerError::InvalidHeader(name.to_owned()))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Reads a header of an incoming request for a required header field.
+    pub fn get_required_header(
+        headers: &http::HeaderMap,
+        name: impl AsRef<str>,
+    ) -> Result<String, FromHttpRequestError> {
+        let name = name.as_ref();
+
+        get_header(headers, name)?
+            .ok_or_else(|| FromHttpRequestError(InnerError::MissingHeader(name.to_owned())))
+    }
+
+    /// Like `from_json_request`, but decompresses the body first if the request has a
+    /// `Content-Encoding: gzip` header.
+    #[cfg(feature = "gzip")]
+    pub fn from_gzip_json_request<T: DeserializeOwned, B: AsRef<[u8]>>(
+        request: &http::Request<B>,
+    ) -> Result<T, FromHttpRequestError> {
+        use std::io::Read as _;
+
+        use flate2::read::GzDecoder;
+
+        let is_gzip = request
+            .headers()
+            .get(http::header::CONTENT_ENCODING)
+            .map_or(false, |encoding| encoding == "gzip");
+        if !is_gzip {
+            return from_json_request(request);
+        }
+
+        check_json_content_type(request)?;
+
+        let max_size = Config::with_global(|config| config.max_request_body_size);
+        let mut decoder = GzDecoder::new(request.body().as_ref());
+        let mut body = Vec::new();
+        match max_size {
+            // Read one byte more than allowed to tell a body of exactly the maximum size from a
+            // larger one.
+            Some(max_size) => {
+                decoder.take(max_size as u64 + 1).read_to_end(&mut body)?;
+                if body.len() > max_size {
+                    return Err(FromHttpRequestError(InnerError::RequestTooLarge(max_size)));
+                }
+            }
+            None => {
+                decoder.read_to_end(&mut body)?;
+            }
+        }
+
+        from_json_body(&body).map_err(|error| FromHttpRequestError(error.0))
+    }
+
+    /// Creates a request with the given value as its JSON body and a matching `Content-Type`.
+    ///
+    /// Header fields of the endpoint are set afterwards, so a `Content-Type` header field still
+    /// takes precedence.
+    pub fn json_request<T: Serialize>(body: &T) -> Result<http::Request<Vec<u8>>, IntoHttpError> {
+        let mut request = http::Request::new(to_json_vec(body)?);
+        request.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/json"),
+        );
+
+        Ok(request)
+    }
+
+    /// Creates a response with the given value as its JSON body and a matching `Content-Type`.
+    pub fn json_response<T: Serialize>(body: &T) -> Result<http::Response<Vec<u8>>, IntoHttpError> {
+        let mut response = http::Response::new(to_json_vec(body)?);
+        response.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/json"),
+        );
+
+        Ok(response)
+    }
+
+    /// Creates a response with an empty JSON object as its body, for responses without body
+    /// fields.
+    pub fn empty_json_response() -> http::Response<Vec<u8>> {
+        json_response(&serde_json::Map::new()).expect("failed to serialize an empty object")
+    }
+
+    /// Creates the error for a request body that failed to serialize with a function given in
+    /// `#[ruma_api(manual_body = ...)]`.
+    pub fn manual_request_body_error<E>(error: E) -> IntoHttpError
+    where
+        E: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        IntoHttpError(InnerError::ManualBody(error.into()))
+    }
+
+    /// Creates the error for a response body that failed to deserialize with a function given in
+    /// `#[ruma_api(manual_body = ...)]`.
+    pub fn manual_response_body_error<E>(
+        error: E,
+        headers: &http::HeaderMap,
+    ) -> FromHttpResponseError
+    where
+        E: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        FromHttpResponseError(InnerError::ManualBody(error.into())).with_headers(headers)
+    }
+
+    /// Attaches the name of the endpoint to an error deserializing the newtype body field of an
+    /// incoming request.
+    pub fn newtype_request_body_error(
+        error: FromHttpRequestError,
+        endpoint: &'static str,
+    ) -> FromHttpRequestError {
+        FromHttpRequestError(InnerError::NewtypeBody(endpoint, Box::new(error.0)))
+    }
+
+    /// Creates the error for a response rejected by the validator of the given field, or of the
+    /// whole response.
+    pub fn validation_error<E: Display>(
+        field: Option<&'static str>,
+        error: E,
+        headers: &http::HeaderMap,
+    ) -> FromHttpResponseError {
+        FromHttpResponseError(InnerError::Validation(field, error.to_string()))
+            .with_headers(headers)
+    }
+
+    /// Checks that the body of a response has the length announced in its `Content-Length`
+    /// header, if it has one.
+    ///
+    /// This way, bodies truncated or otherwise mangled on their way are reported as such instead
+    /// of as a confusing JSON error. Nothing is checked unless `strict_content_length` is enabled
+    /// in the global `Config`, or if the body has a `Content-Encoding`.
+    pub fn check_content_length<B: AsRef<[u8]>>(
+        response: &http::Response<B>,
+    ) -> Result<(), FromHttpResponseError> {
+        if !Config::with_global(|config| config.strict_content_length)
+            || response.headers().contains_key(http::header::CONTENT_ENCODING)
+        {
+            return Ok(());
+        }
+
+        let expected = response
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok());
+
+        let actual = response.body().as_ref().len();
+        match expected {
+            Some(expected) if expected != actual as u64 => {
+                let error = InnerError::ContentLength(expected, actual);
+                Err(FromHttpResponseError(error).with_headers(response.headers()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Compresses the body of a request with gzip and sets the `Content-Encoding` header
+    /// accordingly.
+    #[cfg(feature = "gzip")]
+    pub fn gzip_request_body(request: &mut http::Request<Vec<u8>>) -> Result<(), IntoHttpError> {
+        use std::io::Write as _;
+
+        use flate2::{write::GzEncoder, Compression};
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(request.body())?;
+        *request.body_mut() = encoder.finish()?;
+        request
+            .headers_mut()
+            .insert(http::header::CONTENT_ENCODING, http::header::HeaderValue::from_static("gzip"));
+
+        Ok(())
+    }
 }
 
+/// The name of the header correlating a request and its response, as set by
+/// `#[ruma_api(request_id)]` fields.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
 /// A Matrix API endpoint.
 ///
 /// The type implementing this trait contains any data needed to make a request to the endpoint.
-pub trait Endpoint: TryInto<http::Request<Vec<u8>>, Error = Error> {
-    /// Data returned in a successful response from the endpoint.
-    type Response: TryFrom<http::Response<Vec<u8>>, Error = Error>;
-
+/// This trait only describes the endpoint, the conversions to and from `http` types are in
+/// `OutgoingRequest` for clients and `IncomingRequest` for servers.
+pub trait Endpoint {
     /// Metadata about the endpoint.
     const METADATA: Metadata;
+
+    /// The same as `METADATA.name`, but usable in constants and patterns, e.g. for metrics labels.
+    ///
+    /// Fields of `METADATA` can't be read in constants, as `Metadata` has a destructor.
+    const NAME: &'static str;
+
+    /// The same as `METADATA.path`, but usable in constants and patterns.
+    const PATH: &'static str;
+
+    /// Whether requests to the endpoint are safe in the sense of RFC 7231, i.e. essentially
+    /// read-only. This is the case for `GET`, `HEAD`, `OPTIONS` and `TRACE` requests.
+    ///
+    /// Responses to safe requests can be cached, and failed safe requests can be retried.
+    const IS_SAFE: bool;
+
+    /// Whether requests to the endpoint are idempotent in the sense of RFC 7231, i.e. sending the
+    /// same request multiple times has the same effect as sending it once. This is the case for
+    /// safe requests as well as `PUT` and `DELETE` requests.
+    ///
+    /// Failed idempotent requests can be retried.
+    const IS_IDEMPOTENT: bool;
+
+    /// The segments of `METADATA.path`, so routers can match request paths against the endpoint
+    /// without splitting the path template at runtime.
+    const PATH_SEGMENTS: &'static [PathSegment];
+
+    /// The names of the path parameters in `METADATA.path`, in the order they appear in the path.
+    const PATH_PARAM_NAMES: &'static [&'static str];
+
+    /// An example of a request body for this endpoint, as JSON.
+    ///
+    /// This is meant for documentation and for mock servers. Endpoints generated by `ruma_api!`
+    /// set it from the `examples` section of the macro.
+    const EXAMPLE_REQUEST_BODY: Option<&'static str> = None;
+
+    /// An example of a successful response body for this endpoint, as JSON, e.g. to be returned by
+    /// mock servers by default.
+    const EXAMPLE_RESPONSE_BODY: Option<&'static str> = None;
 }
 
-/// An error when converting an `Endpoint` request or response to the corresponding type from the
-/// `http` crate.
-#[derive(Debug)]
-pub struct Error(pub(crate) InnerError);
+/// A request to an endpoint as sent by a client, which converts it to an `http::Request` and the
+/// `http::Response` it gets back to the endpoint's response type.
+pub trait OutgoingRequest:
+    Endpoint + TryInto<http::Request<Vec<u8>>, Error = IntoHttpError>
+{
+    /// Data returned in a successful response from the endpoint.
+    type IncomingResponse: TryFrom<http::Response<Vec<u8>>, Error = FromHttpResponseError>;
 
-impl Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        let message = match self.0 {
-            InnerError::Http(_) => "An error converting to or from `http` types occurred.".into(),
-            InnerError::Io(_) => "An I/O error occurred.".into(),
-            InnerError::SerdeJson(_) => "A JSON error occurred.".into(),
-            InnerError::SerdeUrlEncodedDe(_) => {
-                "A URL encoding deserialization error occurred.".into()
-            }
-            InnerError::SerdeUrlEncodedSer(_) => {
-                "A URL encoding serialization error occurred.".into()
-            }
-            InnerError::RumaIdentifiers(_) => "A ruma-identifiers error occurred.".into(),
-            InnerError::StatusCode(code) => format!("A HTTP {} error occurred.", code),
-        };
+    /// Converts the request to an `http::Request` and lets `signer` sign it.
+    fn try_into_signed_http_request(
+        self,
+        signer: &dyn RequestSigner,
+    ) -> Result<http::Request<Vec<u8>>, IntoHttpError> {
+        let mut http_request = self.try_into()?;
+        signer
+            .sign(&Self::METADATA, &mut http_request)
+            .map_err(|error| IntoHttpError(InnerError::Signing(error)))?;
 
-        write!(f, "{}", message)
+        Ok(http_request)
+    }
+
+    /// Converts the request to an `http::Request` for the homeserver at `base_url`, e.g.
+    /// `https://matrix.example.org:8448`.
+    ///
+    /// The placeholder host of the converted request is replaced by the scheme, authority and
+    /// path of `base_url`, and the `Host` header is set to the host and port of the same URL
+    /// unless the request sets it itself. HTTP clients like hyper or reqwest take care of both
+    /// on their own, but clients that write requests to a socket directly need them.
+    fn try_into_http_request_for(
+        self,
+        base_url: &str,
+    ) -> Result<http::Request<Vec<u8>>, IntoHttpError> {
+        let mut http_request = self.try_into()?;
+
+        let path_and_query = http_request.uri().path_and_query().map_or("/", |p| p.as_str());
+        let uri = format!("{}{}", base_url.trim_end_matches('/'), path_and_query);
+        let invalid_uri = || IntoHttpError(InnerError::InvalidUri(uri.clone()));
+        let parsed_uri: http::Uri = uri.parse().map_err(|_| invalid_uri())?;
+        let authority = parsed_uri.authority_part().ok_or_else(invalid_uri)?;
+
+        if !http_request.headers().contains_key(http::header::HOST) {
+            // The authority may contain user info, which doesn't belong in `Host`.
+            let host = match authority.port_u16() {
+                Some(port) => format!("{}:{}", authority.host(), port),
+                None => authority.host().to_owned(),
+            };
+            let host = http::header::HeaderValue::from_str(&host).map_err(|_| invalid_uri())?;
+            http_request.headers_mut().insert(http::header::HOST, host);
+        }
+        *http_request.uri_mut() = parsed_uri;
+
+        Ok(http_request)
     }
 }
 
-impl StdError for Error {}
+/// A request to an endpoint as received by a server, which converts it from an `http::Request` and
+/// the endpoint's response type to the `http::Response` it sends back.
+///
+/// Endpoints generated by `ruma_api!` implement this trait unless the request has a
+/// `manual_body` field or the response has a `manual_body`, `json_stream` or `multipart` field,
+/// which can only be converted in one direction.
+pub trait IncomingRequest:
+    Endpoint + TryFrom<http::Request<Vec<u8>>, Error = FromHttpRequestError>
+{
+    /// Data sent in a successful response from the endpoint.
+    type OutgoingResponse: TryInto<http::Response<Vec<u8>>, Error = IntoHttpError>;
 
-/// Internal representation of errors.
-#[derive(Debug)]
-pub(crate) enum InnerError {
-    /// An HTTP error.
-    Http(http::Error),
+    /// The names of the query string parameters of the endpoint's query fields, or `None` if it
+    /// accepts parameters whose names aren't known ahead, through a query map or flattened query
+    /// fields.
+    const QUERY_PARAMS: Option<&'static [&'static str]>;
 
-    /// A I/O error.
-    Io(io::Error),
+    /// Returns the percent-decoded keys in the query string of a request to the endpoint that
+    /// aren't consumed by one of its query fields, in the order they appear, e.g. so strict
+    /// servers can reject unknown parameters and lenient ones can log them.
+    ///
+    /// Nothing is returned if the `QUERY_PARAMS` of the endpoint are `None`. With
+    /// `Config::case_insensitive_query_keys`, keys are compared regardless of their case, like
+    /// when the request is converted.
+    fn unknown_query_keys<B>(request: &http::Request<B>) -> Vec<String> {
+        let names = match Self::QUERY_PARAMS {
+            Some(names) => names,
+            None => return Vec::new(),
+        };
+        let pairs: Vec<(String, String)> =
+            serde_urlencoded::from_str(request.uri().query().unwrap_or("")).unwrap_or_default();
+        let ignore_case = Config::with_global(|config| config.case_insensitive_query_keys);
+
+        pairs
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| {
+                !names.iter().any(|name| {
+                    if ignore_case {
+                        name.eq_ignore_ascii_case(key)
+                    } else {
+                        name == key
+                    }
+                })
+            })
+            .collect()
+    }
 
-    /// A Serde JSON error.
-    SerdeJson(serde_json::Error),
+    /// Checks the path, query string and header fields of a request to the endpoint, without
+    /// looking at its body.
+    ///
+    /// These are the same checks the conversion from an `http::Request` starts with.
+    fn check_head<B>(request: &http::Request<B>) -> Result<(), FromHttpRequestError>;
 
-    /// A Serde URL decoding error.
-    SerdeUrlEncodedDe(serde_urlencoded::de::Error),
+    /// Checks the head of a request to the endpoint before its body is read.
+    ///
+    /// Async servers can authenticate or rate-limit the returned `PartialRequest` before reading,
+    /// and allocating for, the body, which is then parsed with `PartialRequest::finish`.
+    fn parse_head(parts: http::request::Parts) -> Result<PartialRequest<Self>, FromHttpRequestError>
+    where
+        Self: Sized,
+    {
+        let head = http::Request::from_parts(parts, ());
+        Self::check_head(&head)?;
+        Ok(PartialRequest::new(head))
+    }
 
-    /// A Serde URL encoding error.
-    SerdeUrlEncodedSer(serde_urlencoded::ser::Error),
+    /// Checks the head of a borrowed request to the endpoint without consuming it or requiring a
+    /// body, e.g. for middleware classifying requests before deciding whether to buffer the body.
+    ///
+    /// The returned `PartialRequest` has a copy of the method, URI, version and headers of the
+    /// request, but not of its extensions.
+    fn try_from_http_request_ref<B>(
+        request: &http::Request<B>,
+    ) -> Result<PartialRequest<Self>, FromHttpRequestError>
+    where
+        Self: Sized,
+    {
+        Self::check_head(request)?;
 
-    /// A Ruma Identitifiers error.
-    RumaIdentifiers(ruma_identifiers::Error),
+        let mut head = http::Request::new(());
+        *head.method_mut() = request.method().clone();
+        *head.uri_mut() = request.uri().clone();
+        *head.version_mut() = request.version();
+        *head.headers_mut() = request.headers().clone();
+        Ok(PartialRequest::new(head))
+    }
 
-    /// An HTTP status code indicating error.
-    StatusCode(StatusCode),
-}
+    /// Converts a request to the endpoint from its method, URI, headers and body, e.g. in tests or
+    /// simple servers that don't assemble an `http::Request` first.
+    ///
+    /// `uri` is usually just the path and query string, like `/_matrix/client/r0/sync?since=s1`.
+    fn try_from_parts(
+        method: Method,
+        uri: &str,
+        headers: http::HeaderMap,
+        body: Vec<u8>,
+    ) -> Result<Self, FromHttpRequestError>
+    where
+        Self: Sized,
+    {
+        let uri = uri
+            .parse::<http::Uri>()
+            .map_err(|_| FromHttpRequestError(InnerError::InvalidUri(uri.to_owned())))?;
 
-impl From<http::Error> for Error {
-    fn from(error: http::Error) -> Self {
-        Self(InnerError::Http(error))
+        let mut request = http::Request::new(body);
+        *request.method_mut() = method;
+        *request.uri_mut() = uri;
+        *request.headers_mut() = headers;
+        Self::try_from(request)
     }
 }
 
-impl From<io::Error> for Error {
-    fn from(error: io::Error) -> Self {
-        Self(InnerError::Io(error))
-    }
+/// Signs outgoing requests once they are converted to `http::Request`s, e.g. for the `X-Matrix`
+/// authorization of server-server requests.
+///
+/// The method, path, query string, headers and body of the request are final when it is signed.
+/// Only the host of its URI is a placeholder, which is replaced when the request is sent.
+pub trait RequestSigner {
+    /// Signs the request, usually by adding a header.
+    fn sign(
+        &self,
+        metadata: &Metadata,
+        request: &mut http::Request<Vec<u8>>,
+    ) -> Result<(), Box<dyn StdError + Send + Sync>>;
 }
 
-impl From<serde_json::Error> for Error {
-    fn from(error: serde_json::Error) -> Self {
-        Self(InnerError::SerdeJson(error))
-    }
+/// A converter from an `http::Response` to the type-erased response of an endpoint, as returned by
+/// `AnyEndpoint::response_converter`.
+pub type ResponseConverter =
+    fn(http::Response<Vec<u8>>) -> Result<Box<dyn Any>, FromHttpResponseError>;
+
+/// A dyn-compatible view of an `OutgoingRequest`, so requests to different endpoints can be held
+/// in one collection or dispatch table.
+///
+/// This trait is implemented for all `OutgoingRequest`s.
+pub trait AnyEndpoint {
+    /// The same as `Endpoint::NAME`.
+    fn name(&self) -> &'static str;
+
+    /// The same as `Endpoint::METADATA`.
+    fn metadata(&self) -> Metadata;
+
+    /// Converts the request to an `http::Request`.
+    fn try_into_http_request(self: Box<Self>) -> Result<http::Request<Vec<u8>>, IntoHttpError>;
+
+    /// Returns the function converting an `http::Response` to the endpoint's `Response`, boxed as
+    /// `dyn Any`.
+    ///
+    /// The function doesn't borrow the request, so it can be obtained before the request is
+    /// converted and used once the response arrives.
+    fn response_converter(&self) -> ResponseConverter;
 }
 
-impl From<serde_urlencoded::de::Error> for Error {
-    fn from(error: serde_urlencoded::de::Error) -> Self {
-        Self(InnerError::SerdeUrlEncodedDe(error))
+impl<E> AnyEndpoint for E
+where
+    E: OutgoingRequest,
+    E::IncomingResponse: 'static,
+{
+    fn name(&self) -> &'static str {
+        E::NAME
     }
-}
 
-impl From<serde_urlencoded::ser::Error> for Error {
-    fn from(error: serde_urlencoded::ser::Error) -> Self {
-        Self(InnerError::SerdeUrlEncodedSer(error))
+    fn metadata(&self) -> Metadata {
+        E::METADATA
     }
-}
 
-impl From<ruma_identifiers::Error> for Error {
-    fn from(error: ruma_identifiers::Error) -> Self {
-        Self(InnerError::RumaIdentifiers(error))
+    fn try_into_http_request(self: Box<Self>) -> Result<http::Request<Vec<u8>>, IntoHttpError> {
+        (*self).try_into()
     }
-}
 
-impl From<StatusCode> for Error {
-    fn from(error: StatusCode) -> Self {
-        Self(InnerError::StatusCode(error))
+    fn response_converter(&self) -> ResponseConverter {
+        |http_response| {
+            E::IncomingResponse::try_from(http_response)
+                .map(|response| Box::new(response) as Box<dyn Any>)
+        }
     }
 }
 
@@ -168,6 +1058,289 @@ pub struct Metadata {
 
     /// Whether or not the server requires an authenticated user for this endpoint.
     pub requires_authentication: bool,
+
+    /// Where the access token is sent for endpoints that require authentication.
+    pub access_token_placement: AccessTokenPlacement,
+
+    /// Whether the request body is compressed with gzip and sent with a `Content-Encoding: gzip`
+    /// header, for endpoints with large request bodies.
+    pub compress_request_body: bool,
+
+    /// Earlier paths of this endpoint, with the same path parameters as `path`, which servers
+    /// still accept for incoming requests. Clients always use `path`.
+    pub history_paths: &'static [&'static str],
+
+    /// The identifier of the Matrix spec change (MSC) this endpoint is an unstable implementation
+    /// of, e.g. `org.matrix.msc3575`.
+    ///
+    /// Clients can look it up in the `unstable_features` map of the server's `/versions` response
+    /// before calling the endpoint.
+    pub unstable_feature: Option<&'static str>,
+}
+
+/// Two `Metadata` values are equal if they have the same name, method and path, so they can be used
+/// as keys identifying endpoints, e.g. in routing tables or metrics. The other fields are
+/// descriptive and ignored.
+impl PartialEq for Metadata {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.method == other.method && self.path == other.path
+    }
+}
+
+impl Eq for Metadata {}
+
+impl Hash for Metadata {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.method.hash(state);
+        self.path.hash(state);
+    }
+}
+
+/// `Metadata` is ordered by path, then method, then name, so sorted lists of endpoints group the
+/// methods of a path together, e.g. when dumping the endpoint inventory of a crate.
+impl Ord for Metadata {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.path, self.method.as_str(), self.name).cmp(&(
+            other.path,
+            other.method.as_str(),
+            other.name,
+        ))
+    }
+}
+
+impl PartialOrd for Metadata {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `Metadata` is serialized as a map of all its fields, with the method as a string, so external
+/// tools can diff the endpoints of two versions of a crate for breaking changes.
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Metadata {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ::serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Metadata", 10)?;
+        state.serialize_field("description", self.description)?;
+        state.serialize_field("method", self.method.as_str())?;
+        state.serialize_field("name", self.name)?;
+        state.serialize_field("path", self.path)?;
+        state.serialize_field("rate_limited", &self.rate_limited)?;
+        state.serialize_field("requires_authentication", &self.requires_authentication)?;
+        state.serialize_field("access_token_placement", &self.access_token_placement)?;
+        state.serialize_field("compress_request_body", &self.compress_request_body)?;
+        state.serialize_field("history_paths", self.history_paths)?;
+        state.serialize_field("unstable_feature", &self.unstable_feature)?;
+        state.end()
+    }
+}
+
+/// Where the access token of an authenticated request is sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AccessTokenPlacement {
+    /// In the `Authorization` header, as `Bearer <token>`. This is what most endpoints use.
+    AuthorizationHeader,
+
+    /// In the `access_token` query parameter, as required by some older endpoints and parts of
+    /// the application service API.
+    QueryString,
+}
+
+impl Metadata {
+    /// Creates metadata for an endpoint, using the defaults for all other fields.
+    ///
+    /// Hand-written endpoints and frameworks registering endpoints at runtime should prefer this
+    /// over a struct literal, so they keep compiling when optional fields are added to
+    /// `Metadata`. The other fields can be set with the `with_*` methods:
+    ///
+    /// ```
+    /// # use ruma_api::{AccessTokenPlacement, Metadata};
+    /// let metadata = Metadata::new(
+    ///     "Register an application service user.",
+    ///     http::Method::POST,
+    ///     "register",
+    ///     "/_matrix/client/r0/register",
+    ///     false,
+    ///     true,
+    /// )
+    /// .with_access_token_placement(AccessTokenPlacement::QueryString);
+    /// ```
+    ///
+    /// Unlike the `with_*` methods, this function can be used in constants such as
+    /// `Endpoint::METADATA`.
+    pub const fn new(
+        description: &'static str,
+        method: Method,
+        name: &'static str,
+        path: &'static str,
+        rate_limited: bool,
+        requires_authentication: bool,
+    ) -> Self {
+        Self {
+            description,
+            method,
+            name,
+            path,
+            rate_limited,
+            requires_authentication,
+            access_token_placement: AccessTokenPlacement::AuthorizationHeader,
+            compress_request_body: false,
+            history_paths: &[],
+            unstable_feature: None,
+        }
+    }
+
+    /// Sets where the access token is sent. Defaults to the `Authorization` header.
+    pub fn with_access_token_placement(mut self, placement: AccessTokenPlacement) -> Self {
+        self.access_token_placement = placement;
+        self
+    }
+
+    /// Sets whether the request body is compressed with gzip. Defaults to `false`.
+    pub fn with_compress_request_body(mut self, compress: bool) -> Self {
+        self.compress_request_body = compress;
+        self
+    }
+
+    /// Sets the earlier paths of this endpoint that servers still accept. Defaults to none.
+    pub fn with_history_paths(mut self, paths: &'static [&'static str]) -> Self {
+        self.history_paths = paths;
+        self
+    }
+
+    /// Sets the identifier of the Matrix spec change this endpoint is an unstable implementation
+    /// of. Defaults to `None`.
+    ///
+    /// Unlike the `unstable_feature` field of `ruma_api!`, this doesn't change the path.
+    pub fn with_unstable_feature(mut self, feature: &'static str) -> Self {
+        self.unstable_feature = Some(feature);
+        self
+    }
+
+    /// Whether a server advertising the given `unstable_features` (as in its `/versions`
+    /// response) supports this endpoint.
+    ///
+    /// Always `true` for endpoints without an unstable feature.
+    pub fn is_supported_by(&self, unstable_features: &BTreeMap<String, bool>) -> bool {
+        match self.unstable_feature {
+            Some(feature) => unstable_features.get(feature).cloned().unwrap_or(false),
+            None => true,
+        }
+    }
+
+    /// Whether a request path matches the path of this endpoint or one of its `history_paths`,
+    /// e.g. for routing requests.
+    ///
+    /// This is the same check the conversion of an incoming request does, so it depends on
+    /// `Config::strict_request_path` and `Config::collapse_request_path_slashes` as well.
+    pub fn matches_path(&self, path: &str) -> bool {
+        std::iter::once(self.path)
+            .chain(self.history_paths.iter().cloned())
+            .any(|template| match_path(path, template).is_some())
+    }
+
+    /// Builds a URL for this endpoint without constructing a typed request, e.g. for link
+    /// generation or logging.
+    ///
+    /// `path_args` are substituted for the path parameters in the order they appear in `path`,
+    /// and `query` is appended as the query string. Both are percent-encoded.
+    ///
+    /// Fails if the number of `path_args` doesn't match the number of path parameters.
+    pub fn make_endpoint_url(
+        &self,
+        base_url: &str,
+        path_args: &[&dyn Display],
+        query: &[(&str, &str)],
+    ) -> Result<String, IntoHttpError> {
+        let segments = self.path.split('/').filter(|segment| !segment.is_empty());
+        let param_count = segments.clone().filter(|segment| segment.starts_with(':')).count();
+        if param_count != path_args.len() {
+            return Err(IntoHttpError(InnerError::PathArgs(param_count, path_args.len())));
+        }
+
+        let mut url = base_url.trim_end_matches('/').to_owned();
+        let mut path_args = path_args.iter();
+
+        for segment in segments {
+            url.push('/');
+
+            if segment.starts_with(':') {
+                let arg = path_args.next().expect("path arguments were counted");
+                url.push_str(&encoding::path_segment(&arg.to_string()));
+            } else {
+                url.push_str(segment);
+            }
+        }
+
+        for (i, (key, value)) in query.iter().enumerate() {
+            url.push(if i == 0 { '?' } else { '&' });
+            url.push_str(&encoding::component(key));
+            url.push('=');
+            url.push_str(&encoding::component(value));
+        }
+
+        Ok(url)
+    }
+}
+
+/// Matches a request path against a path template, returning the segments of the path filled in
+/// for the parameters of the template, by name and still percent-encoded.
+///
+/// Segments after the end of the template are rejected, unless `Config::strict_request_path` is
+/// turned off, and empty segments are skipped if `Config::collapse_request_path_slashes` is turned
+/// on.
+fn match_path<'a>(path: &'a str, template: &'a str) -> Option<Vec<(&'a str, &'a str)>> {
+    let mut params = Vec::new();
+    let collapse_slashes = Config::with_global(|config| config.collapse_request_path_slashes);
+    // The first segment is the empty one before the leading slash, which is always kept.
+    let mut segments = path
+        .split('/')
+        .enumerate()
+        .filter(|&(i, segment)| i == 0 || !collapse_slashes || !segment.is_empty())
+        .map(|(_, segment)| segment);
+    for expected in template.split('/') {
+        let segment = segments.next()?;
+
+        if let Some(name) = expected.strip_prefix(':') {
+            params.push((name, segment));
+        } else if segment != expected {
+            return None;
+        }
+    }
+
+    if segments.next().is_some() && Config::with_global(|config| config.strict_request_path) {
+        return None;
+    }
+
+    Some(params)
+}
+
+/// A segment of an endpoint's path template.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    /// A segment that has to appear verbatim in the path, e.g. `directory` in
+    /// `/_matrix/client/r0/directory/room/:room_alias`.
+    Literal(&'static str),
+
+    /// A segment that is filled in with a path parameter, e.g. `room_alias` in
+    /// `/_matrix/client/r0/directory/room/:room_alias`.
+    Parameter(&'static str),
+}
+
+impl PathSegment {
+    /// Whether the given (percent-encoded) segment of a request path matches this segment.
+    ///
+    /// Parameters match any segment that isn't empty.
+    pub fn matches(&self, segment: &str) -> bool {
+        match *self {
+            PathSegment::Literal(literal) => literal == segment,
+            PathSegment::Parameter(_) => !segment.is_empty(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -181,7 +1354,9 @@ mod tests {
         use serde::{Deserialize, Serialize};
         use serde_json;
 
-        use crate::{Endpoint, Error, Metadata};
+        use crate::{
+            Endpoint, FromHttpResponseError, IntoHttpError, Metadata, OutgoingRequest, PathSegment,
+        };
 
         /// A request to create a new room alias.
         #[derive(Debug)]
@@ -191,20 +1366,39 @@ mod tests {
         }
 
         impl Endpoint for Request {
-            type Response = Response;
-
-            const METADATA: Metadata = Metadata {
-                description: "Add an alias to a room.",
-                method: Method::PUT,
-                name: "create_alias",
-                path: "/_matrix/client/r0/directory/room/:room_alias",
-                rate_limited: false,
-                requires_authentication: true,
-            };
+            const METADATA: Metadata = Metadata::new(
+                "Add an alias to a room.",
+                Method::PUT,
+                "create_alias",
+                "/_matrix/client/r0/directory/room/:room_alias",
+                false,
+                true,
+            );
+
+            const NAME: &'static str = "create_alias";
+            const PATH: &'static str = "/_matrix/client/r0/directory/room/:room_alias";
+
+            const IS_SAFE: bool = false;
+            const IS_IDEMPOTENT: bool = true;
+
+            const PATH_SEGMENTS: &'static [PathSegment] = &[
+                PathSegment::Literal("_matrix"),
+                PathSegment::Literal("client"),
+                PathSegment::Literal("r0"),
+                PathSegment::Literal("directory"),
+                PathSegment::Literal("room"),
+                PathSegment::Parameter("room_alias"),
+            ];
+
+            const PATH_PARAM_NAMES: &'static [&'static str] = &["room_alias"];
+        }
+
+        impl OutgoingRequest for Request {
+            type IncomingResponse = Response;
         }
 
         impl TryFrom<Request> for http::Request<Vec<u8>> {
-            type Error = Error;
+            type Error = IntoHttpError;
 
             fn try_from(request: Request) -> Result<http::Request<Vec<u8>>, Self::Error> {
                 let metadata = Request::METADATA;
@@ -219,7 +1413,7 @@ mod tests {
                 let http_request = http::Request::builder()
                     .method(metadata.method)
                     .uri(path)
-                    .body(serde_json::to_vec(&request_body).map_err(Error::from)?)?;
+                    .body(serde_json::to_vec(&request_body).map_err(IntoHttpError::from)?)?;
 
                 Ok(http_request)
             }
@@ -235,7 +1429,7 @@ mod tests {
         pub struct Response;
 
         impl TryFrom<http::Response<Vec<u8>>> for Response {
-            type Error = Error;
+            type Error = FromHttpResponseError;
 
             fn try_from(http_response: http::Response<Vec<u8>>) -> Result<Response, Self::Error> {
                 if http_response.status().is_success() {
@@ -246,4 +1440,191 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn metadata_eq_and_hash() {
+        use std::collections::HashMap;
+
+        use http::Method;
+
+        use crate::{Endpoint, Metadata};
+
+        let metadata = create::Request::METADATA;
+        let same_endpoint = Metadata { description: "Creates an alias.", ..metadata.clone() };
+        let other_method = Metadata { method: Method::DELETE, ..metadata.clone() };
+
+        assert_eq!(metadata, same_endpoint);
+        assert_ne!(metadata, other_method);
+
+        let mut request_counts = HashMap::new();
+        *request_counts.entry(metadata).or_insert(0) += 1;
+        *request_counts.entry(same_endpoint).or_insert(0) += 1;
+        *request_counts.entry(other_method).or_insert(0) += 1;
+        assert_eq!(request_counts.len(), 2);
+        assert_eq!(request_counts[&create::Request::METADATA], 2);
+    }
+
+    #[test]
+    fn make_endpoint_url() {
+        use crate::Endpoint;
+
+        let metadata = create::Request::METADATA;
+
+        assert_eq!(
+            metadata
+                .make_endpoint_url("https://example.org/", &[&"#alias:example.org"], &[])
+                .unwrap(),
+            "https://example.org/_matrix/client/r0/directory/room/%23alias:example.org"
+        );
+        assert_eq!(
+            metadata
+                .make_endpoint_url(
+                    "https://example.org",
+                    &[&"#alias/x:example.org"],
+                    &[("limit", "10"), ("filter", "a b&c")]
+                )
+                .unwrap(),
+            "https://example.org/_matrix/client/r0/directory/room/%23alias%2Fx:example.org\
+             ?limit=10&filter=a%20b%26c"
+        );
+    }
+
+    #[test]
+    fn json_pointer_of_deserialization_error() {
+        use std::collections::BTreeMap;
+
+        let body = br#"{"rooms":{"a/b~c":[1, "two"]}}"#;
+        let error =
+            crate::exports::from_json_slice::<BTreeMap<String, BTreeMap<String, Vec<u8>>>>(body)
+                .unwrap_err();
+
+        assert_eq!(error.json_pointer(), Some("/rooms/a~1b~0c/1"));
+    }
+
+    #[test]
+    fn metadata_order_and_serialization() {
+        use serde_json::json;
+
+        use crate::{Endpoint, Metadata};
+
+        let mut endpoints = [
+            Metadata::new("Delete.", http::Method::DELETE, "delete", "/b", false, true),
+            Metadata::new("Get.", http::Method::GET, "get", "/b", false, true),
+            create::Request::METADATA,
+        ];
+        endpoints.sort();
+
+        let names: Vec<_> = endpoints.iter().map(|metadata| metadata.name).collect();
+        assert_eq!(names, vec!["create_alias", "delete", "get"]);
+
+        assert_eq!(
+            serde_json::to_value(&endpoints[2]).unwrap(),
+            json!({
+                "description": "Get.",
+                "method": "GET",
+                "name": "get",
+                "path": "/b",
+                "rate_limited": false,
+                "requires_authentication": true,
+                "access_token_placement": "authorization_header",
+                "compress_request_body": false,
+                "history_paths": [],
+                "unstable_feature": null,
+            })
+        );
+    }
+
+    #[test]
+    fn make_endpoint_url_wrong_number_of_path_args() {
+        use crate::Endpoint;
+
+        let metadata = create::Request::METADATA;
+
+        let error = metadata.make_endpoint_url("https://example.org", &[], &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The endpoint's path has 1 parameters, but 0 path arguments were given."
+        );
+
+        let error = metadata.make_endpoint_url("https://example.org", &[&"a", &"b"], &[]);
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "The endpoint's path has 1 parameters, but 2 path arguments were given."
+        );
+    }
+
+    #[test]
+    fn any_endpoint() {
+        use std::convert::TryFrom;
+
+        use ruma_identifiers::{RoomAliasId, RoomId};
+
+        use crate::AnyEndpoint;
+
+        let requests: Vec<Box<dyn AnyEndpoint>> = vec![Box::new(create::Request {
+            room_id: RoomId::try_from("!roomid:example.org").unwrap(),
+            room_alias: RoomAliasId::try_from("#alias:example.org").unwrap(),
+        })];
+
+        for request in requests {
+            assert_eq!(request.name(), "create_alias");
+            assert_eq!(request.metadata().method, http::Method::PUT);
+
+            let convert_response = request.response_converter();
+            let http_request = request.try_into_http_request().unwrap();
+            assert_eq!(http_request.method(), http::Method::PUT);
+
+            let response = convert_response(http::Response::new(Vec::new())).unwrap();
+            assert!(response.downcast_ref::<create::Response>().is_some());
+        }
+    }
+
+    #[test]
+    fn sign_request() {
+        use std::{convert::TryFrom, error::Error as StdError};
+
+        use ruma_identifiers::{RoomAliasId, RoomId};
+
+        use crate::{Metadata, OutgoingRequest, RequestSigner};
+
+        struct PathSigner;
+
+        impl RequestSigner for PathSigner {
+            fn sign(
+                &self,
+                metadata: &Metadata,
+                request: &mut http::Request<Vec<u8>>,
+            ) -> Result<(), Box<dyn StdError + Send + Sync>> {
+                let signature = format!("{} {}", request.method(), metadata.name);
+                request.headers_mut().insert(http::header::AUTHORIZATION, signature.parse()?);
+                Ok(())
+            }
+        }
+
+        struct FailingSigner;
+
+        impl RequestSigner for FailingSigner {
+            fn sign(
+                &self,
+                _metadata: &Metadata,
+                _request: &mut http::Request<Vec<u8>>,
+            ) -> Result<(), Box<dyn StdError + Send + Sync>> {
+                Err("no signing key".into())
+            }
+        }
+
+        let request = || create::Request {
+            room_id: RoomId::try_from("!roomid:example.org").unwrap(),
+            room_alias: RoomAliasId::try_from("#alias:example.org").unwrap(),
+        };
+
+        let http_request = request().try_into_signed_http_request(&PathSigner).unwrap();
+        assert_eq!(http_request.headers()[http::header::AUTHORIZATION], "PUT create_alias");
+
+        let error = request().try_into_signed_http_request(&FailingSigner).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "An error occurred while signing the request: no signing key"
+        );
+    }
 }
@@ -0,0 +1,181 @@
+//! The standard error body of Matrix APIs.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+use http::{header::CONTENT_TYPE, HeaderValue, StatusCode};
+use serde_json::{Map, Value};
+
+/// The JSON body of an unsuccessful response as defined by the Matrix specification, e.g.
+/// `{"errcode": "M_FORBIDDEN", "error": "You are not invited to this room."}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatrixError {
+    /// The error code, e.g. `M_FORBIDDEN`.
+    pub errcode: String,
+
+    /// The human-readable error message, which is empty if the server didn't send one.
+    pub error: String,
+
+    /// All other fields of the body, like `retry_after_ms` or `soft_logout`.
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl MatrixError {
+    /// Parses a response body as a Matrix error.
+    ///
+    /// Returns `None` if the body isn't a JSON object with a string `errcode`.
+    pub fn from_body(body: &[u8]) -> Option<Self> {
+        match serde_json::from_slice(body).ok()? {
+            Value::Object(object) => Self::from_fields(object.into_iter().collect()),
+            _ => None,
+        }
+    }
+
+    /// Creates a Matrix error from the fields of a JSON object, if they include a string
+    /// `errcode`.
+    pub(crate) fn from_fields(mut extra: BTreeMap<String, Value>) -> Option<Self> {
+        let errcode = match extra.remove("errcode")? {
+            Value::String(errcode) => errcode,
+            _ => return None,
+        };
+        let error = match extra.remove("error") {
+            Some(Value::String(error)) => error,
+            Some(other) => {
+                extra.insert("error".to_owned(), other);
+                String::new()
+            }
+            None => String::new(),
+        };
+
+        Some(Self { errcode, error, extra })
+    }
+
+    /// The HTTP status code a server responds with for this error, as given by the Matrix
+    /// specification for its error code.
+    ///
+    /// Error codes the specification doesn't list, including custom ones, map to
+    /// `400 Bad Request`.
+    pub fn status_code(&self) -> StatusCode {
+        match &*self.errcode {
+            "M_UNKNOWN_TOKEN" | "M_MISSING_TOKEN" | "M_UNAUTHORIZED" => StatusCode::UNAUTHORIZED,
+            "M_FORBIDDEN"
+            | "M_USER_DEACTIVATED"
+            | "M_GUEST_ACCESS_FORBIDDEN"
+            | "M_THREEPID_DENIED"
+            | "M_RESOURCE_LIMIT_EXCEEDED"
+            | "M_CANNOT_LEAVE_SERVER_NOTICE_ROOM" => StatusCode::FORBIDDEN,
+            "M_NOT_FOUND" | "M_UNRECOGNIZED" => StatusCode::NOT_FOUND,
+            "M_TOO_LARGE" => StatusCode::PAYLOAD_TOO_LARGE,
+            "M_LIMIT_EXCEEDED" => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// Creates a response with the error as its JSON body and the status code given by
+/// `MatrixError::status_code`.
+impl From<MatrixError> for http::Response<Vec<u8>> {
+    fn from(error: MatrixError) -> Self {
+        let status = error.status_code();
+
+        let mut body: Map<String, Value> = error.extra.into_iter().collect();
+        body.insert("errcode".to_owned(), Value::String(error.errcode));
+        if !error.error.is_empty() {
+            body.insert("error".to_owned(), Value::String(error.error));
+        }
+
+        let mut response = http::Response::new(
+            serde_json::to_vec(&body).expect("failed to serialize a JSON object"),
+        );
+        *response.status_mut() = status;
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        response
+    }
+}
+
+impl Display for MatrixError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if self.error.is_empty() {
+            write!(f, "{}", self.errcode)
+        } else {
+            write!(f, "{}: {}", self.errcode, self.error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use http::{header::CONTENT_TYPE, StatusCode};
+    use serde_json::json;
+
+    use super::MatrixError;
+
+    #[test]
+    fn standard_body() {
+        let error = MatrixError::from_body(
+            br#"{"errcode":"M_LIMIT_EXCEEDED","error":"Too many requests","retry_after_ms":2000}"#,
+        )
+        .unwrap();
+
+        assert_eq!(error.errcode, "M_LIMIT_EXCEEDED");
+        assert_eq!(error.error, "Too many requests");
+        assert_eq!(error.extra["retry_after_ms"], json!(2000));
+        assert_eq!(error.to_string(), "M_LIMIT_EXCEEDED: Too many requests");
+    }
+
+    #[test]
+    fn missing_message() {
+        let error = MatrixError::from_body(br#"{"errcode":"M_UNKNOWN"}"#).unwrap();
+
+        assert_eq!(error.error, "");
+        assert!(error.extra.is_empty());
+        assert_eq!(error.to_string(), "M_UNKNOWN");
+    }
+
+    #[test]
+    fn status_codes() {
+        let status = |errcode: &str| {
+            MatrixError {
+                errcode: errcode.to_owned(),
+                error: String::new(),
+                extra: BTreeMap::new(),
+            }
+            .status_code()
+        };
+
+        assert_eq!(status("M_NOT_FOUND"), StatusCode::NOT_FOUND);
+        assert_eq!(status("M_FORBIDDEN"), StatusCode::FORBIDDEN);
+        assert_eq!(status("M_LIMIT_EXCEEDED"), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(status("M_UNKNOWN_TOKEN"), StatusCode::UNAUTHORIZED);
+        assert_eq!(status("M_TOO_LARGE"), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(status("M_BAD_JSON"), StatusCode::BAD_REQUEST);
+        assert_eq!(status("ORG_EXAMPLE_CUSTOM"), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn into_response() {
+        let body = br#"{"errcode":"M_LIMIT_EXCEEDED","error":"Slow down","retry_after_ms":2000}"#;
+        let response = http::Response::<Vec<u8>>::from(MatrixError::from_body(body).unwrap());
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(response.body()).unwrap(),
+            json!({ "errcode": "M_LIMIT_EXCEEDED", "error": "Slow down", "retry_after_ms": 2000 })
+        );
+    }
+
+    #[test]
+    fn other_bodies() {
+        assert_eq!(MatrixError::from_body(b""), None);
+        assert_eq!(MatrixError::from_body(b"<html>Bad Gateway</html>"), None);
+        assert_eq!(MatrixError::from_body(br#"{"error":"no code"}"#), None);
+        assert_eq!(MatrixError::from_body(br#"{"errcode":404}"#), None);
+        assert_eq!(MatrixError::from_body(br#"["M_UNKNOWN"]"#), None);
+    }
+}
@@ -0,0 +1,114 @@
+//! A field value that can be absent, `null` or a value.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The value of a field in a partial update, which can be left out (`Absent`), be cleared
+/// (`Null`) or be set (`Value`).
+///
+/// This is the type of all body fields of endpoints with `patch: true` in `ruma_api!`. For it to
+/// serialize correctly, a field also needs the `default` and
+/// `skip_serializing_if = "ruma_api::Maybe::is_absent"` serde attributes, which the macro adds
+/// automatically.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Maybe<T> {
+    /// The field is left out, so the value is left unchanged.
+    #[default]
+    Absent,
+
+    /// The field is `null`, so the value is cleared.
+    Null,
+
+    /// The field has a value, which replaces the current one.
+    Value(T),
+}
+
+impl<T> Maybe<T> {
+    /// Whether the field is left out.
+    pub fn is_absent(&self) -> bool {
+        matches!(self, Maybe::Absent)
+    }
+
+    /// Converts to the equivalent `Option<Option<T>>`, where `None` is `Absent` and `Some(None)`
+    /// is `Null`.
+    pub fn into_option(self) -> Option<Option<T>> {
+        match self {
+            Maybe::Absent => None,
+            Maybe::Null => Some(None),
+            Maybe::Value(value) => Some(Some(value)),
+        }
+    }
+}
+
+impl<T> From<T> for Maybe<T> {
+    fn from(value: T) -> Self {
+        Maybe::Value(value)
+    }
+}
+
+impl<T> From<Option<Option<T>>> for Maybe<T> {
+    fn from(value: Option<Option<T>>) -> Self {
+        match value {
+            None => Maybe::Absent,
+            Some(None) => Maybe::Null,
+            Some(Some(value)) => Maybe::Value(value),
+        }
+    }
+}
+
+/// `Absent` should be skipped via `skip_serializing_if = "ruma_api::Maybe::is_absent"`, it is
+/// serialized as `null` otherwise.
+impl<T: Serialize> Serialize for Maybe<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Maybe::Value(value) => value.serialize(serializer),
+            Maybe::Absent | Maybe::Null => serializer.serialize_none(),
+        }
+    }
+}
+
+/// A missing field is only deserialized as `Absent` if the field has the `default` serde
+/// attribute.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Maybe<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Option::deserialize(deserializer).map(|value| match value {
+            Some(value) => Maybe::Value(value),
+            None => Maybe::Null,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::Maybe;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Profile {
+        #[serde(default, skip_serializing_if = "Maybe::is_absent")]
+        displayname: Maybe<String>,
+    }
+
+    #[test]
+    fn serialize_absent_null_and_value() {
+        let absent = Profile { displayname: Maybe::Absent };
+        let null = Profile { displayname: Maybe::Null };
+        let value = Profile { displayname: "alice".to_owned().into() };
+
+        assert_eq!(serde_json::to_value(&absent).unwrap(), json!({}));
+        assert_eq!(serde_json::to_value(&null).unwrap(), json!({ "displayname": null }));
+        assert_eq!(serde_json::to_value(&value).unwrap(), json!({ "displayname": "alice" }));
+    }
+
+    #[test]
+    fn deserialize_absent_null_and_value() {
+        let absent: Profile = serde_json::from_value(json!({})).unwrap();
+        let null: Profile = serde_json::from_value(json!({ "displayname": null })).unwrap();
+        let value: Profile = serde_json::from_value(json!({ "displayname": "alice" })).unwrap();
+
+        assert_eq!(absent.displayname, Maybe::Absent);
+        assert_eq!(null.displayname, Maybe::Null);
+        assert_eq!(value.displayname.into_option(), Some(Some("alice".to_owned())));
+    }
+}
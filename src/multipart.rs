@@ -0,0 +1,244 @@
+//! Responses made of a JSON metadata part and a content part, as used by federation media
+//! endpoints.
+
+use http::{
+    header::{HeaderName, HeaderValue, CONTENT_TYPE},
+    HeaderMap,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{FromHttpResponseError, InnerError};
+
+/// A `multipart/mixed` response body, whose first part is JSON metadata of type `T` and whose
+/// second part is the content the metadata describes, e.g. a media file.
+///
+/// The content part keeps its own headers, like its `Content-Type`, or a `Location` header if the
+/// content has to be fetched from somewhere else. Any further parts are ignored.
+#[derive(Clone, Debug)]
+pub struct Multipart<T> {
+    /// The deserialized metadata from the first part.
+    pub metadata: T,
+
+    /// The headers of the content part.
+    pub content_headers: HeaderMap,
+
+    /// The body of the content part.
+    pub content: Vec<u8>,
+}
+
+impl<T> Multipart<T> {
+    /// The `Content-Type` of the content part, if it has one.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok())
+    }
+}
+
+impl<T: DeserializeOwned> Multipart<T> {
+    /// Parses the body of a response with a `multipart/*` `Content-Type`.
+    ///
+    /// This is meant to be used in the `TryFrom<http::Response<_>>` implementation of an
+    /// endpoint's response type. On failure, the headers of the response are attached to the
+    /// error.
+    pub fn from_response<B: AsRef<[u8]>>(
+        response: &http::Response<B>,
+    ) -> Result<Self, FromHttpResponseError> {
+        Self::parse(response).map_err(|error| error.with_headers(response.headers()))
+    }
+
+    fn parse<B: AsRef<[u8]>>(response: &http::Response<B>) -> Result<Self, FromHttpResponseError> {
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| invalid("the response has no `Content-Type` header"))?;
+        let boundary = boundary(content_type)?;
+
+        let mut parts = split_parts(response.body().as_ref(), boundary.as_bytes())?.into_iter();
+        let (_, metadata) = parts.next().ok_or_else(|| invalid("the metadata part is missing"))?;
+        let (content_headers, content) =
+            parts.next().ok_or_else(|| invalid("the content part is missing"))?;
+
+        Ok(Self {
+            metadata: serde_json::from_slice(metadata)?,
+            content_headers,
+            content: content.to_vec(),
+        })
+    }
+}
+
+/// Extracts the boundary from a `multipart/*` `Content-Type`.
+fn boundary(content_type: &str) -> Result<String, FromHttpResponseError> {
+    let mut params = content_type.split(';');
+    let media_type = params.next().unwrap_or_default().trim();
+    if !media_type.to_ascii_lowercase().starts_with("multipart/") {
+        return Err(invalid("the `Content-Type` is not `multipart/*`"));
+    }
+
+    params
+        .filter_map(|param| {
+            let mut parts = param.splitn(2, '=');
+            let name = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            if name.eq_ignore_ascii_case("boundary") {
+                Some(value.trim_matches('"').to_owned())
+            } else {
+                None
+            }
+        })
+        .find(|boundary| !boundary.is_empty())
+        .ok_or_else(|| invalid("the `Content-Type` has no boundary"))
+}
+
+/// Splits a multipart body into the headers and bodies of its parts.
+///
+/// Lines may end with `\r\n` or just `\n`.
+fn split_parts<'a>(
+    body: &'a [u8],
+    boundary: &[u8],
+) -> Result<Vec<(HeaderMap, &'a [u8])>, FromHttpResponseError> {
+    let mut delimiter = b"--".to_vec();
+    delimiter.extend_from_slice(boundary);
+
+    let mut start = find(body, &delimiter)
+        .ok_or_else(|| invalid("the body doesn't contain the boundary"))?
+        + delimiter.len();
+    let mut parts = Vec::new();
+
+    loop {
+        let rest = &body[start..];
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+
+        // The rest of the delimiter line, usually empty.
+        let line_end = find(rest, b"\n").ok_or_else(|| invalid("a part is truncated"))?;
+        let part_start = start + line_end + 1;
+
+        let mut next_delimiter = b"\n".to_vec();
+        next_delimiter.extend_from_slice(&delimiter);
+        let part_len = find(&body[part_start..], &next_delimiter)
+            .ok_or_else(|| invalid("the closing boundary is missing"))?;
+        let mut part = &body[part_start..part_start + part_len];
+        if part.ends_with(b"\r") {
+            part = &part[..part.len() - 1];
+        }
+
+        parts.push(parse_part(part)?);
+        start = part_start + part_len + next_delimiter.len();
+    }
+}
+
+/// Splits a part into its headers and its body.
+fn parse_part(part: &[u8]) -> Result<(HeaderMap, &[u8]), FromHttpResponseError> {
+    let mut headers = HeaderMap::new();
+    let mut rest = part;
+
+    loop {
+        let line_end = find(rest, b"\n").ok_or_else(|| invalid("a part has no body"))?;
+        let mut line = &rest[..line_end];
+        if line.ends_with(b"\r") {
+            line = &line[..line.len() - 1];
+        }
+        rest = &rest[line_end + 1..];
+
+        if line.is_empty() {
+            return Ok((headers, rest));
+        }
+
+        let colon = line
+            .iter()
+            .position(|&byte| byte == b':')
+            .ok_or_else(|| invalid("a part has a malformed header"))?;
+        let name = HeaderName::from_bytes(&line[..colon])
+            .map_err(|_| invalid("a part has a malformed header"))?;
+        let value = HeaderValue::from_bytes(trim(&line[colon + 1..]))
+            .map_err(|_| invalid("a part has a malformed header"))?;
+        headers.append(name, value);
+    }
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Trims spaces and tabs from both ends of a header value.
+fn trim(value: &[u8]) -> &[u8] {
+    let is_content = |byte: &u8| *byte != b' ' && *byte != b'\t';
+    match value.iter().position(is_content) {
+        Some(start) => &value[start..=value.iter().rposition(is_content).unwrap()],
+        None => &[],
+    }
+}
+
+fn invalid(message: &'static str) -> FromHttpResponseError {
+    FromHttpResponseError(InnerError::Multipart(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::Multipart;
+
+    #[derive(Debug, Deserialize)]
+    struct Metadata {
+        name: String,
+    }
+
+    fn response(content_type: &str, body: &str) -> http::Response<Vec<u8>> {
+        http::Response::builder()
+            .header(http::header::CONTENT_TYPE, content_type)
+            .body(body.as_bytes().to_vec())
+            .unwrap()
+    }
+
+    #[test]
+    fn metadata_and_content() {
+        let body =
+            "preamble\r\n--abc\r\nContent-Type: application/json\r\n\r\n{\"name\":\"cat\"}\r\n\
+                    --abc\r\nContent-Type: image/png\r\n\r\n\x01\r\n\x02\r\n--abc--\r\n";
+        let multipart =
+            Multipart::<Metadata>::from_response(&response("multipart/mixed; boundary=abc", body))
+                .unwrap();
+
+        assert_eq!(multipart.metadata.name, "cat");
+        assert_eq!(multipart.content_type(), Some("image/png"));
+        assert_eq!(multipart.content, b"\x01\r\n\x02");
+    }
+
+    #[test]
+    fn quoted_boundary_and_bare_newlines() {
+        let body =
+            "--a b\n\n{\"name\":\"dog\"}\n--a b\nLocation: https://example.org/dog\n\n\n--a b--";
+        let multipart = Multipart::<Metadata>::from_response(&response(
+            "multipart/mixed; boundary=\"a b\"",
+            body,
+        ))
+        .unwrap();
+
+        assert_eq!(multipart.metadata.name, "dog");
+        assert_eq!(multipart.content_headers["location"], "https://example.org/dog");
+        assert!(multipart.content.is_empty());
+    }
+
+    #[test]
+    fn invalid_bodies() {
+        let error =
+            Multipart::<Metadata>::from_response(&response("application/json", "{}")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The multipart response body is invalid: the `Content-Type` is not `multipart/*`"
+        );
+        assert!(error.headers().is_some());
+
+        let body = "--abc\r\n\r\n{\"name\":\"cat\"}\r\n--abc--";
+        let error =
+            Multipart::<Metadata>::from_response(&response("multipart/mixed; boundary=abc", body))
+                .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The multipart response body is invalid: the content part is missing"
+        );
+    }
+}
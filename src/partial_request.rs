@@ -0,0 +1,54 @@
+//! The head of an incoming request, parsed before its body is read.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+};
+
+use http::{HeaderMap, Method, Uri};
+
+use crate::{FromHttpRequestError, IncomingRequest};
+
+/// The head of an incoming request to the endpoint `R`, whose path, query string and header
+/// fields have been checked, but whose body hasn't been read yet.
+///
+/// Servers get one from `IncomingRequest::parse_head` and can authenticate or rate-limit the
+/// request before reading its body, which `finish` then parses into `R`.
+pub struct PartialRequest<R> {
+    head: http::Request<()>,
+    request: PhantomData<fn() -> R>,
+}
+
+impl<R: IncomingRequest> PartialRequest<R> {
+    /// Creates a partial request from a request head that passed `IncomingRequest::check_head`.
+    pub(crate) fn new(head: http::Request<()>) -> Self {
+        Self { head, request: PhantomData }
+    }
+
+    /// The method of the request.
+    pub fn method(&self) -> &Method {
+        self.head.method()
+    }
+
+    /// The URI of the request.
+    pub fn uri(&self) -> &Uri {
+        self.head.uri()
+    }
+
+    /// The headers of the request.
+    pub fn headers(&self) -> &HeaderMap {
+        self.head.headers()
+    }
+
+    /// Parses the body of the request and returns the complete request.
+    pub fn finish(self, body: Vec<u8>) -> Result<R, FromHttpRequestError> {
+        let (parts, ()) = self.head.into_parts();
+        R::try_from(http::Request::from_parts(parts, body))
+    }
+}
+
+impl<R> Debug for PartialRequest<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PartialRequest").field("head", &self.head).finish()
+    }
+}
@@ -0,0 +1,43 @@
+//! Helpers for reverse proxies and bridges that re-emit received requests upstream.
+//!
+//! The request and response types of an endpoint generated by `ruma_api!` are the same on both
+//! sides, so a received request is forwarded by converting it from the `http::Request` it arrived
+//! as and back into one for the upstream server, and likewise for the response.
+
+use std::convert::{TryFrom, TryInto};
+
+use crate::{Error, IncomingRequest, IntoHttpError, OutgoingRequest};
+
+/// Parses a received request to the endpoint `R` and converts it into the request to send
+/// upstream.
+///
+/// Only the parts of the request that are fields of `R` are forwarded, so headers like
+/// `Authorization` have to be added again. Like every converted request, the result has a
+/// placeholder host that is replaced by the URL of the upstream server when it is sent.
+pub fn forward_request<R>(request: http::Request<Vec<u8>>) -> Result<http::Request<Vec<u8>>, Error>
+where
+    R: IncomingRequest + OutgoingRequest,
+{
+    let request = R::try_from(request)?;
+    Ok(request.try_into()?)
+}
+
+/// Parses the response of the upstream server to the endpoint `R` and converts it into the
+/// response to send back.
+///
+/// Unsuccessful responses are passed through as they are, so clients see the errors of the
+/// upstream server.
+pub fn forward_response<R>(
+    response: http::Response<Vec<u8>>,
+) -> Result<http::Response<Vec<u8>>, Error>
+where
+    R: OutgoingRequest,
+    R::IncomingResponse: TryInto<http::Response<Vec<u8>>, Error = IntoHttpError>,
+{
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+
+    let response = R::IncomingResponse::try_from(response)?;
+    Ok(response.try_into()?)
+}
@@ -0,0 +1,82 @@
+//! Extraction of the time to wait before retrying from error responses.
+
+use std::time::{Duration, SystemTime};
+
+use http::header::RETRY_AFTER;
+
+/// Returns how long the server asked the client to wait before retrying, if it did.
+///
+/// The `Retry-After` header, given either in seconds or as an HTTP date, takes precedence over
+/// the `retry_after_ms` field of a Matrix error body.
+pub(crate) fn from_response<B: AsRef<[u8]>>(response: &http::Response<B>) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(from_header)
+        .or_else(|| from_body(response.body().as_ref()))
+}
+
+/// Parses the value of a `Retry-After` header.
+fn from_header(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            // A date in the past means the request can be retried right away.
+            Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+        }
+    }
+}
+
+/// Reads the `retry_after_ms` field of a Matrix error body.
+fn from_body(body: &[u8]) -> Option<Duration> {
+    let body: serde_json::Value = serde_json::from_slice(body).ok()?;
+    body.get("retry_after_ms")?.as_u64().map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::from_response;
+
+    fn response(retry_after: Option<&str>, body: &str) -> http::Response<Vec<u8>> {
+        let mut response = http::Response::builder();
+        response.status(429);
+        if let Some(retry_after) = retry_after {
+            response.header("Retry-After", retry_after);
+        }
+        response.body(body.as_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn retry_after_header() {
+        assert_eq!(from_response(&response(Some("120"), "")), Some(Duration::from_secs(120)));
+
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let retry_after = from_response(&response(Some(&date), "")).unwrap();
+        assert!(retry_after > Duration::from_secs(55) && retry_after <= Duration::from_secs(60));
+
+        let past = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(from_response(&response(Some(past), "")), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn retry_after_ms_body_field() {
+        let body =
+            r#"{"errcode":"M_LIMIT_EXCEEDED","error":"Too many requests","retry_after_ms":2000}"#;
+
+        assert_eq!(from_response(&response(None, body)), Some(Duration::from_millis(2000)));
+        assert_eq!(from_response(&response(Some("5"), body)), Some(Duration::from_secs(5)));
+        assert_eq!(from_response(&response(Some("soon"), body)), Some(Duration::from_millis(2000)));
+    }
+
+    #[test]
+    fn no_retry_after() {
+        assert_eq!(from_response(&response(None, r#"{"errcode":"M_FORBIDDEN"}"#)), None);
+        assert_eq!(from_response(&response(None, "not json")), None);
+    }
+}
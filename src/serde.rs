@@ -0,0 +1,10 @@
+//! Modules to hold functions for de-/serializing remote types, for use with `#[serde(with = ...)]`.
+
+#[cfg(feature = "base64")]
+pub mod base64;
+pub mod double_option;
+pub mod duration;
+pub mod empty_string_as_none;
+pub mod number_or_string;
+pub mod opt_number_or_string;
+pub mod time;
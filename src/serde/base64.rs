@@ -0,0 +1,139 @@
+//! De-/serialization functions for binary data encoded as base64 strings.
+//!
+//! Each submodule can be used with `#[serde(with = ...)]` on fields of type `Vec<u8>`, e.g.
+//! `#[serde(with = "ruma_api::serde::base64::unpadded")]`. Serialization works for any type
+//! implementing `AsRef<[u8]>`.
+
+use base64::Config;
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+macro_rules! base64_module {
+    ($(#[$attr:meta])* $name:ident, $config:expr) => {
+        $(#[$attr])*
+        pub mod $name {
+            use serde::{Deserializer, Serializer};
+
+            /// Serializes the given bytes as a base64 string.
+            pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                T: AsRef<[u8]>,
+                S: Serializer,
+            {
+                super::serialize(bytes.as_ref(), serializer, $config)
+            }
+
+            /// Deserializes a base64 string into bytes.
+            pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                super::deserialize(deserializer, $config)
+            }
+        }
+    };
+}
+
+base64_module! {
+    /// The standard base64 alphabet, with padding.
+    standard, base64::STANDARD
+}
+
+base64_module! {
+    /// The standard base64 alphabet, without padding. This is what the Matrix specification calls
+    /// "unpadded base64".
+    unpadded, base64::STANDARD_NO_PAD
+}
+
+base64_module! {
+    /// The URL-safe base64 alphabet, with padding.
+    url_safe, base64::URL_SAFE
+}
+
+base64_module! {
+    /// The URL-safe base64 alphabet, without padding.
+    url_safe_unpadded, base64::URL_SAFE_NO_PAD
+}
+
+/// Serializes bytes as a base64 string with the given configuration.
+fn serialize<S: Serializer>(
+    bytes: &[u8],
+    serializer: S,
+    config: Config,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::encode_config(bytes, config))
+}
+
+/// Deserializes a base64 string with the given configuration into bytes.
+fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    config: Config,
+) -> Result<Vec<u8>, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    base64::decode_config(&encoded, config).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Keys {
+        #[serde(with = "super::standard")]
+        standard: Vec<u8>,
+        #[serde(with = "super::unpadded")]
+        unpadded: Vec<u8>,
+        #[serde(with = "super::url_safe")]
+        url_safe: Vec<u8>,
+        #[serde(with = "super::url_safe_unpadded")]
+        url_safe_unpadded: Vec<u8>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = vec![0xfb, 0xff, 0x01];
+        let keys = Keys {
+            standard: bytes.clone(),
+            unpadded: bytes.clone(),
+            url_safe: bytes.clone(),
+            url_safe_unpadded: bytes,
+        };
+        let json = json!({
+            "standard": "+/8B",
+            "unpadded": "+/8B",
+            "url_safe": "-_8B",
+            "url_safe_unpadded": "-_8B",
+        });
+
+        assert_eq!(serde_json::to_value(&keys).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Keys>(json).unwrap(), keys);
+    }
+
+    #[test]
+    fn padding() {
+        #[derive(Debug, Deserialize, Serialize)]
+        struct Key {
+            #[serde(with = "super::standard")]
+            padded: Vec<u8>,
+            #[serde(with = "super::unpadded")]
+            unpadded: Vec<u8>,
+        }
+
+        let key = Key { padded: b"a".to_vec(), unpadded: b"a".to_vec() };
+        assert_eq!(
+            serde_json::to_value(&key).unwrap(),
+            json!({ "padded": "YQ==", "unpadded": "YQ" })
+        );
+    }
+
+    #[test]
+    fn invalid_base64() {
+        assert!(serde_json::from_value::<Keys>(json!({
+            "standard": "not base64!",
+            "unpadded": "",
+            "url_safe": "",
+            "url_safe_unpadded": "",
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,69 @@
+//! De-/serialization functions for `Option<Option<T>>` fields that need to distinguish between a
+//! field being absent and a field being explicitly set to `null`.
+//!
+//! `None` means the field is absent, `Some(None)` means the field is `null` and `Some(Some(_))`
+//! means the field has a value. For this to work, the field also needs the `default` and
+//! `skip_serializing_if = "Option::is_none"` serde attributes. The `ruma_api!` macro adds all of
+//! these automatically to body fields of type `Option<Option<T>>`.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serializes `Some(None)` as `null` and `Some(Some(value))` as `value`.
+///
+/// `None` should be skipped via `skip_serializing_if = "Option::is_none"`, it is serialized as
+/// `null` otherwise.
+pub fn serialize<T, S>(value: &Option<Option<T>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    match value {
+        Some(inner) => inner.serialize(serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Deserializes `null` as `Some(None)` and any other value as `Some(Some(value))`.
+///
+/// A missing field is only deserialized as `None` if the field has the `default` serde attribute.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct DisplayName {
+        #[serde(default, skip_serializing_if = "Option::is_none", with = "super")]
+        displayname: Option<Option<String>>,
+    }
+
+    #[test]
+    fn serialize_absent_null_and_value() {
+        let absent = DisplayName { displayname: None };
+        let null = DisplayName { displayname: Some(None) };
+        let value = DisplayName { displayname: Some(Some("alice".into())) };
+
+        assert_eq!(serde_json::to_value(&absent).unwrap(), json!({}));
+        assert_eq!(serde_json::to_value(&null).unwrap(), json!({ "displayname": null }));
+        assert_eq!(serde_json::to_value(&value).unwrap(), json!({ "displayname": "alice" }));
+    }
+
+    #[test]
+    fn deserialize_absent_null_and_value() {
+        let absent: DisplayName = serde_json::from_value(json!({})).unwrap();
+        let null: DisplayName = serde_json::from_value(json!({ "displayname": null })).unwrap();
+        let value: DisplayName = serde_json::from_value(json!({ "displayname": "alice" })).unwrap();
+
+        assert_eq!(absent.displayname, None);
+        assert_eq!(null.displayname, Some(None));
+        assert_eq!(value.displayname, Some(Some("alice".into())));
+    }
+}
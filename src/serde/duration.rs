@@ -0,0 +1,119 @@
+//! De-/serialization functions for `Duration`s.
+
+pub mod ms {
+    //! De-/serialization functions for `Duration` objects represented as integer milliseconds,
+    //! e.g. `timeout` fields.
+
+    use std::{convert::TryFrom, time::Duration};
+
+    use serde::{ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serializes a `Duration` as an integer number of milliseconds.
+    ///
+    /// Returns an error if the duration is too large to be represented as a `u64`.
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match u64::try_from(duration.as_millis()) {
+            Ok(ms) => ms.serialize(serializer),
+            Err(_) => Err(S::Error::custom("duration too large to be represented in milliseconds")),
+        }
+    }
+
+    /// Deserializes an integer number of milliseconds into a `Duration`.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+pub mod opt_ms {
+    //! De-/serialization functions for `Option<Duration>` objects represented as integer
+    //! milliseconds, e.g. `retry_after_ms` fields.
+    //!
+    //! To omit the field when serializing `None`, also add
+    //! `skip_serializing_if = "Option::is_none"`. To accept a missing field when deserializing,
+    //! also add `default`.
+
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Serializes an `Option<Duration>` as an integer number of milliseconds or `null`.
+    ///
+    /// Returns an error if the duration is too large to be represented as a `u64`.
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match duration {
+            Some(duration) => super::ms::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Deserializes an integer number of milliseconds or `null` into an `Option<Duration>`.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<u64>::deserialize(deserializer).map(|ms| ms.map(Duration::from_millis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Timeouts {
+        #[serde(with = "super::ms")]
+        timeout: Duration,
+        #[serde(default, with = "super::opt_ms", skip_serializing_if = "Option::is_none")]
+        retry_after_ms: Option<Duration>,
+    }
+
+    #[test]
+    fn serialize() {
+        let timeouts = Timeouts {
+            timeout: Duration::from_secs(30),
+            retry_after_ms: Some(Duration::from_millis(1500)),
+        };
+        assert_eq!(
+            serde_json::to_value(&timeouts).unwrap(),
+            json!({ "timeout": 30000, "retry_after_ms": 1500 })
+        );
+
+        let timeouts = Timeouts { timeout: Duration::from_millis(1), retry_after_ms: None };
+        assert_eq!(serde_json::to_value(&timeouts).unwrap(), json!({ "timeout": 1 }));
+    }
+
+    #[test]
+    fn deserialize() {
+        assert_eq!(
+            serde_json::from_value::<Timeouts>(json!({ "timeout": 30000, "retry_after_ms": 1500 }))
+                .unwrap(),
+            Timeouts {
+                timeout: Duration::from_secs(30),
+                retry_after_ms: Some(Duration::from_millis(1500))
+            }
+        );
+        assert_eq!(
+            serde_json::from_value::<Timeouts>(json!({ "timeout": 0 })).unwrap(),
+            Timeouts { timeout: Duration::from_secs(0), retry_after_ms: None }
+        );
+        assert!(serde_json::from_value::<Timeouts>(json!({ "timeout": -1 })).is_err());
+    }
+
+    #[test]
+    fn serialize_too_large() {
+        let timeouts = Timeouts { timeout: Duration::from_secs(u64::MAX), retry_after_ms: None };
+        assert!(serde_json::to_value(&timeouts).is_err());
+    }
+}
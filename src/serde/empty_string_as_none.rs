@@ -0,0 +1,83 @@
+//! De-/serialization functions for `Option<T>` fields where some servers send an empty string
+//! instead of omitting the field.
+//!
+//! `T` has to be deserializable from a string, like `String` or the identifier types from
+//! ruma-identifiers. To accept a missing field when deserializing, also add `default`.
+
+use serde::{
+    de::{Deserialize, Deserializer, IntoDeserializer as _},
+    ser::{Serialize, Serializer},
+};
+
+/// Serializes `Some(value)` as `value` and `None` as `null`.
+///
+/// To omit the field for `None` instead, also add `skip_serializing_if = "Option::is_none"`.
+pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    value.serialize(serializer)
+}
+
+/// Deserializes an empty string or `null` as `None` and any other string as `Some(value)`.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(ref string) if string.is_empty() => Ok(None),
+        Some(string) => T::deserialize(string.into_deserializer()).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma_identifiers::UserId;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Profile {
+        #[serde(default, with = "super", skip_serializing_if = "Option::is_none")]
+        avatar_url: Option<String>,
+        #[serde(default, with = "super", skip_serializing_if = "Option::is_none")]
+        user_id: Option<UserId>,
+    }
+
+    #[test]
+    fn deserialize() {
+        let profile: Profile =
+            serde_json::from_value(json!({ "avatar_url": "", "user_id": "" })).unwrap();
+        assert_eq!(profile, Profile { avatar_url: None, user_id: None });
+
+        let profile: Profile = serde_json::from_value(json!({ "avatar_url": null })).unwrap();
+        assert_eq!(profile, Profile { avatar_url: None, user_id: None });
+
+        let profile: Profile = serde_json::from_value(
+            json!({ "avatar_url": "mxc://example.org/abc", "user_id": "@alice:example.org" }),
+        )
+        .unwrap();
+        assert_eq!(profile.avatar_url.unwrap(), "mxc://example.org/abc");
+        assert_eq!(profile.user_id.unwrap().to_string(), "@alice:example.org");
+    }
+
+    #[test]
+    fn deserialize_invalid_value() {
+        assert!(serde_json::from_value::<Profile>(json!({ "user_id": "alice" })).is_err());
+    }
+
+    #[test]
+    fn serialize() {
+        let profile = Profile { avatar_url: None, user_id: None };
+        assert_eq!(serde_json::to_value(&profile).unwrap(), json!({}));
+
+        let profile = Profile { avatar_url: Some("mxc://example.org/abc".into()), user_id: None };
+        assert_eq!(
+            serde_json::to_value(&profile).unwrap(),
+            json!({ "avatar_url": "mxc://example.org/abc" })
+        );
+    }
+}
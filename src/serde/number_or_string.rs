@@ -0,0 +1,65 @@
+//! De-/serialization functions for number fields that some servers send as strings, e.g. `"42"`
+//! instead of `42`.
+//!
+//! Numbers are always serialized as numbers.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serializes a number as a number.
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    value.serialize(serializer)
+}
+
+/// Deserializes a number, or a string containing a number.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString<T> {
+        Number(T),
+        String(String),
+    }
+
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number),
+        NumberOrString::String(string) => string.parse().map_err(D::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Count {
+        #[serde(with = "super")]
+        count: u64,
+    }
+
+    #[test]
+    fn deserialize_number_or_string() {
+        let number: Count = serde_json::from_value(json!({ "count": 42 })).unwrap();
+        let string: Count = serde_json::from_value(json!({ "count": "42" })).unwrap();
+
+        assert_eq!(number, Count { count: 42 });
+        assert_eq!(string, Count { count: 42 });
+        assert!(serde_json::from_value::<Count>(json!({ "count": "forty-two" })).is_err());
+        assert!(serde_json::from_value::<Count>(json!({ "count": true })).is_err());
+    }
+
+    #[test]
+    fn serialize_as_number() {
+        assert_eq!(serde_json::to_value(&Count { count: 42 }).unwrap(), json!({ "count": 42 }));
+    }
+}
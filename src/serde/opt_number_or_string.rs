@@ -0,0 +1,68 @@
+//! De-/serialization functions for optional number fields that some servers send as strings, e.g.
+//! `"42"` instead of `42`.
+//!
+//! Numbers are always serialized as numbers. To omit the field when serializing `None`, also add
+//! `skip_serializing_if = "Option::is_none"`. To accept a missing field when deserializing, also
+//! add `default`.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serializes an `Option` of a number as the number or `null`.
+pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    value.serialize(serializer)
+}
+
+/// Deserializes `null`, a number, or a string containing a number.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(bound(deserialize = "T: Deserialize<'de> + FromStr, T::Err: Display"))]
+    struct NumberOrString<T>(#[serde(with = "super::number_or_string")] T);
+
+    Ok(Option::<NumberOrString<T>>::deserialize(deserializer)?.map(|number| number.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Count {
+        #[serde(default, with = "super", skip_serializing_if = "Option::is_none")]
+        count: Option<u64>,
+    }
+
+    #[test]
+    fn deserialize_opt_number_or_string() {
+        let number: Count = serde_json::from_value(json!({ "count": 42 })).unwrap();
+        let string: Count = serde_json::from_value(json!({ "count": "42" })).unwrap();
+        let null: Count = serde_json::from_value(json!({ "count": null })).unwrap();
+        let absent: Count = serde_json::from_value(json!({})).unwrap();
+
+        assert_eq!(number, Count { count: Some(42) });
+        assert_eq!(string, Count { count: Some(42) });
+        assert_eq!(null, Count { count: None });
+        assert_eq!(absent, Count { count: None });
+        assert!(serde_json::from_value::<Count>(json!({ "count": "forty-two" })).is_err());
+    }
+
+    #[test]
+    fn serialize_as_number() {
+        assert_eq!(
+            serde_json::to_value(&Count { count: Some(42) }).unwrap(),
+            json!({ "count": 42 })
+        );
+        assert_eq!(serde_json::to_value(&Count { count: None }).unwrap(), json!({}));
+    }
+}
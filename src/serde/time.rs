@@ -0,0 +1,218 @@
+//! De-/serialization functions for `SystemTime`s represented as milliseconds since the unix epoch,
+//! e.g. `origin_server_ts` fields.
+
+use std::{
+    convert::TryFrom,
+    fmt::{Formatter, Result as FmtResult},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{
+    de::{self, Visitor},
+    ser::Error as _,
+    Serialize, Serializer,
+};
+
+pub mod ms_since_unix_epoch {
+    //! De-/serialization functions for `SystemTime` objects represented as an integer number of
+    //! milliseconds since the unix epoch.
+
+    use std::time::SystemTime;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Serializes a `SystemTime` as an integer number of milliseconds since the unix epoch.
+    ///
+    /// Returns an error if the time is before the unix epoch or too far in the future to be
+    /// represented as a `u64`.
+    pub fn serialize<S>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        super::serialize(time, serializer)
+    }
+
+    /// Deserializes an integer number of milliseconds since the unix epoch into a `SystemTime`.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        u64::deserialize(deserializer).map(super::from_ms)
+    }
+}
+
+pub mod opt_ms_since_unix_epoch {
+    //! De-/serialization functions for `Option<SystemTime>` objects represented as an integer
+    //! number of milliseconds since the unix epoch.
+    //!
+    //! To omit the field when serializing `None`, also add
+    //! `skip_serializing_if = "Option::is_none"`. To accept a missing field when deserializing,
+    //! also add `default`.
+
+    use std::time::SystemTime;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Serializes an `Option<SystemTime>` as an integer number of milliseconds since the unix
+    /// epoch or `null`.
+    pub fn serialize<S>(time: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match time {
+            Some(time) => super::serialize(time, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Deserializes an integer number of milliseconds since the unix epoch or `null` into an
+    /// `Option<SystemTime>`.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<SystemTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<u64>::deserialize(deserializer).map(|ms| ms.map(super::from_ms))
+    }
+}
+
+pub mod lenient_ms_since_unix_epoch {
+    //! Like `ms_since_unix_epoch`, but deserialization also accepts the number of milliseconds
+    //! encoded as a string (e.g. `"1577836800000"`), as sent by some buggy servers.
+    //!
+    //! Serialization always produces an integer.
+
+    use std::time::SystemTime;
+
+    use serde::{Deserializer, Serializer};
+
+    /// Serializes a `SystemTime` as an integer number of milliseconds since the unix epoch.
+    pub fn serialize<S>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        super::serialize(time, serializer)
+    }
+
+    /// Deserializes an integer number of milliseconds since the unix epoch, or a string containing
+    /// one, into a `SystemTime`.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(super::LenientMsVisitor).map(super::from_ms)
+    }
+}
+
+/// Serializes a `SystemTime` as an integer number of milliseconds since the unix epoch.
+fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let since_epoch = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| S::Error::custom("time is before the unix epoch"))?;
+
+    match u64::try_from(since_epoch.as_millis()) {
+        Ok(ms) => ms.serialize(serializer),
+        Err(_) => Err(S::Error::custom("time too far in the future to be represented")),
+    }
+}
+
+/// Converts a number of milliseconds since the unix epoch into a `SystemTime`.
+fn from_ms(ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms)
+}
+
+/// Accepts a non-negative integer or a string containing one.
+struct LenientMsVisitor;
+
+impl<'de> Visitor<'de> for LenientMsVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("a number of milliseconds as an integer or string")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<u64, E> {
+        Ok(value)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<u64, E> {
+        u64::try_from(value).map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<u64, E> {
+        value.trim().parse().map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Event {
+        #[serde(with = "super::ms_since_unix_epoch")]
+        origin_server_ts: SystemTime,
+        #[serde(
+            default,
+            with = "super::opt_ms_since_unix_epoch",
+            skip_serializing_if = "Option::is_none"
+        )]
+        redacted_ts: Option<SystemTime>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct LenientEvent {
+        #[serde(with = "super::lenient_ms_since_unix_epoch")]
+        origin_server_ts: SystemTime,
+    }
+
+    fn ts(ms: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn round_trip() {
+        let event = Event { origin_server_ts: ts(1_577_836_800_000), redacted_ts: Some(ts(1)) };
+        let json = json!({ "origin_server_ts": 1_577_836_800_000u64, "redacted_ts": 1 });
+
+        assert_eq!(serde_json::to_value(&event).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+
+        let event = Event { origin_server_ts: ts(0), redacted_ts: None };
+        let json = json!({ "origin_server_ts": 0 });
+
+        assert_eq!(serde_json::to_value(&event).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+    }
+
+    #[test]
+    fn strict_rejects_strings() {
+        assert!(serde_json::from_value::<Event>(json!({ "origin_server_ts": "1" })).is_err());
+    }
+
+    #[test]
+    fn lenient_accepts_strings() {
+        assert_eq!(
+            serde_json::from_value::<LenientEvent>(json!({ "origin_server_ts": "1577836800000" }))
+                .unwrap(),
+            LenientEvent { origin_server_ts: ts(1_577_836_800_000) }
+        );
+        assert_eq!(
+            serde_json::from_value::<LenientEvent>(json!({ "origin_server_ts": 5 })).unwrap(),
+            LenientEvent { origin_server_ts: ts(5) }
+        );
+        assert!(
+            serde_json::from_value::<LenientEvent>(json!({ "origin_server_ts": "soon" })).is_err()
+        );
+        assert!(serde_json::from_value::<LenientEvent>(json!({ "origin_server_ts": -5 })).is_err());
+    }
+
+    #[test]
+    fn serialize_before_epoch() {
+        let event =
+            Event { origin_server_ts: UNIX_EPOCH - Duration::from_secs(1), redacted_ts: None };
+        assert!(serde_json::to_value(&event).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! The body of responses asking for user-interactive authentication.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::MatrixError;
+
+/// The information a server sends with a `401 Unauthorized` response to a request that needs
+/// user-interactive authentication, describing the ways the client can authenticate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UiaaInfo {
+    /// The lists of stages the client can complete to authenticate, one of which has to be
+    /// completed.
+    pub flows: Vec<AuthFlow>,
+
+    /// The stages the client has already completed.
+    pub completed: Vec<String>,
+
+    /// The parameters each stage needs, keyed by the stage's type.
+    pub params: BTreeMap<String, Value>,
+
+    /// The session identifier the client has to send along with its next attempt, if any.
+    pub session: Option<String>,
+
+    /// The error of the previous attempt, if it failed.
+    pub auth_error: Option<MatrixError>,
+}
+
+/// A list of stages that together authenticate the client, e.g. `["m.login.password"]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthFlow {
+    /// The types of the stages, in the order they have to be completed.
+    pub stages: Vec<String>,
+}
+
+impl UiaaInfo {
+    /// Parses a response body as user-interactive authentication information.
+    ///
+    /// Returns `None` if the body isn't a JSON object with a `flows` array whose entries all have
+    /// a `stages` array of strings.
+    pub fn from_body(body: &[u8]) -> Option<Self> {
+        let mut object = match serde_json::from_slice(body).ok()? {
+            Value::Object(object) => object.into_iter().collect::<BTreeMap<_, _>>(),
+            _ => return None,
+        };
+
+        let flows = object
+            .remove("flows")?
+            .as_array()?
+            .iter()
+            .map(|flow| Some(AuthFlow { stages: strings(flow.get("stages")?)? }))
+            .collect::<Option<_>>()?;
+        let completed = match object.remove("completed") {
+            Some(completed) => strings(&completed)?,
+            None => Vec::new(),
+        };
+        let params = match object.remove("params") {
+            Some(Value::Object(params)) => params.into_iter().collect(),
+            Some(_) => return None,
+            None => BTreeMap::new(),
+        };
+        let session = match object.remove("session") {
+            Some(Value::String(session)) => Some(session),
+            Some(_) => return None,
+            None => None,
+        };
+
+        // The remaining fields are the error of the previous attempt, if there was one.
+        let auth_error = MatrixError::from_fields(object);
+
+        Some(Self { flows, completed, params, session, auth_error })
+    }
+}
+
+/// Reads a JSON array of strings.
+fn strings(value: &Value) -> Option<Vec<String>> {
+    value.as_array()?.iter().map(|value| value.as_str().map(ToOwned::to_owned)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{AuthFlow, UiaaInfo};
+
+    #[test]
+    fn first_attempt() {
+        let body = br#"{
+            "flows": [{ "stages": ["m.login.password"] }, { "stages": ["m.login.sso"] }],
+            "params": { "m.login.terms": { "policies": {} } },
+            "session": "xxxxxx"
+        }"#;
+        let info = UiaaInfo::from_body(body).unwrap();
+
+        assert_eq!(
+            info.flows,
+            vec![
+                AuthFlow { stages: vec!["m.login.password".into()] },
+                AuthFlow { stages: vec!["m.login.sso".into()] },
+            ]
+        );
+        assert!(info.completed.is_empty());
+        assert_eq!(info.params["m.login.terms"], json!({ "policies": {} }));
+        assert_eq!(info.session.as_deref(), Some("xxxxxx"));
+        assert_eq!(info.auth_error, None);
+    }
+
+    #[test]
+    fn failed_attempt() {
+        let body = br#"{
+            "errcode": "M_FORBIDDEN",
+            "error": "Invalid password",
+            "completed": ["m.login.dummy"],
+            "flows": [{ "stages": ["m.login.dummy", "m.login.password"] }]
+        }"#;
+        let info = UiaaInfo::from_body(body).unwrap();
+
+        assert_eq!(info.completed, vec!["m.login.dummy".to_owned()]);
+        assert_eq!(info.session, None);
+        let auth_error = info.auth_error.unwrap();
+        assert_eq!(auth_error.errcode, "M_FORBIDDEN");
+        assert_eq!(auth_error.error, "Invalid password");
+        assert!(auth_error.extra.is_empty());
+    }
+
+    #[test]
+    fn other_bodies() {
+        assert_eq!(UiaaInfo::from_body(br#"{"errcode":"M_UNKNOWN_TOKEN"}"#), None);
+        assert_eq!(UiaaInfo::from_body(br#"{"flows":[{"stages":[1]}]}"#), None);
+        assert_eq!(UiaaInfo::from_body(br#"{"flows":[],"session":5}"#), None);
+        assert_eq!(UiaaInfo::from_body(b""), None);
+    }
+}
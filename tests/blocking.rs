@@ -0,0 +1,686 @@
+#![cfg(all(feature = "blocking", feature = "client"))]
+
+use std::{
+    convert::TryFrom,
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Barrier, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ruma_api::{
+    blocking::{
+        send_all_blocking, BodySizeObserver, CircuitBreaker, RequestCoalescer, SendBlocking,
+        SmokeTest, SmokeTestOutcome, TokenProvider,
+    },
+    Metadata, RequestSigner,
+};
+use ruma_identifiers::{RoomAliasId, RoomId};
+
+pub mod create_alias {
+    use ruma_api::ruma_api;
+    use ruma_identifiers::{RoomAliasId, RoomId};
+
+    ruma_api! {
+        metadata {
+            description: "Add an alias to a room.",
+            method: PUT,
+            name: "create_alias",
+            path: "/_matrix/client/r0/directory/room/:room_alias",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub room_alias: RoomAliasId,
+            pub room_id: RoomId,
+        }
+
+        response {}
+    }
+}
+
+pub mod query_token_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "query_token_endpoint",
+            path: "/_matrix/app/v1/users/:user_id",
+            rate_limited: false,
+            requires_authentication: true,
+            access_token_placement: QueryString,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub user_id: String,
+        }
+
+        response {}
+    }
+}
+
+pub mod uiaa_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "uiaa_endpoint",
+            path: "/_matrix/foo/uiaa",
+            rate_limited: false,
+            requires_authentication: true,
+            uiaa: true,
+        }
+
+        request {}
+
+        response {}
+    }
+}
+
+pub mod header_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "header_endpoint",
+            path: "/_matrix/foo/header",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(header = ACCEPT_LANGUAGE)]
+            pub accept_language: String,
+        }
+
+        response {}
+    }
+}
+
+pub mod request_id_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "request_id_endpoint",
+            path: "/_matrix/foo/request_id",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(request_id)]
+            pub request_id: Option<String>,
+        }
+
+        response {}
+    }
+}
+
+pub mod panicking_endpoint {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use ruma_api::ruma_api;
+
+    static PANICKED: AtomicBool = AtomicBool::new(false);
+
+    /// Panics the first time a response is converted.
+    fn panic_once(_: &Response) -> Result<(), String> {
+        if !PANICKED.swap(true, Ordering::SeqCst) {
+            panic!("converting the response panicked");
+        }
+
+        Ok(())
+    }
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "panicking_endpoint",
+            path: "/_matrix/foo/panicking",
+            rate_limited: false,
+            requires_authentication: false,
+            validate_response: panic_once,
+        }
+
+        request {}
+
+        response {}
+    }
+}
+
+/// Reads a request with a body of the length given in its `Content-Length` header, if any.
+fn read_request(stream: &mut TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    let header_len = loop {
+        if let Some(pos) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos + 4;
+        }
+
+        let read = stream.read(&mut buf).unwrap();
+        request.extend_from_slice(&buf[..read]);
+    };
+
+    let headers = String::from_utf8_lossy(&request[..header_len]).to_lowercase();
+    let content_length = headers
+        .lines()
+        .find(|line| line.starts_with("content-length:"))
+        .and_then(|line| line["content-length:".len()..].trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while request.len() < header_len + content_length {
+        let read = stream.read(&mut buf).unwrap();
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    String::from_utf8(request).unwrap()
+}
+
+/// Serves a single request with the given raw HTTP response and returns the raw request.
+fn serve_once(response: &'static str) -> (String, thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let request = read_request(&mut stream);
+        stream.write_all(response.as_bytes()).unwrap();
+        request
+    });
+
+    (base_url, handle)
+}
+
+/// Waits up to a second for another connection, to check whether a request was sent.
+fn accepts_another(listener: &TcpListener) -> Option<TcpStream> {
+    listener.set_nonblocking(true).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while Instant::now() < deadline {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false).unwrap();
+                return Some(stream);
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(error) => panic!("accept failed: {}", error),
+        }
+    }
+
+    None
+}
+
+fn request() -> create_alias::Request {
+    create_alias::Request {
+        room_alias: RoomAliasId::try_from("#alias:example.org").unwrap(),
+        room_id: RoomId::try_from("!roomid:example.org").unwrap(),
+    }
+}
+
+#[test]
+fn send_blocking_success() {
+    let (base_url, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+
+    let result = request().send_blocking(&ureq::agent(), &base_url, Some("secret"));
+    let raw_request = server.join().unwrap();
+
+    assert!(result.is_ok());
+    assert!(raw_request.starts_with("PUT /_matrix/client/r0/directory/room/"));
+    assert!(raw_request.to_lowercase().contains("authorization: bearer secret\r\n"));
+    assert!(raw_request.ends_with(r#"{"room_id":"!roomid:example.org"}"#));
+}
+
+#[test]
+fn send_blocking_keeps_multi_valued_headers() {
+    struct FeatureSigner;
+
+    impl RequestSigner for FeatureSigner {
+        fn sign(
+            &self,
+            _metadata: &Metadata,
+            request: &mut http::Request<Vec<u8>>,
+        ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            let headers = request.headers_mut();
+            headers.append("x-feature", "a".parse().unwrap());
+            headers.append("x-feature", "b".parse().unwrap());
+            headers.append(http::header::COOKIE, "c=1".parse().unwrap());
+            headers.append(http::header::COOKIE, "d=2".parse().unwrap());
+            Ok(())
+        }
+    }
+
+    let (base_url, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+
+    let result = request().send_blocking_signed(&ureq::agent(), &base_url, None, &FeatureSigner);
+    let raw_request = server.join().unwrap().to_lowercase();
+
+    assert!(result.is_ok());
+    assert!(raw_request.contains("x-feature: a, b\r\n"));
+    assert!(raw_request.contains("cookie: c=1; d=2\r\n"));
+}
+
+#[test]
+fn send_blocking_error_status() {
+    let (base_url, server) = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 2\r\n\r\n{}");
+
+    let result = request().send_blocking(&ureq::agent(), &base_url, None);
+    server.join().unwrap();
+
+    assert_eq!(result.unwrap_err().to_string(), "A HTTP 404 Not Found error occurred.");
+}
+
+#[test]
+fn send_blocking_with_limit() {
+    let (base_url, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+    let result = request().send_blocking_with_limit(&ureq::agent(), &base_url, None, 2);
+    server.join().unwrap();
+    assert!(result.is_ok());
+
+    let (base_url, server) =
+        serve_once("HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\n{\"a\":\"bcd\"}");
+    let result = request().send_blocking_with_limit(&ureq::agent(), &base_url, None, 10);
+    server.join().unwrap();
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "The response body exceeded the maximum size of 10 bytes."
+    );
+
+    let (base_url, server) = serve_once(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nb\r\n{\"a\":\"bcd\"}\r\n0\r\n\r\n",
+    );
+    let result = request().send_blocking_with_limit(&ureq::agent(), &base_url, None, 10);
+    server.join().unwrap();
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "The response body exceeded the maximum size of 10 bytes."
+    );
+}
+
+#[test]
+fn send_all_blocking_keeps_order() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    // Answers requests for room IDs starting with `ok` successfully, and all others with 404.
+    let server = thread::spawn(move || {
+        for stream in listener.incoming().take(5) {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let status = if read_request(&mut stream).contains("\"!ok") {
+                    "200 OK"
+                } else {
+                    "404 Not Found"
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nConnection: close\r\nContent-Length: 2\r\n\r\n{{}}",
+                    status
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            });
+        }
+    });
+
+    let requests =
+        ["ok1", "missing1", "ok2", "ok3", "missing2"].iter().map(|id| create_alias::Request {
+            room_id: RoomId::try_from(&format!("!{}:example.org", id)[..]).unwrap(),
+            ..request()
+        });
+    let results = send_all_blocking(&ureq::agent(), &base_url, None, requests, 2);
+    server.join().unwrap();
+
+    let successes: Vec<_> = results.iter().map(Result::is_ok).collect();
+    assert_eq!(successes, [true, false, true, true, false]);
+}
+
+#[test]
+fn send_blocking_observed() {
+    struct Sizes(Mutex<Vec<(&'static str, usize, usize)>>);
+
+    impl BodySizeObserver for Sizes {
+        fn observe(&self, metadata: &Metadata, request_size: usize, response_size: usize) {
+            self.0.lock().unwrap().push((metadata.name, request_size, response_size));
+        }
+    }
+
+    let (base_url, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+    let sizes = Sizes(Mutex::new(Vec::new()));
+
+    let result = request().send_blocking_observed(&ureq::agent(), &base_url, None, &sizes);
+    server.join().unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(*sizes.0.lock().unwrap(), [("create_alias", 33, 2)]);
+}
+
+#[test]
+fn send_blocking_refreshes_rejected_token() {
+    struct Tokens(Mutex<String>);
+
+    impl TokenProvider for Tokens {
+        fn access_token(&self) -> Option<String> {
+            Some(self.0.lock().unwrap().clone())
+        }
+
+        fn refresh(&self, rejected: Option<&str>) -> Option<String> {
+            assert_eq!(rejected, Some("expired"));
+            *self.0.lock().unwrap() = "fresh".to_owned();
+            self.access_token()
+        }
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    // Rejects requests with the expired access token.
+    let server = thread::spawn(move || {
+        for stream in listener.incoming().take(2) {
+            let mut stream = stream.unwrap();
+            let (status, body) = if read_request(&mut stream).contains("Bearer expired") {
+                ("401 Unauthorized", r#"{"errcode":"M_UNKNOWN_TOKEN","error":"Unknown token"}"#)
+            } else {
+                ("200 OK", "{}")
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    let tokens = Tokens(Mutex::new("expired".to_owned()));
+    let result = request().send_blocking_with_token_provider(&ureq::agent(), &base_url, &tokens);
+    server.join().unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(tokens.access_token(), Some("fresh".to_owned()));
+}
+
+#[test]
+fn send_blocking_keeps_token_on_uiaa() {
+    struct Tokens;
+
+    impl TokenProvider for Tokens {
+        fn access_token(&self) -> Option<String> {
+            Some("valid".to_owned())
+        }
+
+        fn refresh(&self, _: Option<&str>) -> Option<String> {
+            panic!("refreshed the access token for a user-interactive authentication response");
+        }
+    }
+
+    let (base_url, server) = serve_once(concat!(
+        "HTTP/1.1 401 Unauthorized\r\nContent-Length: 57\r\n\r\n",
+        r#"{"flows":[{"stages":["m.login.password"]}],"session":"a"}"#,
+    ));
+
+    let result = uiaa_endpoint::Request.send_blocking_with_token_provider(
+        &ureq::agent(),
+        &base_url,
+        &Tokens,
+    );
+    server.join().unwrap();
+
+    assert_eq!(result.unwrap_err().uiaa_info().unwrap().session.as_deref(), Some("a"));
+}
+
+#[test]
+fn circuit_breaker_opens_after_server_failures() {
+    let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+    let unavailable = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 2\r\n\r\n{}";
+
+    for _ in 0..2 {
+        assert!(!breaker.is_open("create_alias"));
+        let (base_url, server) = serve_once(unavailable);
+        let result = breaker.send_blocking(request(), &ureq::agent(), &base_url, None);
+        server.join().unwrap();
+        assert!(result.is_err());
+    }
+
+    assert!(breaker.is_open("create_alias"));
+    let result = breaker.send_blocking(request(), &ureq::agent(), "http://127.0.0.1:1", None);
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Requests to the `create_alias` endpoint are failing, not sending another one."
+    );
+}
+
+#[test]
+fn circuit_breaker_ignores_client_errors() {
+    let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+
+    let (base_url, server) = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 2\r\n\r\n{}");
+    let result = breaker.send_blocking(request(), &ureq::agent(), &base_url, None);
+    server.join().unwrap();
+
+    assert!(result.is_err());
+    assert!(!breaker.is_open("create_alias"));
+}
+
+#[test]
+fn smoke_test() {
+    let mut smoke_test = SmokeTest::new("http://127.0.0.1:1");
+    smoke_test.register(request);
+    assert!(smoke_test.with_only(&["other_endpoint"]).run(&ureq::agent()).is_empty());
+
+    let (base_url, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+    let mut smoke_test = SmokeTest::new(base_url).with_access_token("secret");
+    smoke_test.register(request);
+    let results = smoke_test.run(&ureq::agent());
+    server.join().unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "create_alias");
+    assert!(results[0].outcome.is_passed());
+
+    let (base_url, server) = serve_once("HTTP/1.1 403 Forbidden\r\nContent-Length: 2\r\n\r\n{}");
+    let mut smoke_test = SmokeTest::new(base_url);
+    smoke_test.register(request);
+    let results = smoke_test.run(&ureq::agent());
+    server.join().unwrap();
+
+    match &results[0].outcome {
+        SmokeTestOutcome::ErrorResponse(error) => {
+            assert_eq!(error.status_code(), Some(http::StatusCode::FORBIDDEN));
+        }
+        outcome => panic!("unexpected outcome: {:?}", outcome),
+    }
+}
+
+#[test]
+fn access_token_in_query_string() {
+    let (base_url, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+
+    let request = query_token_endpoint::Request { user_id: "@bot:example.org".into() };
+    let result = request.send_blocking(&ureq::agent(), &base_url, Some("as+token"));
+    let raw_request = server.join().unwrap();
+
+    assert!(result.is_ok());
+    assert!(raw_request.starts_with(
+        "GET /_matrix/app/v1/users/@bot:example.org?access_token=as%2Btoken HTTP/1.1"
+    ));
+    assert!(!raw_request.to_lowercase().contains("authorization:"));
+}
+
+#[test]
+fn identical_requests_are_coalesced() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        read_request(&mut stream);
+
+        // Give the other requests time to start waiting for this one.
+        thread::sleep(Duration::from_millis(300));
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}").unwrap();
+
+        listener.set_nonblocking(true).unwrap();
+        listener.accept().err().map(|error| error.kind()) == Some(ErrorKind::WouldBlock)
+    });
+
+    let coalescer = Arc::new(RequestCoalescer::new());
+    let barrier = Arc::new(Barrier::new(3));
+    let clients: Vec<_> = (0..3)
+        .map(|_| {
+            let coalescer = Arc::clone(&coalescer);
+            let barrier = Arc::clone(&barrier);
+            let base_url = base_url.clone();
+            thread::spawn(move || {
+                let request = query_token_endpoint::Request { user_id: "@bot:example.org".into() };
+                barrier.wait();
+                coalescer.send_blocking(request, &ureq::agent(), &base_url, Some("token"))
+            })
+        })
+        .collect();
+
+    for client in clients {
+        assert!(client.join().unwrap().is_ok());
+    }
+    assert!(server.join().unwrap(), "more than one request was sent");
+}
+
+#[test]
+fn requests_with_different_request_ids_are_coalesced() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        read_request(&mut stream);
+
+        // Give the other request time to start waiting for this one.
+        thread::sleep(Duration::from_millis(300));
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}").unwrap();
+
+        accepts_another(&listener).is_none()
+    });
+
+    let coalescer = Arc::new(RequestCoalescer::new());
+    let barrier = Arc::new(Barrier::new(2));
+    let clients: Vec<_> = [Some("abc123".to_owned()), None]
+        .iter()
+        .cloned()
+        .map(|request_id| {
+            let coalescer = Arc::clone(&coalescer);
+            let barrier = Arc::clone(&barrier);
+            let base_url = base_url.clone();
+            thread::spawn(move || {
+                let request = request_id_endpoint::Request { request_id };
+                barrier.wait();
+                coalescer.send_blocking(request, &ureq::agent(), &base_url, None)
+            })
+        })
+        .collect();
+
+    for client in clients {
+        assert!(client.join().unwrap().is_ok());
+    }
+    assert!(server.join().unwrap(), "more than one request was sent");
+}
+
+#[test]
+fn requests_with_different_headers_are_not_coalesced() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        read_request(&mut stream);
+
+        // The second request is only sent while the first one is in flight if it isn't
+        // coalesced with it.
+        let other = accepts_another(&listener);
+        let response = b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\n{}";
+        stream.write_all(response).unwrap();
+
+        let mut other = other.expect("the second request wasn't sent");
+        read_request(&mut other);
+        other.write_all(response).unwrap();
+    });
+
+    let coalescer = Arc::new(RequestCoalescer::new());
+    let barrier = Arc::new(Barrier::new(2));
+    let clients: Vec<_> = ["en", "de"]
+        .iter()
+        .map(|&accept_language| {
+            let coalescer = Arc::clone(&coalescer);
+            let barrier = Arc::clone(&barrier);
+            let base_url = base_url.clone();
+            thread::spawn(move || {
+                let request =
+                    header_endpoint::Request { accept_language: accept_language.to_owned() };
+                barrier.wait();
+                coalescer.send_blocking(request, &ureq::agent(), &base_url, None)
+            })
+        })
+        .collect();
+
+    for client in clients {
+        assert!(client.join().unwrap().is_ok());
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn waiting_requests_are_sent_if_the_leader_panics() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server = thread::spawn(move || {
+        for (i, stream) in listener.incoming().take(2).enumerate() {
+            let mut stream = stream.unwrap();
+            read_request(&mut stream);
+
+            if i == 0 {
+                // Give the other request time to start waiting for this one.
+                thread::sleep(Duration::from_millis(300));
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\n{}")
+                .unwrap();
+        }
+    });
+
+    let coalescer = Arc::new(RequestCoalescer::new());
+    let barrier = Arc::new(Barrier::new(2));
+    let clients: Vec<_> = (0..2)
+        .map(|_| {
+            let coalescer = Arc::clone(&coalescer);
+            let barrier = Arc::clone(&barrier);
+            let base_url = base_url.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                coalescer.send_blocking(
+                    panicking_endpoint::Request,
+                    &ureq::agent(),
+                    &base_url,
+                    None,
+                )
+            })
+        })
+        .collect();
+
+    let results: Vec<_> = clients.into_iter().map(|client| client.join()).collect();
+    server.join().unwrap();
+
+    assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+    assert!(results.into_iter().filter_map(Result::ok).all(|result| result.is_ok()));
+}
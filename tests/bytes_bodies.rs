@@ -0,0 +1,53 @@
+#![cfg(all(feature = "bytes-bodies", feature = "client", feature = "server"))]
+
+use std::convert::TryFrom;
+
+use bytes::Bytes;
+
+pub mod some_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: PUT,
+            name: "some_endpoint",
+            path: "/_matrix/foo/:bar",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub bar: String,
+            pub baz: u64,
+        }
+
+        response {
+            pub value: String,
+        }
+    }
+}
+
+#[test]
+fn request_round_trip() {
+    let request = some_endpoint::Request { bar: "bar".into(), baz: 3 };
+    let http_request = http::Request::<Bytes>::try_from(request).unwrap();
+    assert_eq!(http_request.uri().path(), "/_matrix/foo/bar");
+    assert_eq!(http_request.body(), &Bytes::from_static(br#"{"baz":3}"#));
+
+    let request = some_endpoint::Request::try_from(http_request).unwrap();
+    assert_eq!(request.bar, "bar");
+    assert_eq!(request.baz, 3);
+}
+
+#[test]
+fn response_round_trip() {
+    let response = some_endpoint::Response { value: "value".into() };
+    let http_response = http::Response::<Bytes>::try_from(response).unwrap();
+    assert_eq!(http_response.headers()[http::header::CONTENT_TYPE], "application/json");
+    assert_eq!(http_response.body(), &Bytes::from_static(br#"{"value":"value"}"#));
+
+    let response = some_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.value, "value");
+}
@@ -0,0 +1,211 @@
+//! Tests for the global `Config`. They live in their own test binary, since the configuration can
+//! only be set once per process.
+#![cfg(feature = "client")]
+
+use std::{convert::TryFrom, sync::Mutex};
+
+use ruma_api::{Config, ContentTypeAnomaly};
+
+static ANOMALIES: Mutex<Vec<ContentTypeAnomaly>> = Mutex::new(Vec::new());
+
+pub mod config_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "config_endpoint",
+            path: "/_matrix/foo/config",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(header = USER_AGENT)]
+            pub user_agent: Option<String>,
+        }
+
+        response {
+            pub hello: String,
+        }
+    }
+}
+
+pub mod body_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "body_endpoint",
+            path: "/_matrix/foo/body",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            pub name: Option<String>,
+        }
+
+        response {}
+    }
+}
+
+pub mod query_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "query_endpoint",
+            path: "/_matrix/foo/query",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(query)]
+            pub since: Option<String>,
+            #[ruma_api(query)]
+            pub set_presence: Option<String>,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn global_config() {
+    assert!(Config::global().is_none());
+
+    let mut config = Config::default();
+    config.default_headers.insert(http::header::USER_AGENT, "default/1.0".parse().unwrap());
+    config.default_headers.append("x-feature", "a".parse().unwrap());
+    config.default_headers.append("x-feature", "b".parse().unwrap());
+    config.strict_content_length = true;
+    config.strict_request_path = false;
+    config.collapse_request_path_slashes = true;
+    config.case_insensitive_query_keys = true;
+    config.strict_request_content_type = true;
+    config.on_content_type_anomaly = Some(|anomaly| ANOMALIES.lock().unwrap().push(anomaly));
+    config.set_global().unwrap();
+
+    let second = Config { max_response_body_size: Some(1), ..Config::default() };
+    assert_eq!(second.set_global().unwrap_err().max_response_body_size, Some(1));
+    assert!(Config::global().unwrap().strict_content_length);
+
+    let request = config_endpoint::Request { user_agent: None };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.headers()[http::header::USER_AGENT], "default/1.0");
+    let features: Vec<_> = http_request.headers().get_all("x-feature").iter().collect();
+    assert_eq!(features, vec!["a", "b"]);
+
+    let request = config_endpoint::Request { user_agent: Some("custom/2.0".into()) };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.headers()[http::header::USER_AGENT], "custom/2.0");
+
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_LENGTH, "100")
+        .body(br#"{ "hello": "world" }"#.to_vec())
+        .unwrap();
+    let error = config_endpoint::Response::try_from(http_response).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "The response body has 20 bytes, but its `Content-Length` header announced 100."
+    );
+
+    // The header of a compressed body announces the compressed length, so it isn't checked.
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_LENGTH, "100")
+        .header(http::header::CONTENT_ENCODING, "gzip")
+        .body(br#"{ "hello": "world" }"#.to_vec())
+        .unwrap();
+    let response = config_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.hello, "world");
+
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "text/plain")
+        .body(br#"{ "hello": "world" }"#.to_vec())
+        .unwrap();
+    assert!(config_endpoint::Response::try_from(http_response).is_ok());
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(br#"{ "hello": "world" }"#.to_vec())
+        .unwrap();
+    assert!(config_endpoint::Response::try_from(http_response).is_ok());
+    assert_eq!(
+        *ANOMALIES.lock().unwrap(),
+        [
+            ContentTypeAnomaly { endpoint: "config_endpoint", content_type: None },
+            ContentTypeAnomaly {
+                endpoint: "config_endpoint",
+                content_type: Some("text/plain".to_owned())
+            },
+        ]
+    );
+
+    #[cfg(feature = "server")]
+    {
+        use ruma_api::IncomingRequest;
+
+        let http_request = http::Request::builder()
+            .method("POST")
+            .uri("/_matrix/foo/config/extra")
+            .body(Vec::new())
+            .unwrap();
+        assert!(config_endpoint::Request::try_from(http_request).is_ok());
+
+        let http_request = http::Request::builder()
+            .method("POST")
+            .uri("//_matrix//foo/config/")
+            .body(Vec::new())
+            .unwrap();
+        assert!(config_endpoint::Request::try_from(http_request).is_ok());
+
+        let body_request = |content_type: Option<&str>, body: &[u8]| {
+            let mut http_request = http::Request::builder();
+            http_request.method("POST").uri("/_matrix/foo/body");
+            if let Some(content_type) = content_type {
+                http_request.header(http::header::CONTENT_TYPE, content_type);
+            }
+            body_endpoint::Request::try_from(http_request.body(body.to_vec()).unwrap())
+        };
+
+        let body = br#"{"name":"foo"}"#;
+        let request = body_request(Some("application/json; charset=utf-8"), body).unwrap();
+        assert_eq!(request.name.as_deref(), Some("foo"));
+        assert!(body_request(None, b"").is_ok());
+
+        let error = body_request(Some("text/plain"), body).unwrap_err();
+        assert_eq!(error.errcode(), Some("M_NOT_JSON"));
+        assert_eq!(
+            error.to_string(),
+            "The request body must be JSON, but its Content-Type is `text/plain`."
+        );
+        let error = body_request(None, body).unwrap_err();
+        assert_eq!(error.to_string(), "The request body must be JSON, but it has no Content-Type.");
+
+        // Requests built by the client pass the check.
+        let request = body_endpoint::Request { name: Some("foo".into()) };
+        let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+        assert_eq!(http_request.headers()[http::header::CONTENT_TYPE], "application/json");
+        let request = body_endpoint::Request::try_from(http_request).unwrap();
+        assert_eq!(request.name.as_deref(), Some("foo"));
+
+        let http_request = http::Request::builder()
+            .uri("/_matrix/foo/query?SINCE=s1&Set_Presence=online&other=1")
+            .body(Vec::new())
+            .unwrap();
+        assert_eq!(query_endpoint::Request::unknown_query_keys(&http_request), ["other"]);
+        let request = query_endpoint::Request::try_from(http_request).unwrap();
+        assert_eq!(request.since.as_deref(), Some("s1"));
+        assert_eq!(request.set_presence.as_deref(), Some("online"));
+
+        // Outgoing requests use the canonical names.
+        let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+        assert_eq!(http_request.uri().query(), Some("since=s1&set_presence=online"));
+    }
+}
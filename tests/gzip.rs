@@ -0,0 +1,88 @@
+#![cfg(all(feature = "gzip", feature = "client"))]
+
+use std::{convert::TryFrom, io::Read};
+
+use flate2::read::GzDecoder;
+
+pub mod bulk_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "bulk_endpoint",
+            path: "/_matrix/foo/bulk",
+            rate_limited: false,
+            requires_authentication: false,
+            compress_request_body: true,
+        }
+
+        request {
+            pub events: Vec<String>,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn request_body_is_compressed() {
+    let request = bulk_endpoint::Request { events: vec!["a".into(), "b".into()] };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+
+    assert_eq!(http_request.headers()["Content-Encoding"], "gzip");
+
+    let mut body = String::new();
+    GzDecoder::new(http_request.body().as_slice()).read_to_string(&mut body).unwrap();
+    assert_eq!(body, r#"{"events":["a","b"]}"#);
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn incoming_request_body_is_decompressed() {
+    let request = bulk_endpoint::Request { events: vec!["a".into(), "b".into()] };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+
+    let request = bulk_endpoint::Request::try_from(http_request).unwrap();
+    assert_eq!(request.events, vec!["a".to_owned(), "b".to_owned()]);
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn incoming_request_body_size_is_limited() {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    // 10 MiB and one byte of spaces compress to about 10 KiB.
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&vec![b' '; 10 * 1024 * 1024 + 1]).unwrap();
+    let body = encoder.finish().unwrap();
+    assert!(body.len() < 20 * 1024);
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/foo/bulk")
+        .header(http::header::CONTENT_ENCODING, "gzip")
+        .body(body)
+        .unwrap();
+    let error = bulk_endpoint::Request::try_from(http_request).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "The decompressed request body exceeded the maximum size of 10485760 bytes."
+    );
+    assert_eq!(error.errcode(), Some("M_TOO_LARGE"));
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn borrowed_incoming_request_body_is_decompressed() {
+    let request = bulk_endpoint::Request { events: vec!["a".into()] };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    let (parts, body) = http_request.into_parts();
+    let http_request = http::Request::from_parts(parts, body.as_slice());
+
+    let request = bulk_endpoint::Request::try_from(http_request).unwrap();
+    assert_eq!(request.events, vec!["a".to_owned()]);
+}
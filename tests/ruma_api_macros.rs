@@ -1,3 +1,5 @@
+#![cfg(feature = "client")]
+
 pub mod some_endpoint {
     use ruma_api::ruma_api;
 
@@ -92,3 +94,2372 @@ pub mod query_map_endpoint {
         }
     }
 }
+
+pub mod nullable_body_field_endpoint {
+    use ruma_api_macros::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: PUT,
+            name: "nullable_body_field_endpoint",
+            path: "/_matrix/some/nullable/endpoint",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            pub displayname: Option<Option<String>>,
+        }
+
+        response {
+            pub avatar_url: Option<Option<String>>,
+        }
+    }
+}
+
+#[test]
+fn nullable_body_field_request_distinguishes_absent_and_null() {
+    use std::convert::TryFrom;
+
+    use nullable_body_field_endpoint::Request;
+
+    let body = |displayname| {
+        http::Request::<Vec<u8>>::try_from(Request { displayname }).unwrap().into_body()
+    };
+
+    assert_eq!(body(None), b"{}");
+    assert_eq!(body(Some(None)), br#"{"displayname":null}"#);
+    assert_eq!(body(Some(Some("alice".into()))), br#"{"displayname":"alice"}"#);
+}
+
+#[test]
+fn nullable_body_field_response_distinguishes_absent_and_null() {
+    use std::convert::TryFrom;
+
+    use nullable_body_field_endpoint::Response;
+
+    let avatar_url =
+        |body: &[u8]| Response::try_from(http::Response::new(body.to_vec())).unwrap().avatar_url;
+
+    assert_eq!(avatar_url(b"{}"), None);
+    assert_eq!(avatar_url(br#"{"avatar_url":null}"#), Some(None));
+    assert_eq!(avatar_url(br#"{"avatar_url":"mxc://a/b"}"#), Some(Some("mxc://a/b".into())));
+}
+
+pub mod json_stream_endpoint {
+    use ruma_api_macros::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "json_stream_endpoint",
+            path: "/_matrix/some/json/stream/endpoint",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {}
+
+        response {
+            #[ruma_api(header = CONTENT_TYPE)]
+            pub content_type: String,
+
+            #[ruma_api(json_stream)]
+            pub values: ruma_api::JsonStream<u32>,
+        }
+    }
+}
+
+#[test]
+fn json_stream_response_is_deserialized_lazily() {
+    use std::convert::TryFrom;
+
+    use json_stream_endpoint::Response;
+
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/x-ndjson")
+        .body(b"1\n2\n3\n".to_vec())
+        .unwrap();
+    let response = Response::try_from(http_response).unwrap();
+
+    assert_eq!(response.content_type, "application/x-ndjson");
+    assert_eq!(response.values.iter().collect::<Result<Vec<_>, _>>().unwrap(), vec![1, 2, 3]);
+}
+
+pub mod serde_attrs_endpoint {
+    use ruma_api_macros::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: PUT,
+            name: "serde_attrs_endpoint",
+            path: "/_matrix/some/serde/attrs/endpoint",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub reason: Option<String>,
+
+            #[serde(rename = "m.room_id")]
+            pub room_id: String,
+        }
+
+        response {
+            #[ruma_api(body)]
+            #[serde(with = "ruma_api::serde::base64::unpadded")]
+            pub key: Vec<u8>,
+        }
+    }
+}
+
+#[test]
+fn serde_attrs_are_applied_to_body_fields() {
+    use std::convert::TryFrom;
+
+    use serde_attrs_endpoint::{Request, Response};
+
+    let request = Request { reason: None, room_id: "!room:example.org".into() };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.body(), br#"{"m.room_id":"!room:example.org"}"#);
+
+    let response = Response::try_from(http::Response::new(br#""YQ""#.to_vec())).unwrap();
+    assert_eq!(response.key, b"a");
+}
+
+pub mod identifiers_endpoint {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{EventId, RoomAliasId, RoomId, UserId};
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "identifiers_endpoint",
+            path: "/_matrix/some/identifiers/endpoint/:room_alias/:user_id",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub room_alias: RoomAliasId,
+
+            #[ruma_api(path)]
+            pub user_id: UserId,
+
+            #[ruma_api(query)]
+            pub event_id: EventId,
+
+            #[ruma_api(query)]
+            pub room_id: Option<RoomId>,
+        }
+
+        response {
+            pub room_id: RoomId,
+        }
+    }
+}
+
+#[test]
+fn identifiers_in_path_and_query_are_escaped() {
+    use std::convert::TryFrom;
+
+    use identifiers_endpoint::Request;
+    use ruma_identifiers::{EventId, RoomAliasId, RoomId, UserId};
+
+    let request = Request {
+        room_alias: RoomAliasId::try_from("#room/with?chars:example.org").unwrap(),
+        user_id: UserId::try_from("@alice:example.org").unwrap(),
+        event_id: EventId::try_from("$event&id:example.org").unwrap(),
+        room_id: Some(RoomId::try_from("!room:example.org").unwrap()),
+    };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+
+    assert_eq!(
+        http_request.uri().path(),
+        "/_matrix/some/identifiers/endpoint/%23room%2Fwith%3Fchars:example.org/@alice:example.org"
+    );
+    assert_eq!(
+        http_request.uri().query(),
+        Some("event_id=%24event%26id%3Aexample.org&room_id=%21room%3Aexample.org")
+    );
+}
+
+#[test]
+fn identifiers_in_response_are_validated() {
+    use std::convert::TryFrom;
+
+    use identifiers_endpoint::Response;
+
+    let response =
+        Response::try_from(http::Response::new(br#"{"room_id":"!room:example.org"}"#.to_vec()))
+            .unwrap();
+    assert_eq!(response.room_id.to_string(), "!room:example.org");
+
+    assert!(Response::try_from(http::Response::new(br#"{"room_id":"room"}"#.to_vec())).is_err());
+}
+
+pub mod path_encoding_endpoint {
+    use ruma_api_macros::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "path_encoding_endpoint",
+            path: "/_matrix/some/path/encoding/endpoint/:default/:component/:verbatim",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub default: String,
+
+            #[ruma_api(path, encode = component)]
+            pub component: String,
+
+            #[ruma_api(path, encode = none)]
+            pub verbatim: String,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn path_encoding_can_be_overridden() {
+    use std::convert::TryFrom;
+
+    use path_encoding_endpoint::Request;
+
+    let request = Request {
+        default: "!room:example.org".into(),
+        component: "!room:example.org".into(),
+        verbatim: "media%2Fid".into(),
+    };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+
+    assert_eq!(
+        http_request.uri().path(),
+        "/_matrix/some/path/encoding/endpoint/!room:example.org/%21room%3Aexample.org/media%2Fid"
+    );
+}
+
+#[test]
+fn http_semantics_are_derived_from_method() {
+    use ruma_api::Endpoint;
+
+    fn safe_and_idempotent<E: Endpoint>() -> (bool, bool) {
+        (E::IS_SAFE, E::IS_IDEMPOTENT)
+    }
+
+    assert_eq!(safe_and_idempotent::<some_endpoint::Request>(), (false, false));
+    assert_eq!(safe_and_idempotent::<newtype_body_endpoint::Request>(), (false, true));
+    assert_eq!(safe_and_idempotent::<query_map_endpoint::Request>(), (true, true));
+}
+
+pub mod single_field_response_endpoint {
+    use ruma_api_macros::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "single_field_response_endpoint",
+            path: "/_matrix/some/single/field/response/endpoint",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {}
+
+        response {
+            pub user_id: String,
+        }
+    }
+}
+
+#[test]
+fn single_field_response_into_inner() {
+    use std::convert::TryFrom;
+
+    use newtype_body_endpoint::MyCustomType;
+
+    let http_response = http::Response::new(br#"{"user_id":"@alice:example.org"}"#.to_vec());
+    let response = single_field_response_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(String::from(response.clone()), "@alice:example.org");
+    assert_eq!(response.into_inner(), "@alice:example.org");
+
+    let http_response = http::Response::new(br#"{"foo":"bar"}"#.to_vec());
+    let response = newtype_body_endpoint::Response::try_from(http_response).unwrap();
+    let my_custom_type: MyCustomType = response.into();
+    assert_eq!(my_custom_type.foo, "bar");
+}
+
+#[test]
+fn path_segments() {
+    use ruma_api::{Endpoint, PathSegment};
+
+    assert_eq!(
+        some_endpoint::Request::PATH_SEGMENTS,
+        &[
+            PathSegment::Literal("_matrix"),
+            PathSegment::Literal("some"),
+            PathSegment::Literal("endpoint"),
+            PathSegment::Parameter("baz"),
+        ]
+    );
+
+    let path = "/_matrix/some/endpoint/value";
+    let segments = some_endpoint::Request::PATH_SEGMENTS;
+    assert!(path[1..]
+        .split('/')
+        .zip(segments)
+        .all(|(segment, expected)| expected.matches(segment)));
+    assert!(!segments[0].matches("_synapse"));
+}
+
+#[test]
+fn path_param_names() {
+    use ruma_api::Endpoint;
+
+    assert_eq!(some_endpoint::Request::PATH_PARAM_NAMES, &["baz"]);
+    assert_eq!(
+        path_encoding_endpoint::Request::PATH_PARAM_NAMES,
+        &["default", "component", "verbatim"]
+    );
+    assert!(newtype_body_endpoint::Request::PATH_PARAM_NAMES.is_empty());
+}
+
+#[test]
+fn error_response_retry_after() {
+    use std::{convert::TryFrom, time::Duration};
+
+    let body =
+        br#"{"errcode":"M_LIMIT_EXCEEDED","error":"Too many requests","retry_after_ms":1500}"#;
+    let mut http_response = http::Response::new(body.to_vec());
+    *http_response.status_mut() = http::StatusCode::TOO_MANY_REQUESTS;
+
+    let error = single_field_response_endpoint::Response::try_from(http_response).unwrap_err();
+    assert_eq!(error.status_code(), Some(http::StatusCode::TOO_MANY_REQUESTS));
+    assert_eq!(error.retry_after(), Some(Duration::from_millis(1500)));
+}
+
+#[test]
+fn deserialization_errors_point_to_the_failing_value() {
+    use std::convert::TryFrom;
+
+    let http_response = http::Response::new(br#"{"user_id":42}"#.to_vec());
+    let error = single_field_response_endpoint::Response::try_from(http_response).unwrap_err();
+    assert_eq!(error.json_pointer(), Some("/user_id"));
+    assert!(error.to_string().starts_with("A JSON error occurred at `/user_id`: invalid type"));
+
+    let http_response = http::Response::new(b"[]".to_vec());
+    let error = single_field_response_endpoint::Response::try_from(http_response).unwrap_err();
+    assert_eq!(error.json_pointer(), Some(""));
+}
+
+pub mod colliding_names_endpoint {
+    use ruma_api::ruma_api;
+
+    /// Items with the same names as the types generated for (de)serialization.
+    pub struct RequestBody;
+    pub struct RequestQuery;
+    pub struct ResponseBody;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "colliding_names_endpoint",
+            path: "/_matrix/foo/colliding",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(query)]
+            pub limit: u32,
+            pub name: String,
+        }
+
+        response {
+            pub name: String,
+        }
+    }
+}
+
+#[test]
+fn generated_types_dont_collide_with_user_items() {
+    use std::convert::TryFrom;
+
+    let request = colliding_names_endpoint::Request { limit: 10, name: "foo".into() };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.uri().query(), Some("limit=10"));
+    assert_eq!(http_request.body(), br#"{"name":"foo"}"#);
+
+    let http_response = http::Response::new(br#"{"name":"bar"}"#.to_vec());
+    let response = colliding_names_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.name, "bar");
+}
+
+pub mod tuple_struct_request_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "tuple_struct_request_endpoint",
+            path: "/_matrix/foo/events",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// The events to send.
+            #[ruma_api(body)]
+            pub Vec<String>,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn tuple_struct_request() {
+    use std::convert::TryFrom;
+
+    let request = tuple_struct_request_endpoint::Request(vec!["a".into(), "b".into()]);
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.body(), br#"["a","b"]"#);
+}
+
+pub mod enum_body_endpoint {
+    use ruma_api::ruma_api;
+    use serde::{Deserialize, Serialize};
+
+    /// How the user is identified.
+    #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+    #[serde(tag = "type")]
+    pub enum UserIdentifier {
+        #[serde(rename = "m.id.user")]
+        User { user: String },
+        #[serde(rename = "m.id.phone")]
+        Phone { country: String, phone: String },
+    }
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "enum_body_endpoint",
+            path: "/_matrix/foo/identify",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(body)]
+            pub UserIdentifier,
+        }
+
+        response {
+            #[ruma_api(body)]
+            pub identifier: UserIdentifier,
+        }
+    }
+}
+
+#[test]
+fn enum_bodies() {
+    use std::convert::TryFrom;
+
+    use enum_body_endpoint::{Request, Response, UserIdentifier};
+
+    let request = Request(UserIdentifier::User { user: "alice".into() });
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.body(), br#"{"type":"m.id.user","user":"alice"}"#);
+
+    let body = br#"{"type":"m.id.phone","country":"GB","phone":"123"}"#;
+    let response = Response::try_from(http::Response::new(body.to_vec())).unwrap();
+    assert_eq!(
+        response.identifier,
+        UserIdentifier::Phone { country: "GB".into(), phone: "123".into() }
+    );
+
+    let body = br#"{"type":"m.id.thirdparty","address":"alice@example.org"}"#;
+    let error = Response::try_from(http::Response::new(body.to_vec())).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("unknown variant `m.id.thirdparty`, expected `m.id.user` or `m.id.phone`"));
+}
+
+pub mod flatten_endpoint {
+    use ruma_api::ruma_api;
+    use serde::{Deserialize, Serialize};
+
+    /// Pagination parameters shared by several endpoints.
+    #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+    pub struct Pagination {
+        pub from: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub limit: Option<u32>,
+    }
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "flatten_endpoint",
+            path: "/_matrix/foo/search",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            pub search_term: String,
+            #[ruma_api(body, flatten)]
+            pub pagination: Pagination,
+        }
+
+        response {
+            pub results: Vec<String>,
+            #[ruma_api(body, flatten)]
+            pub next: Pagination,
+        }
+    }
+}
+
+#[test]
+fn flattened_body_fields() {
+    use std::convert::TryFrom;
+
+    use flatten_endpoint::{Pagination, Request, Response};
+
+    let request = Request {
+        search_term: "foo".into(),
+        pagination: Pagination { from: "abc".into(), limit: Some(10) },
+    };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.body(), br#"{"search_term":"foo","from":"abc","limit":10}"#);
+
+    let body = br#"{"results":["bar"],"from":"def"}"#;
+    let response = Response::try_from(http::Response::new(body.to_vec())).unwrap();
+    assert_eq!(response.results, vec!["bar".to_owned()]);
+    assert_eq!(response.next, Pagination { from: "def".into(), limit: None });
+}
+
+pub mod query_mixin_endpoint {
+    use ruma_api::ruma_api;
+    use serde::{Deserialize, Serialize};
+
+    /// Query parameters shared by several endpoints.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    pub struct PaginationParams {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub from: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub to: Option<String>,
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "ruma_api::serde::opt_number_or_string"
+        )]
+        pub limit: Option<u32>,
+    }
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "query_mixin_endpoint",
+            path: "/_matrix/foo/messages",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(query)]
+            pub dir: String,
+            #[ruma_api(query, flatten)]
+            pub pagination: PaginationParams,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn flattened_query_fields() {
+    use std::convert::TryFrom;
+
+    use query_mixin_endpoint::{PaginationParams, Request};
+
+    let request = Request {
+        dir: "b".into(),
+        pagination: PaginationParams {
+            from: Some("s1".into()),
+            limit: Some(5),
+            ..Default::default()
+        },
+    };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.uri().query(), Some("dir=b&from=s1&limit=5"));
+
+    #[cfg(feature = "server")]
+    {
+        let request = Request::try_from(http_request).unwrap();
+        assert_eq!(request.dir, "b");
+        assert_eq!(request.pagination.from.as_deref(), Some("s1"));
+        assert_eq!(request.pagination.to, None);
+        assert_eq!(request.pagination.limit, Some(5));
+    }
+}
+
+pub mod optional_query_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "optional_query_endpoint",
+            path: "/_matrix/foo/optional",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(query)]
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub limit: Option<u32>,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn empty_query_string_is_omitted() {
+    use std::convert::TryFrom;
+
+    use optional_query_endpoint::Request;
+
+    let http_request = http::Request::<Vec<u8>>::try_from(Request { limit: None }).unwrap();
+    assert_eq!(
+        http_request.uri().to_string(),
+        "http://invalid-host-please-change/_matrix/foo/optional"
+    );
+
+    let http_request = http::Request::<Vec<u8>>::try_from(Request { limit: Some(3) }).unwrap();
+    assert_eq!(http_request.uri().query(), Some("limit=3"));
+}
+
+#[test]
+fn name_and_path_consts() {
+    use ruma_api::Endpoint;
+
+    const LABEL: &str = some_endpoint::Request::NAME;
+
+    assert_eq!(LABEL, "some_endpoint");
+    assert_eq!(some_endpoint::Request::PATH, "/_matrix/some/endpoint/:baz");
+    assert_eq!(some_endpoint::Request::PATH, some_endpoint::Request::METADATA.path);
+}
+
+pub mod cfg_endpoint {
+    use ruma_api::ruma_api;
+
+    // Both invocations generate a `Request` and a `Response` type, which only compiles because
+    // the first one is configured out.
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "cfg_endpoint",
+            path: "/_matrix/foo/disabled",
+            rate_limited: false,
+            requires_authentication: false,
+            cfg: any(),
+        }
+
+        request {}
+
+        response {}
+    }
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "cfg_endpoint",
+            path: "/_matrix/foo/enabled",
+            rate_limited: false,
+            requires_authentication: false,
+            cfg: all(feature = "with-ruma-api-macros", not(any())),
+        }
+
+        request {}
+
+        response {}
+    }
+}
+
+#[test]
+fn endpoints_are_gated_behind_cfg() {
+    use ruma_api::Endpoint;
+
+    assert_eq!(cfg_endpoint::Request::PATH, "/_matrix/foo/enabled");
+}
+
+pub mod optional_header_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "optional_header_endpoint",
+            path: "/_matrix/foo/optional_header",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(header = IF_MATCH)]
+            pub if_match: Option<String>,
+        }
+
+        response {
+            #[ruma_api(header = CONTENT_TYPE)]
+            pub content_type: Option<String>,
+            pub hello: String,
+        }
+    }
+}
+
+#[test]
+fn optional_headers() {
+    use std::convert::TryFrom;
+
+    use optional_header_endpoint::{Request, Response};
+
+    let http_request = http::Request::<Vec<u8>>::try_from(Request { if_match: None }).unwrap();
+    assert!(http_request.headers().get(http::header::IF_MATCH).is_none());
+
+    let http_request =
+        http::Request::<Vec<u8>>::try_from(Request { if_match: Some("\"abc\"".into()) }).unwrap();
+    assert_eq!(http_request.headers()[http::header::IF_MATCH], "\"abc\"");
+
+    // A wrong or missing content type doesn't prevent the body from being parsed.
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "text/plain")
+        .body(br#"{ "hello": "world" }"#.to_vec())
+        .unwrap();
+    let response = Response::try_from(http_response).unwrap();
+    assert_eq!(response.content_type, Some("text/plain".to_owned()));
+    assert_eq!(response.hello, "world");
+
+    let http_response = http::Response::new(br#"{ "hello": "world" }"#.to_vec());
+    let response = Response::try_from(http_response).unwrap();
+    assert_eq!(response.content_type, None);
+    assert_eq!(response.hello, "world");
+}
+
+#[test]
+fn content_length() {
+    use std::convert::TryFrom;
+
+    let request = tuple_struct_request_endpoint::Request(vec!["a".into(), "b".into()]);
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.headers()[http::header::CONTENT_LENGTH], "9");
+
+    let request = optional_header_endpoint::Request { if_match: None };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert!(http_request.headers().get(http::header::CONTENT_LENGTH).is_none());
+
+    // The length of response bodies isn't checked against `Content-Length` by default.
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_LENGTH, "30")
+        .body(br#"{ "hello": "world" }"#.to_vec())
+        .unwrap();
+    let response = optional_header_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.hello, "world");
+}
+
+pub mod iter_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "iter_endpoint",
+            path: "/_matrix/foo/rooms",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {}
+
+        response {
+            #[ruma_api(iter)]
+            pub rooms: Vec<String>,
+            pub next_batch: Option<String>,
+        }
+    }
+}
+
+#[test]
+fn iterate_over_response() {
+    use std::convert::TryFrom;
+
+    let http_response = http::Response::new(br#"{ "rooms": ["a", "b"] }"#.to_vec());
+    let response = iter_endpoint::Response::try_from(http_response).unwrap();
+
+    assert_eq!(response.len(), 2);
+    assert_eq!(response.first().map(String::as_str), Some("a"));
+    assert_eq!((&response).into_iter().map(String::as_str).collect::<Vec<_>>(), vec!["a", "b"]);
+    assert_eq!(response.into_iter().collect::<Vec<_>>(), vec!["a".to_owned(), "b".to_owned()]);
+}
+
+pub mod request_id_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "request_id_endpoint",
+            path: "/_matrix/foo/traced",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(request_id)]
+            pub request_id: Option<String>,
+        }
+
+        response {
+            #[ruma_api(request_id)]
+            pub request_id: Option<String>,
+        }
+    }
+}
+
+#[test]
+fn request_ids() {
+    use std::convert::TryFrom;
+
+    use request_id_endpoint::{Request, Response};
+
+    let request = Request { request_id: Some("abc123".into()) };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.headers()[ruma_api::REQUEST_ID_HEADER], "abc123");
+
+    let generated_id = |request| {
+        let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+        http_request.headers()[ruma_api::REQUEST_ID_HEADER].to_str().unwrap().to_owned()
+    };
+    let first = generated_id(Request { request_id: None });
+    let second = generated_id(Request { request_id: None });
+    assert!(!first.is_empty());
+    assert_ne!(first, second);
+
+    let http_response =
+        http::Response::builder().header("X-Request-Id", "abc123").body(Vec::new()).unwrap();
+    let response = Response::try_from(http_response).unwrap();
+    assert_eq!(response.request_id, Some("abc123".to_owned()));
+}
+
+pub mod query_params_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "query_params_endpoint",
+            path: "/_matrix/foo/messages",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(query)]
+            pub limit: u32,
+            #[ruma_api(query)]
+            #[serde(rename = "dir")]
+            pub direction: String,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn query_param_name_consts() {
+    use std::convert::TryFrom;
+
+    use query_params_endpoint::{query_params, Request};
+
+    assert_eq!(query_params::LIMIT, "limit");
+    assert_eq!(query_params::DIRECTION, "dir");
+    assert_eq!(some_endpoint::query_params::BAR, "bar");
+
+    let request = Request { limit: 10, direction: "b".into() };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    let query = http_request.uri().query().unwrap();
+    assert!(query.contains(&format!("{}=10", query_params::LIMIT)));
+    assert!(query.contains(&format!("{}=b", query_params::DIRECTION)));
+}
+
+#[test]
+fn response_headers_are_kept_on_errors() {
+    use std::convert::TryFrom;
+
+    use request_id_endpoint::Response;
+
+    let http_response = http::Response::builder()
+        .header("X-Request-Id", "abc123")
+        .body(br#"{ "hello": "#.to_vec())
+        .unwrap();
+    let error = optional_header_endpoint::Response::try_from(http_response).unwrap_err();
+    assert_eq!(error.headers().unwrap()["x-request-id"], "abc123");
+    assert!(error.to_string().starts_with("A JSON error occurred"));
+
+    let http_response = http::Response::builder()
+        .status(http::StatusCode::TOO_MANY_REQUESTS)
+        .header(http::header::RETRY_AFTER, "3")
+        .body(Vec::new())
+        .unwrap();
+    let error = ruma_api::Error::from(Response::try_from(http_response).unwrap_err());
+    assert_eq!(error.status_code(), Some(http::StatusCode::TOO_MANY_REQUESTS));
+    assert_eq!(error.headers().unwrap()[http::header::RETRY_AFTER], "3");
+}
+
+pub mod empty_object_body_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: DELETE,
+            name: "empty_object_body_endpoint",
+            path: "/_matrix/foo/directory/:room_alias",
+            rate_limited: false,
+            requires_authentication: false,
+            empty_object_body: true,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub room_alias: String,
+        }
+
+        response {
+            pub reason: Option<String>,
+        }
+    }
+}
+
+#[test]
+fn empty_bodies() {
+    use std::convert::TryFrom;
+
+    let request = empty_object_body_endpoint::Request { room_alias: "alias".into() };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.body(), b"{}");
+    assert_eq!(http_request.headers()[http::header::CONTENT_LENGTH], "2");
+
+    let request = optional_header_endpoint::Request { if_match: None };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert!(http_request.body().is_empty());
+
+    for body in &[&b""[..], b"\n", b"{}"] {
+        let http_response = http::Response::new(body.to_vec());
+        let response = empty_object_body_endpoint::Response::try_from(http_response).unwrap();
+        assert_eq!(response.reason, None);
+    }
+}
+
+pub mod unstable_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "unstable_endpoint",
+            path: "/_matrix/client/r0/sync/:conn_id",
+            rate_limited: false,
+            requires_authentication: false,
+            unstable_feature: "org.matrix.msc3575",
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub conn_id: String,
+        }
+
+        response {}
+    }
+}
+
+pub mod history_paths_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "history_paths_endpoint",
+            path: "/_matrix/client/v3/rooms/:room_id/thing",
+            history_paths: [
+                "/_matrix/client/r0/rooms/:room_id/thing",
+                "/_matrix/client/unstable/rooms/:room_id/old_thing",
+            ],
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub room_id: String,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn history_paths() {
+    use std::convert::TryFrom;
+
+    use ruma_api::Endpoint;
+
+    let metadata = history_paths_endpoint::Request::METADATA;
+    assert_eq!(
+        metadata.history_paths,
+        [
+            "/_matrix/client/r0/rooms/:room_id/thing",
+            "/_matrix/client/unstable/rooms/:room_id/old_thing"
+        ]
+    );
+    assert!(metadata.matches_path("/_matrix/client/v3/rooms/!a:b/thing"));
+    assert!(metadata.matches_path("/_matrix/client/r0/rooms/!a:b/thing"));
+    assert!(!metadata.matches_path("/_matrix/client/r1/rooms/!a:b/thing"));
+
+    let request = history_paths_endpoint::Request { room_id: "!a:b".into() };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.uri().path(), "/_matrix/client/v3/rooms/!a:b/thing");
+
+    #[cfg(feature = "server")]
+    for path in &[
+        "/_matrix/client/v3/rooms/!a:b/thing",
+        "/_matrix/client/r0/rooms/!a:b/thing",
+        "/_matrix/client/unstable/rooms/!a:b/old_thing",
+    ] {
+        let http_request = http::Request::builder().uri(*path).body(Vec::new()).unwrap();
+        let request = history_paths_endpoint::Request::try_from(http_request).unwrap();
+        assert_eq!(request.room_id, "!a:b");
+    }
+}
+
+#[test]
+fn unstable_feature() {
+    use std::{collections::BTreeMap, convert::TryFrom};
+
+    use ruma_api::Endpoint;
+
+    let metadata = unstable_endpoint::Request::METADATA;
+    assert_eq!(metadata.path, "/_matrix/client/unstable/org.matrix.msc3575/sync/:conn_id");
+    assert_eq!(metadata.unstable_feature, Some("org.matrix.msc3575"));
+
+    let request = unstable_endpoint::Request { conn_id: "main".into() };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.uri().path(), "/_matrix/client/unstable/org.matrix.msc3575/sync/main");
+
+    let mut unstable_features = BTreeMap::new();
+    assert!(!metadata.is_supported_by(&unstable_features));
+    assert!(empty_object_body_endpoint::Request::METADATA.is_supported_by(&unstable_features));
+
+    unstable_features.insert("org.matrix.msc3575".to_owned(), true);
+    assert!(metadata.is_supported_by(&unstable_features));
+}
+
+pub mod examples_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "examples_endpoint",
+            path: "/_matrix/foo/examples",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            pub name: String,
+            #[serde(rename = "m.topic")]
+            pub topic: Option<String>,
+        }
+
+        response {
+            pub room_id: String,
+            #[serde(default)]
+            pub servers: Vec<String>,
+        }
+
+        examples {
+            request: r#"{ "name": "Room", "m.topic": "Chat" }"#,
+            response: r#"{ "room_id": "!room:example.org" }"#,
+        }
+    }
+}
+
+#[test]
+fn examples() {
+    use std::convert::TryFrom;
+
+    use ruma_api::Endpoint;
+
+    assert_eq!(
+        examples_endpoint::Request::EXAMPLE_REQUEST_BODY,
+        Some(r#"{ "name": "Room", "m.topic": "Chat" }"#)
+    );
+    assert_eq!(optional_header_endpoint::Request::EXAMPLE_RESPONSE_BODY, None);
+
+    let body = examples_endpoint::Request::EXAMPLE_RESPONSE_BODY.unwrap();
+    let http_response = http::Response::new(body.as_bytes().to_vec());
+    let response = examples_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.room_id, "!room:example.org");
+    assert!(response.servers.is_empty());
+}
+
+pub mod validated_endpoint {
+    use ruma_api::ruma_api;
+
+    fn not_empty(value: &str) -> Result<(), &'static str> {
+        if value.is_empty() {
+            Err("must not be empty")
+        } else {
+            Ok(())
+        }
+    }
+
+    fn ordered(response: &Response) -> Result<(), String> {
+        if response.start <= response.end {
+            Ok(())
+        } else {
+            Err(format!("start {} is after end {}", response.start, response.end))
+        }
+    }
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "validated_endpoint",
+            path: "/_matrix/foo/validated",
+            rate_limited: false,
+            requires_authentication: false,
+            validate_response: ordered,
+        }
+
+        request {}
+
+        response {
+            #[ruma_api(validate = not_empty)]
+            pub chunk: String,
+            pub start: u64,
+            pub end: u64,
+        }
+    }
+}
+
+#[test]
+fn response_validation() {
+    use std::convert::TryFrom;
+
+    let parse = |body: &str| {
+        let http_response = http::Response::builder()
+            .header(ruma_api::REQUEST_ID_HEADER, "req")
+            .body(body.as_bytes().to_vec())
+            .unwrap();
+        validated_endpoint::Response::try_from(http_response)
+    };
+
+    let response = parse(r#"{ "chunk": "a", "start": 1, "end": 2 }"#).unwrap();
+    assert_eq!(response.chunk, "a");
+
+    let error = parse(r#"{ "chunk": "", "start": 1, "end": 2 }"#).unwrap_err();
+    assert!(error.is_validation_error());
+    assert_eq!(error.to_string(), "The response field `chunk` is invalid: must not be empty");
+    assert_eq!(error.headers().unwrap()[ruma_api::REQUEST_ID_HEADER], "req");
+
+    let error = parse(r#"{ "chunk": "a", "start": 3, "end": 2 }"#).unwrap_err();
+    assert!(error.is_validation_error());
+    assert_eq!(error.to_string(), "The response is invalid: start 3 is after end 2");
+
+    assert!(!parse("{}").unwrap_err().is_validation_error());
+}
+
+#[test]
+fn metadata_methods() {
+    use ruma_api::Endpoint;
+
+    fn describe(metadata: &'static ruma_api::Metadata) -> String {
+        format!("{} {}", metadata.method, metadata.path)
+    }
+
+    let request = examples_endpoint::Request { name: "Room".into(), topic: None };
+    assert_eq!(request.metadata(), &examples_endpoint::Request::METADATA);
+    assert_eq!(describe(request.metadata()), "POST /_matrix/foo/examples");
+
+    let response = iter_endpoint::Response { rooms: Vec::new(), next_batch: None };
+    assert_eq!(response.metadata().name, "iter_endpoint");
+}
+
+pub mod patch_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: PATCH,
+            name: "patch_endpoint",
+            path: "/_matrix/foo/profile/:user_id",
+            rate_limited: false,
+            requires_authentication: false,
+            patch: true,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub user_id: String,
+            pub displayname: String,
+            pub avatar_url: String,
+            #[serde(rename = "m.status")]
+            pub status: Option<String>,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn patch_bodies() {
+    use std::convert::TryFrom;
+
+    use ruma_api::Maybe;
+
+    let request = patch_endpoint::Request {
+        user_id: "@alice:example.org".into(),
+        displayname: Maybe::Value("Alice".into()),
+        avatar_url: Maybe::Null,
+        status: Maybe::Absent,
+    };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.body(), br#"{"displayname":"Alice","avatar_url":null}"#);
+
+    let request = patch_endpoint::Request {
+        user_id: "@alice:example.org".into(),
+        displayname: Maybe::Absent,
+        avatar_url: Maybe::Absent,
+        status: Maybe::Value(Some("busy".into())),
+    };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.body(), br#"{"m.status":"busy"}"#);
+}
+
+pub mod ordered_headers_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "ordered_headers_endpoint",
+            path: "/_matrix/foo/ordered_headers",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(header = IF_NONE_MATCH)]
+            pub if_none_match: String,
+            #[ruma_api(header = ACCEPT)]
+            pub accept: String,
+            #[ruma_api(header = IF_MATCH)]
+            pub if_match: Option<String>,
+            pub hello: String,
+        }
+
+        response {}
+    }
+}
+
+#[test]
+fn request_header_order() {
+    use std::convert::TryFrom;
+
+    let request = ordered_headers_endpoint::Request {
+        if_none_match: "*".into(),
+        accept: "application/json".into(),
+        if_match: Some("etag".into()),
+        hello: "world".into(),
+    };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+
+    let names: Vec<_> = http_request.headers().keys().map(|name| name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec!["content-type", "if-none-match", "accept", "if-match", "content-length"]
+    );
+    assert!(http_request
+        .headers()
+        .iter()
+        .all(|(name, _)| { http_request.headers().get_all(name).iter().count() == 1 }));
+}
+
+pub mod manual_body_endpoint {
+    use ruma_api::ruma_api;
+
+    fn to_body(lines: &[String]) -> Result<Vec<u8>, String> {
+        if lines.iter().any(|line| line.contains('\n')) {
+            return Err("lines must not contain line breaks".into());
+        }
+
+        Ok(lines.join("\n").into_bytes())
+    }
+
+    fn from_body(body: &[u8]) -> Result<Vec<String>, std::str::Utf8Error> {
+        Ok(std::str::from_utf8(body)?.lines().map(ToOwned::to_owned).collect())
+    }
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: PUT,
+            name: "manual_body_endpoint",
+            path: "/_matrix/foo/lines/:id",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub id: String,
+            #[ruma_api(manual_body = to_body)]
+            pub lines: Vec<String>,
+        }
+
+        response {
+            #[ruma_api(manual_body = from_body)]
+            pub lines: Vec<String>,
+        }
+    }
+}
+
+#[test]
+fn manual_bodies() {
+    use std::convert::TryFrom;
+
+    let request =
+        manual_body_endpoint::Request { id: "a".into(), lines: vec!["one".into(), "two".into()] };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.uri().path(), "/_matrix/foo/lines/a");
+    assert_eq!(http_request.body(), b"one\ntwo");
+
+    let request = manual_body_endpoint::Request { id: "a".into(), lines: vec!["a\nb".into()] };
+    let error = http::Request::<Vec<u8>>::try_from(request).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "An error occurred in a custom body conversion: lines must not contain line breaks"
+    );
+
+    let http_response = http::Response::new(b"three\nfour\n".to_vec());
+    let response = manual_body_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.lines, vec!["three".to_owned(), "four".to_owned()]);
+
+    let http_response = http::Response::new(vec![0xff]);
+    assert!(manual_body_endpoint::Response::try_from(http_response).is_err());
+}
+
+pub mod versioned_endpoint {
+    pub mod v1 {
+        use ruma_api::ruma_api;
+
+        ruma_api! {
+            metadata {
+                description: "Does something.",
+                method: POST,
+                name: "versioned_endpoint",
+                path: "/_matrix/foo/v1/ban/:room_id",
+                rate_limited: false,
+                requires_authentication: false,
+            }
+
+            request {
+                #[ruma_api(path)]
+                pub room_id: String,
+                pub user: String,
+            }
+
+            response {
+                pub banned: bool,
+            }
+        }
+    }
+
+    pub mod v2 {
+        use ruma_api::ruma_api;
+
+        ruma_api! {
+            metadata {
+                description: "Does something.",
+                method: POST,
+                name: "versioned_endpoint",
+                path: "/_matrix/foo/v2/ban/:room_id",
+                rate_limited: false,
+                requires_authentication: false,
+            }
+
+            request {
+                #[ruma_api(path)]
+                pub room_id: String,
+                pub user_id: String,
+                pub reason: Option<String>,
+            }
+
+            response {
+                pub banned: bool,
+                pub event_id: String,
+            }
+
+            compat super::v1 {
+                request {
+                    user_id: old.user,
+                    reason: None,
+                }
+                response {
+                    banned,
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn compat_conversions() {
+    use versioned_endpoint::{v1, v2};
+
+    let old = v1::Request { room_id: "!room".into(), user: "@alice".into() };
+    let request = v2::Request::from(old);
+    assert_eq!(request.room_id, "!room");
+    assert_eq!(request.user_id, "@alice");
+    assert_eq!(request.reason, None);
+
+    let response = v2::Response { banned: true, event_id: "$event".into() };
+    assert!(v1::Response::from(response).banned);
+}
+
+pub mod multipart_endpoint {
+    use ruma_api::{ruma_api, Multipart};
+    use serde::Deserialize;
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct ContentMetadata {}
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "multipart_endpoint",
+            path: "/_matrix/federation/v1/media/download/:media_id",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub media_id: String,
+        }
+
+        response {
+            #[ruma_api(multipart)]
+            pub media: Multipart<ContentMetadata>,
+        }
+    }
+}
+
+#[test]
+fn multipart_responses() {
+    use std::convert::TryFrom;
+
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "multipart/mixed; boundary=6d4b")
+        .body(
+            b"--6d4b\r\nContent-Type: application/json\r\n\r\n{}\r\n\
+              --6d4b\r\nContent-Type: text/plain\r\n\r\nhello\r\n--6d4b--\r\n"
+                .to_vec(),
+        )
+        .unwrap();
+    let media = multipart_endpoint::Response::try_from(http_response).unwrap().into_inner();
+    assert_eq!(media.content_type(), Some("text/plain"));
+    assert_eq!(media.content, b"hello");
+
+    let http_response = http::Response::new(b"{}".to_vec());
+    assert!(multipart_endpoint::Response::try_from(http_response).is_err());
+}
+
+pub mod attributes_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        #[deny(missing_docs)]
+        #[doc(hidden)]
+
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "attributes_endpoint",
+            path: "/_matrix/foo/attributes",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// A documented field, required by `deny(missing_docs)`.
+            #[ruma_api(query)]
+            pub q: String,
+        }
+
+        response {
+            /// A documented field, required by `deny(missing_docs)`.
+            pub hello: String,
+        }
+    }
+}
+
+#[test]
+fn forwarded_attributes() {
+    use std::convert::TryFrom;
+
+    let request = attributes_endpoint::Request { q: "x".into() };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.uri().query(), Some("q=x"));
+}
+
+ruma_api::endpoint_kind! {
+    /// Some of the endpoints above.
+    pub enum TestEndpoint {
+        /// `manual_body_endpoint`
+        ManualBody => manual_body_endpoint::Request,
+        /// `multipart_endpoint`
+        Multipart => multipart_endpoint::Request,
+    }
+}
+
+#[test]
+fn endpoint_kinds() {
+    assert_eq!(TestEndpoint::ALL, &[TestEndpoint::ManualBody, TestEndpoint::Multipart]);
+    assert_eq!(TestEndpoint::Multipart.name(), "multipart_endpoint");
+    assert_eq!(TestEndpoint::ManualBody.metadata().method, http::Method::PUT);
+    assert_eq!(TestEndpoint::from_name("manual_body_endpoint"), Some(TestEndpoint::ManualBody));
+    assert_eq!(TestEndpoint::from_name("unknown"), None);
+}
+
+#[cfg(feature = "server")]
+ruma_api::any_incoming_request! {
+    /// Requests to some of the endpoints above.
+    pub enum AnyTestRequest {
+        /// `some_endpoint`
+        Some(some_endpoint::Request),
+        /// `cookie_endpoint`
+        Cookie(cookie_endpoint::Request),
+        /// `computed_header_endpoint`
+        ComputedHeader(computed_header_endpoint::Request),
+    }
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn routing() {
+    use ruma_api::RouteError;
+
+    let route = |method: &str, uri: &str| {
+        let http_request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(br#"{"foo":"foo"}"#.to_vec())
+            .unwrap();
+        AnyTestRequest::route(http_request)
+    };
+
+    match route("POST", "/_matrix/some/endpoint/baz?bar=bar").unwrap() {
+        AnyTestRequest::Some(request) => assert_eq!(request.baz, "baz"),
+        request => panic!("routed to the wrong endpoint: {:?}", request),
+    }
+
+    let request = route("GET", "/_matrix/some/content").unwrap();
+    assert!(matches!(request, AnyTestRequest::ComputedHeader(_)));
+    assert_eq!(request.metadata().name, "computed_header_endpoint");
+
+    assert!(matches!(route("POST", "/_matrix/some/cookie").unwrap(), AnyTestRequest::Cookie(_)));
+
+    match route("PUT", "/_matrix/some/content") {
+        Err(RouteError::MethodNotAllowed(methods)) => assert_eq!(methods, [http::Method::GET]),
+        result => panic!("unexpected routing result: {:?}", result),
+    }
+    assert!(matches!(route("GET", "/_matrix/other"), Err(RouteError::NotFound)));
+    assert!(matches!(route("POST", "/_matrix/some/endpoint/baz"), Err(RouteError::Request(_))));
+}
+
+pub mod raw_body_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "raw_body_endpoint",
+            path: "/_matrix/media/r0/upload",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(raw_body)]
+            pub file: Vec<u8>,
+            #[ruma_api(header = CONTENT_TYPE)]
+            pub content_type: String,
+            #[ruma_api(query)]
+            pub filename: Option<String>,
+        }
+
+        response {
+            pub content_uri: String,
+        }
+    }
+}
+
+#[test]
+fn raw_bodies() {
+    use std::convert::TryFrom;
+
+    let request = raw_body_endpoint::Request {
+        file: vec![0x89, b'P', b'N', b'G'],
+        content_type: "image/png".into(),
+        filename: Some("cat.png".into()),
+    };
+    let http_request = http::Request::<Vec<u8>>::try_from(request).unwrap();
+    assert_eq!(http_request.body(), &[0x89, b'P', b'N', b'G']);
+    assert_eq!(http_request.headers()[http::header::CONTENT_TYPE], "image/png");
+    assert_eq!(http_request.uri().query(), Some("filename=cat.png"));
+}
+
+pub mod raw_response_body_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "raw_response_body_endpoint",
+            path: "/_matrix/media/r0/download/:server_name/:media_id",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            #[ruma_api(path)]
+            pub server_name: String,
+            #[ruma_api(path)]
+            pub media_id: String,
+        }
+
+        response {
+            #[ruma_api(raw_body)]
+            pub file: Vec<u8>,
+            #[ruma_api(header = CONTENT_TYPE)]
+            pub content_type: Option<String>,
+        }
+    }
+}
+
+#[test]
+fn raw_response_bodies() {
+    use std::convert::TryFrom;
+
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "image/png")
+        .body(vec![0x89, b'P', b'N', b'G'])
+        .unwrap();
+    let response = raw_response_body_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.file, vec![0x89, b'P', b'N', b'G']);
+    assert_eq!(response.content_type.as_deref(), Some("image/png"));
+
+    let http_response = http::Response::new(Vec::new());
+    let response = raw_response_body_endpoint::Response::try_from(http_response).unwrap();
+    assert!(response.file.is_empty());
+}
+
+pub mod error_ty_endpoint {
+    use ruma_api::ruma_api;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    pub struct BanError {
+        pub errcode: String,
+        pub banned_until: u64,
+    }
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: GET,
+            name: "error_ty_endpoint",
+            path: "/_matrix/foo/error_ty",
+            rate_limited: false,
+            requires_authentication: false,
+            error_ty: BanError,
+        }
+
+        request {}
+
+        response {}
+    }
+}
+
+#[test]
+fn typed_error_bodies() {
+    use std::convert::TryFrom;
+
+    use error_ty_endpoint::BanError;
+
+    let http_response = http::Response::builder()
+        .status(403)
+        .body(br#"{ "errcode": "M_FORBIDDEN", "banned_until": 42 }"#.to_vec())
+        .unwrap();
+    let error = error_ty_endpoint::Response::try_from(http_response).unwrap_err();
+    assert_eq!(error.status_code(), Some(http::StatusCode::FORBIDDEN));
+    assert_eq!(
+        error.error_body::<BanError>(),
+        Some(&BanError { errcode: "M_FORBIDDEN".into(), banned_until: 42 })
+    );
+    assert_eq!(error.error_body::<String>(), None);
+
+    let http_response = http::Response::builder().status(502).body(b"<html>".to_vec()).unwrap();
+    let error = error_ty_endpoint::Response::try_from(http_response).unwrap_err();
+    assert_eq!(error.status_code(), Some(http::StatusCode::BAD_GATEWAY));
+    assert_eq!(error.error_body::<BanError>(), None);
+}
+
+#[test]
+fn matrix_error_bodies() {
+    use std::convert::TryFrom;
+
+    let body = br#"{"errcode":"M_FORBIDDEN","error":"You are not invited","soft_logout":true}"#;
+    let http_response = http::Response::builder().status(403).body(body.to_vec()).unwrap();
+    let error = single_field_response_endpoint::Response::try_from(http_response).unwrap_err();
+    let matrix_error = error.matrix_error().unwrap();
+    assert_eq!(matrix_error.errcode, "M_FORBIDDEN");
+    assert_eq!(matrix_error.error, "You are not invited");
+    assert_eq!(matrix_error.extra["soft_logout"], serde_json::Value::Bool(true));
+    assert_eq!(
+        error.to_string(),
+        "A HTTP 403 Forbidden error occurred: M_FORBIDDEN: You are not invited"
+    );
+
+    let http_response = http::Response::builder().status(502).body(b"<html>".to_vec()).unwrap();
+    let error = single_field_response_endpoint::Response::try_from(http_response).unwrap_err();
+    assert_eq!(error.status_code(), Some(http::StatusCode::BAD_GATEWAY));
+    assert!(error.matrix_error().is_none());
+    assert_eq!(error.to_string(), "A HTTP 502 Bad Gateway error occurred.");
+}
+
+pub mod uiaa_endpoint {
+    use ruma_api_macros::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Does something.",
+            method: POST,
+            name: "uiaa_endpoint",
+            path: "/_matrix/some/uiaa/endpoint",
+            rate_limited: false,
+            requires_authentication: true,
+            uiaa: true,
+        }
+
+        request {}
+
+        response {}
+    }
+}
+
+#[test]
+fn uiaa_responses() {
+    use std::convert::TryFrom;
+
+    let body = br#"{"flows":[{"stages":["m.login.password"]}],"session":"abc"}"#;
+    let http_response = http::Response::builder().status(401).body(body.to_vec()).unwrap();
+    let error = uiaa_endpoint::Response::try_from(http_response).unwrap_err();
+    let info = error.uiaa_info().unwrap();
+    assert_eq!(info.flows[0].stages, vec!["m.login.password".to_owned()]);
+    assert_eq!(info.session.as_deref(), Some("abc"));
+    assert_eq!(error.status_code(), Some(http::StatusCode::UNAUTHORIZED));
+    assert_eq!(error.to_string(), "The server requires user-interactive authentication.");
+
+    // A 401 without flows, e.g. for an invalid access token, is an ordinary error.
+    let body = br#"{"errcode":"M_UNKNOWN_TOKEN","error":"Unknown token"}"#;
+    let http_response = http::Response::builder().status(401).body(body.to_vec()).unwrap();
+    let error = uiaa_endpoint::Response::try_from(http_response).unwrap_err();
+    assert!(error.uiaa_info().is_none());
+    assert_eq!(error.matrix_error().unwrap().errcode, "M_UNKNOWN_TOKEN");
+
+    // Endpoints without `uiaa: true` don't parse the flows.
+    let body = br#"{"flows":[{"stages":["m.login.password"]}]}"#;
+    let http_response = http::Response::builder().status(401).body(body.to_vec()).unwrap();
+    let error = single_field_response_endpoint::Response::try_from(http_response).unwrap_err();
+    assert!(error.uiaa_info().is_none());
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn incoming_requests() {
+    use ruma_api::{IncomingRequest, OutgoingRequest};
+
+    fn round_trip<R: IncomingRequest + OutgoingRequest>(request: R) -> R {
+        R::try_from(request.try_into().unwrap()).unwrap()
+    }
+
+    let request = round_trip(some_endpoint::Request {
+        foo: "foo".into(),
+        content_type: "application/json".into(),
+        bar: "a b&c".into(),
+        baz: "!room:example.org/x".into(),
+    });
+    assert_eq!(request.foo, "foo");
+    assert_eq!(request.content_type, "application/json");
+    assert_eq!(request.bar, "a b&c");
+    assert_eq!(request.baz, "!room:example.org/x");
+
+    let request = round_trip(path_encoding_endpoint::Request {
+        default: "#room:example.org".into(),
+        component: "!room:example.org".into(),
+        verbatim: "media%2Fid".into(),
+    });
+    assert_eq!(request.default, "#room:example.org");
+    assert_eq!(request.component, "!room:example.org");
+    assert_eq!(request.verbatim, "media%2Fid");
+
+    let request = round_trip(query_map_endpoint::Request {
+        fields: vec![("a".into(), "1".into()), ("b".into(), "2".into())],
+    });
+    assert_eq!(request.fields, vec![("a".into(), "1".into()), ("b".into(), "2".into())]);
+
+    let request = round_trip(raw_body_endpoint::Request {
+        file: vec![0x89, b'P', b'N', b'G'],
+        content_type: "image/png".into(),
+        filename: None,
+    });
+    assert_eq!(request.file, &[0x89, b'P', b'N', b'G']);
+    assert_eq!(request.filename, None);
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn incoming_requests_from_parts() {
+    use http::header::{HeaderMap, HeaderValue};
+    use ruma_api::IncomingRequest;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(http::header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    let request = some_endpoint::Request::try_from_parts(
+        http::Method::POST,
+        "/_matrix/some/endpoint/a%20b?bar=bar",
+        headers,
+        br#"{"foo":"foo"}"#.to_vec(),
+    )
+    .unwrap();
+    assert_eq!(request.foo, "foo");
+    assert_eq!(request.bar, "bar");
+    assert_eq!(request.baz, "a b");
+
+    let request = query_map_endpoint::Request::try_from_parts(
+        http::Method::GET,
+        "https://example.org/_matrix/some/query/map/endpoint?a=1",
+        HeaderMap::new(),
+        Vec::new(),
+    );
+    assert_eq!(request.unwrap().fields, vec![("a".to_owned(), "1".to_owned())]);
+
+    let error = some_endpoint::Request::try_from_parts(
+        http::Method::POST,
+        "not a uri",
+        HeaderMap::new(),
+        Vec::new(),
+    )
+    .unwrap_err();
+    assert_eq!(error.to_string(), "The request URI `not a uri` is invalid.");
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn incoming_request_heads() {
+    use ruma_api::IncomingRequest;
+
+    let (parts, body) = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?bar=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(br#"{"foo":"foo"}"#.to_vec())
+        .unwrap()
+        .into_parts();
+    let partial = some_endpoint::Request::parse_head(parts).unwrap();
+    assert_eq!(partial.method(), http::Method::POST);
+    assert_eq!(partial.uri().path(), "/_matrix/some/endpoint/baz");
+    assert_eq!(partial.headers()[http::header::CONTENT_TYPE], "application/json");
+
+    let request = partial.finish(body).unwrap();
+    assert_eq!(request.foo, "foo");
+    assert_eq!(request.bar, "bar");
+    assert_eq!(request.baz, "baz");
+
+    // The head is rejected before the body is read.
+    let (parts, _) = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(())
+        .unwrap()
+        .into_parts();
+    assert!(some_endpoint::Request::parse_head(parts).is_err());
+
+    let (parts, _) = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?bar=bar")
+        .body(())
+        .unwrap()
+        .into_parts();
+    let error = some_endpoint::Request::parse_head(parts).unwrap_err();
+    assert_eq!(error.to_string(), "The request is missing the required header `content-type`.");
+
+    // A malformed body is only noticed when the request is finished.
+    let (parts, _) = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?bar=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(())
+        .unwrap()
+        .into_parts();
+    let partial = some_endpoint::Request::parse_head(parts).unwrap();
+    assert!(partial.finish(b"{".to_vec()).is_err());
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn borrowed_incoming_request_heads() {
+    use ruma_api::IncomingRequest;
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?bar=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(br#"{"foo":"foo"}"#.to_vec())
+        .unwrap();
+    let partial = some_endpoint::Request::try_from_http_request_ref(&http_request).unwrap();
+    assert_eq!(partial.uri(), http_request.uri());
+    assert_eq!(partial.headers(), http_request.headers());
+
+    let request = partial.finish(http_request.into_body()).unwrap();
+    assert_eq!(request.foo, "foo");
+
+    // No body is needed to check the head.
+    let http_request =
+        http::Request::builder().method("GET").uri("/_matrix/some/other/path").body(()).unwrap();
+    assert!(some_endpoint::Request::try_from_http_request_ref(&http_request).is_err());
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn incoming_request_errors() {
+    use std::convert::TryFrom;
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/other/endpoint/baz?bar=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(br#"{"foo":"foo"}"#.to_vec())
+        .unwrap();
+    let error = some_endpoint::Request::try_from(http_request).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "The request path `/_matrix/other/endpoint/baz` doesn't match the path of the endpoint."
+    );
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz/extra?bar=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(br#"{"foo":"foo"}"#.to_vec())
+        .unwrap();
+    let error = some_endpoint::Request::try_from(http_request).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "The request path `/_matrix/some/endpoint/baz/extra` doesn't match the path of the \
+         endpoint."
+    );
+
+    // Query keys are case-sensitive unless `Config::case_insensitive_query_keys` is set.
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?BAR=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(br#"{"foo":"foo"}"#.to_vec())
+        .unwrap();
+    assert!(some_endpoint::Request::try_from(http_request).is_err());
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix//some/endpoint/baz?bar=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(br#"{"foo":"foo"}"#.to_vec())
+        .unwrap();
+    assert!(some_endpoint::Request::try_from(http_request).is_err());
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?bar=bar")
+        .body(br#"{"foo":"foo"}"#.to_vec())
+        .unwrap();
+    let error = some_endpoint::Request::try_from(http_request).unwrap_err();
+    assert_eq!(error.to_string(), "The request is missing the required header `content-type`.");
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?bar=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(br#"{"foo":1}"#.to_vec())
+        .unwrap();
+    let error = some_endpoint::Request::try_from(http_request).unwrap_err();
+    assert_eq!(error.json_pointer(), Some("/foo"));
+    assert_eq!(error.errcode(), Some("M_BAD_JSON"));
+    assert_eq!(error.newtype_body_endpoint(), None);
+
+    // The Content-Type isn't checked by default.
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?bar=bar")
+        .header(http::header::CONTENT_TYPE, "text/plain")
+        .body(b"{".to_vec())
+        .unwrap();
+    let error = some_endpoint::Request::try_from(http_request).unwrap_err();
+    assert_eq!(error.errcode(), Some("M_NOT_JSON"));
+
+    let http_request = http::Request::builder()
+        .method("PUT")
+        .uri("/_matrix/some/newtype/body/endpoint")
+        .body(br#"[1,"2"]"#.to_vec())
+        .unwrap();
+    let error = newtype_body_endpoint::Request::try_from(http_request).unwrap_err();
+    assert_eq!(error.newtype_body_endpoint(), Some("newtype_body_endpoint"));
+    assert_eq!(error.json_pointer(), Some("/1"));
+    assert!(error
+        .to_string()
+        .starts_with("The body of the `newtype_body_endpoint` request couldn't be deserialized: "));
+}
+
+pub mod cookie_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Logs in with a cookie.",
+            method: POST,
+            name: "cookie_endpoint",
+            path: "/_matrix/some/cookie",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {}
+
+        response {
+            #[ruma_api(header = SET_COOKIE)]
+            pub cookies: Vec<String>,
+            #[ruma_api(header = CACHE_CONTROL)]
+            pub cache_control: Option<String>,
+        }
+    }
+}
+
+pub mod computed_header_endpoint {
+    use ruma_api::ruma_api;
+
+    ruma_api! {
+        metadata {
+            description: "Gets some content along with its entity tag.",
+            method: GET,
+            name: "computed_header_endpoint",
+            path: "/_matrix/some/content",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {}
+
+        response {
+            #[ruma_api(header = ETAG, from = "etag")]
+            pub etag: Option<String>,
+            pub content: String,
+        }
+    }
+
+    #[cfg(feature = "server")]
+    fn etag(response: &Response) -> Option<String> {
+        Some(format!("\"{}\"", response.content.len()))
+    }
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn computed_response_headers() {
+    use std::convert::TryFrom;
+
+    let response = computed_header_endpoint::Response { etag: None, content: "content".into() };
+    let http_response = http::Response::<Vec<u8>>::try_from(response).unwrap();
+    assert_eq!(http_response.headers()[http::header::ETAG], "\"7\"");
+
+    let response = computed_header_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.etag.as_deref(), Some("\"7\""));
+    assert_eq!(response.content, "content");
+}
+
+#[test]
+fn multi_valued_response_headers() {
+    use std::convert::TryFrom;
+
+    let http_response = http::Response::builder()
+        .header(http::header::SET_COOKIE, "a=1")
+        .header(http::header::SET_COOKIE, "b=2")
+        .body(b"{}".to_vec())
+        .unwrap();
+    let response = cookie_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.cookies, ["a=1", "b=2"]);
+    assert_eq!(response.cache_control, None);
+
+    let http_response = http::Response::builder().body(b"{}".to_vec()).unwrap();
+    let response = cookie_endpoint::Response::try_from(http_response).unwrap();
+    assert!(response.cookies.is_empty());
+
+    #[cfg(feature = "server")]
+    {
+        let response = cookie_endpoint::Response {
+            cookies: vec!["a=1".into(), "b=2".into(), "c=3".into()],
+            cache_control: Some("no-store".into()),
+        };
+        let http_response = http::Response::<Vec<u8>>::try_from(response).unwrap();
+        let cookies: Vec<_> =
+            http_response.headers().get_all(http::header::SET_COOKIE).iter().collect();
+        assert_eq!(cookies, ["a=1", "b=2", "c=3"]);
+        assert_eq!(http_response.headers()[http::header::CACHE_CONTROL], "no-store");
+        assert_eq!(http_response.headers()[http::header::CONTENT_TYPE], "application/json");
+
+        let response = cookie_endpoint::Response::try_from(http_response).unwrap();
+        assert_eq!(response.cookies, ["a=1", "b=2", "c=3"]);
+        assert_eq!(response.cache_control.as_deref(), Some("no-store"));
+    }
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn outgoing_responses() {
+    use std::convert::TryFrom;
+
+    let response =
+        some_endpoint::Response { content_type: "text/plain".into(), value: "value".into() };
+    let http_response = http::Response::<Vec<u8>>::try_from(response).unwrap();
+    assert_eq!(http_response.headers()[http::header::CONTENT_TYPE], "text/plain");
+    assert_eq!(http_response.body(), br#"{"value":"value"}"#);
+
+    let response = some_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.value, "value");
+
+    let http_response = http::Response::<Vec<u8>>::try_from(patch_endpoint::Response).unwrap();
+    assert_eq!(http_response.headers()[http::header::CONTENT_TYPE], "application/json");
+    assert_eq!(http_response.body(), b"{}");
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn outgoing_responses_with_extra_headers() {
+    use http::header::{HeaderMap, HeaderValue};
+
+    let mut headers = HeaderMap::new();
+    headers.insert(http::header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    headers.append(http::header::VARY, HeaderValue::from_static("Origin"));
+    headers.append(http::header::VARY, HeaderValue::from_static("Accept"));
+    headers.insert(http::header::CONTENT_TYPE, HeaderValue::from_static("text/html"));
+    headers.insert(http::header::SET_COOKIE, HeaderValue::from_static("c=3"));
+
+    let response = cookie_endpoint::Response {
+        cookies: vec!["a=1".into(), "b=2".into()],
+        cache_control: None,
+    };
+    let http_response = response.try_into_http_response_with(headers).unwrap();
+    let headers = http_response.headers();
+    assert_eq!(headers[http::header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    let vary: Vec<_> = headers.get_all(http::header::VARY).iter().collect();
+    assert_eq!(vary, ["Origin", "Accept"]);
+    // Headers set by the response itself are kept.
+    assert_eq!(headers[http::header::CONTENT_TYPE], "application/json");
+    let cookies: Vec<_> = headers.get_all(http::header::SET_COOKIE).iter().collect();
+    assert_eq!(cookies, ["a=1", "b=2"]);
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn forwarded_requests_and_responses() {
+    use ruma_api::proxy::{forward_request, forward_response};
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/a%20b?bar=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .header(http::header::AUTHORIZATION, "Bearer token")
+        .body(br#"{"foo":"foo"}"#.to_vec())
+        .unwrap();
+    let upstream_request = forward_request::<some_endpoint::Request>(http_request).unwrap();
+    assert_eq!(upstream_request.method(), http::Method::POST);
+    assert_eq!(upstream_request.uri().path(), "/_matrix/some/endpoint/a%20b");
+    assert_eq!(upstream_request.uri().query(), Some("bar=bar"));
+    assert_eq!(upstream_request.headers()[http::header::CONTENT_TYPE], "application/json");
+    assert!(!upstream_request.headers().contains_key(http::header::AUTHORIZATION));
+    assert_eq!(upstream_request.body(), br#"{"foo":"foo"}"#);
+
+    let http_request =
+        http::Request::builder().method("POST").uri("/_matrix/some/endpoint/baz").body(Vec::new());
+    assert!(forward_request::<some_endpoint::Request>(http_request.unwrap()).is_err());
+
+    let upstream_response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(br#"{"value":"value","extra":true}"#.to_vec())
+        .unwrap();
+    let http_response = forward_response::<some_endpoint::Request>(upstream_response).unwrap();
+    assert_eq!(http_response.headers()[http::header::CONTENT_TYPE], "application/json");
+    assert_eq!(http_response.body(), br#"{"value":"value"}"#);
+
+    let upstream_response = http::Response::builder()
+        .status(http::StatusCode::NOT_FOUND)
+        .body(br#"{"errcode":"M_NOT_FOUND","error":"Not found"}"#.to_vec())
+        .unwrap();
+    let http_response = forward_response::<some_endpoint::Request>(upstream_response).unwrap();
+    assert_eq!(http_response.status(), http::StatusCode::NOT_FOUND);
+    assert_eq!(http_response.body(), br#"{"errcode":"M_NOT_FOUND","error":"Not found"}"#);
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn borrowed_bodies() {
+    use std::convert::TryFrom;
+
+    let body = br#"{"foo":"foo"}"#;
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?bar=bar")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(&body[..])
+        .unwrap();
+    let request = some_endpoint::Request::try_from(http_request).unwrap();
+    assert_eq!(request.foo, "foo");
+    assert_eq!(request.baz, "baz");
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/media/r0/upload")
+        .header(http::header::CONTENT_TYPE, "image/png")
+        .body(&[0x89, b'P', b'N', b'G'][..])
+        .unwrap();
+    let request = raw_body_endpoint::Request::try_from(http_request).unwrap();
+    assert_eq!(request.file, &[0x89, b'P', b'N', b'G']);
+    assert_eq!(request.content_type, "image/png");
+
+    let body = br#"{"value":"value"}"#;
+    let http_response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(&body[..])
+        .unwrap();
+    let response = some_endpoint::Response::try_from(http_response).unwrap();
+    assert_eq!(response.value, "value");
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn unknown_query_keys() {
+    use ruma_api::IncomingRequest;
+
+    assert_eq!(some_endpoint::Request::QUERY_PARAMS, Some(&["bar"][..]));
+    assert_eq!(query_map_endpoint::Request::QUERY_PARAMS, None);
+
+    let http_request = http::Request::builder()
+        .method("POST")
+        .uri("/_matrix/some/endpoint/baz?bar=bar&extra=1&x%20y=2&bar=again&BAR=3")
+        .body(())
+        .unwrap();
+    assert_eq!(some_endpoint::Request::unknown_query_keys(&http_request), ["extra", "x y", "BAR"]);
+
+    let http_request =
+        http::Request::builder().method("POST").uri("/_matrix/some/endpoint/baz").body(()).unwrap();
+    assert!(some_endpoint::Request::unknown_query_keys(&http_request).is_empty());
+
+    let http_request = http::Request::builder()
+        .method("GET")
+        .uri("/_matrix/some/query/map/endpoint?anything=1")
+        .body(())
+        .unwrap();
+    assert!(query_map_endpoint::Request::unknown_query_keys(&http_request).is_empty());
+}
+
+#[test]
+fn requests_for_a_base_url() {
+    use ruma_api::OutgoingRequest;
+
+    let request = || some_endpoint::Request {
+        foo: "foo".into(),
+        content_type: "application/json".into(),
+        bar: "bar".into(),
+        baz: "baz".into(),
+    };
+
+    let http_request =
+        request().try_into_http_request_for("https://user@matrix.example.org:8448/").unwrap();
+    assert_eq!(
+        http_request.uri(),
+        "https://user@matrix.example.org:8448/_matrix/some/endpoint/baz?bar=bar"
+    );
+    assert_eq!(http_request.headers()[http::header::HOST], "matrix.example.org:8448");
+
+    let http_request = request().try_into_http_request_for("http://localhost/prefix").unwrap();
+    assert_eq!(http_request.uri(), "http://localhost/prefix/_matrix/some/endpoint/baz?bar=bar");
+    assert_eq!(http_request.headers()[http::header::HOST], "localhost");
+
+    let error = request().try_into_http_request_for("not a url").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "The request URI `not a url/_matrix/some/endpoint/baz?bar=bar` is invalid."
+    );
+}